@@ -14,7 +14,14 @@ cargo clippy --quiet --workspace --all-targets --all-features --  -D warnings -W
 cargo test --quiet --workspace --all-targets --all-features
 cargo fmt --all -- --check
 
-cargo doc --quiet -p puffin -p puffin_egui -p puffin_http -p puffin_viewer --lib --no-deps --all-features
+cargo doc --quiet -p puffin -p puffin_egui -p puffin_http -p puffin_rayon -p puffin_viewer --lib --no-deps --all-features
 
 (cd puffin && cargo check --quiet --no-default-features --features "zstd")
 (cd puffin && cargo check --quiet --no-default-features --features "serialization")
+# The `disable` feature compiles the profiler macros away, so test puffin without it too:
+(cd puffin && cargo test --quiet)
+(cd puffin && cargo test --quiet --features "serialization zstd quanta macros alloc-tracking")
+(cd puffin && cargo test --quiet --features "macros" --test profile_attribute)
+(cd puffin && cargo test --quiet --features "disable" --test disable)
+(cd puffin && cargo test --quiet --features "disable macros" --test disable)
+(cd puffin && cargo test --quiet --features "alloc-tracking" --test alloc_tracking)
@@ -31,6 +31,13 @@ pub fn criterion_benchmark(c: &mut Criterion) {
             puffin::profile_scope!("my longish scope name", "my_mesh.obj");
         })
     });
+    c.bench_function("profile_scope_data_interned", |b| {
+        puffin::GlobalProfiler::lock().new_frame();
+        puffin::profile_scope!("keep one scope open so we don't profile sending scopes");
+        b.iter(|| {
+            puffin::profile_scope!("my longish scope name", puffin::Interned("my_mesh.obj"));
+        })
+    });
     c.bench_function("flush_frames", |b| {
         puffin::GlobalProfiler::lock().new_frame();
         let _fv = puffin::GlobalFrameView::default();
@@ -41,6 +48,25 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         })
     });
 
+    let stream = example_stream();
+    let compact = stream.to_compact().unwrap();
+    println!(
+        "Stream of {} scopes: {} bytes, compacted: {} bytes ({:.0}% smaller)",
+        puffin::Reader::count_scope_and_depth(&stream).unwrap().0,
+        stream.len(),
+        compact.len(),
+        100.0 * (1.0 - compact.len() as f64 / stream.len() as f64)
+    );
+    c.bench_function("stream_to_compact", |b| {
+        b.iter(|| stream.to_compact().unwrap());
+    });
+    c.bench_function("count_scopes", |b| {
+        b.iter(|| puffin::Reader::count_scope_and_depth(&stream).unwrap());
+    });
+    c.bench_function("count_scopes_compact", |b| {
+        b.iter(|| puffin::Reader::count_scope_and_depth(&compact).unwrap());
+    });
+
     puffin::set_scopes_on(false);
     c.bench_function("profile_function_off", |b| {
         puffin::GlobalProfiler::lock().new_frame();
@@ -72,5 +98,31 @@ pub fn criterion_benchmark(c: &mut Criterion) {
     });
 }
 
+fn scope_id(id: u32) -> puffin::ScopeId {
+    puffin::ScopeId(std::num::NonZeroU32::new(id).unwrap())
+}
+
+/// A frame with a few hundred scopes at different depths, some with data.
+fn example_stream() -> puffin::Stream {
+    let mut stream = puffin::Stream::default();
+    let mut ns = 1_700_000_000_000_000_000;
+    let (frame, _) = stream.begin_scope(|| ns, scope_id(1), "");
+    for system in 0..10 {
+        ns += 1_000;
+        let (outer, _) = stream.begin_scope(|| ns, scope_id(2 + system), "");
+        for entity in 0..30 {
+            let data = if entity % 3 == 0 { "my_mesh.obj" } else { "" };
+            ns += 50;
+            let (inner, _) = stream.begin_scope(|| ns, scope_id(20 + entity), data);
+            ns += 200;
+            stream.end_scope(inner, ns);
+        }
+        ns += 100;
+        stream.end_scope(outer, ns);
+    }
+    stream.end_scope(frame, ns + 10);
+    stream
+}
+
 criterion_group!(benches, criterion_benchmark);
 criterion_main!(benches);
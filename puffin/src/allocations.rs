@@ -0,0 +1,179 @@
+/// The memory allocated and freed during a scope, including its child scopes.
+///
+/// Only recorded when `puffin::TrackingAllocator` is the global allocator
+/// (with the `alloc-tracking` feature), and zero otherwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Allocations {
+    /// Number of allocations, including reallocations.
+    pub alloc_count: u64,
+
+    /// Number of bytes allocated.
+    pub alloc_bytes: u64,
+
+    /// Number of deallocations, including reallocations.
+    pub dealloc_count: u64,
+
+    /// Number of bytes freed.
+    pub dealloc_bytes: u64,
+}
+
+impl Allocations {
+    /// Nothing allocated or freed.
+    pub const ZERO: Self = Self {
+        alloc_count: 0,
+        alloc_bytes: 0,
+        dealloc_count: 0,
+        dealloc_bytes: 0,
+    };
+
+    /// Was nothing allocated or freed?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::ZERO
+    }
+
+    /// The allocations in `self` that are not in `other`, e.g. those of a scope minus those of its children.
+    #[inline]
+    pub fn saturating_sub(self, other: Self) -> Self {
+        Self {
+            alloc_count: self.alloc_count.saturating_sub(other.alloc_count),
+            alloc_bytes: self.alloc_bytes.saturating_sub(other.alloc_bytes),
+            dealloc_count: self.dealloc_count.saturating_sub(other.dealloc_count),
+            dealloc_bytes: self.dealloc_bytes.saturating_sub(other.dealloc_bytes),
+        }
+    }
+}
+
+impl std::ops::Add for Allocations {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, other: Self) -> Self {
+        Self {
+            alloc_count: self.alloc_count + other.alloc_count,
+            alloc_bytes: self.alloc_bytes + other.alloc_bytes,
+            dealloc_count: self.dealloc_count + other.dealloc_count,
+            dealloc_bytes: self.dealloc_bytes + other.dealloc_bytes,
+        }
+    }
+}
+
+impl std::ops::AddAssign for Allocations {
+    #[inline]
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+#[cfg(feature = "alloc-tracking")]
+thread_local! {
+    /// Everything allocated on this thread so far, minus what the profiler itself allocated.
+    static THREAD_ALLOCATIONS: std::cell::Cell<Allocations> =
+        const { std::cell::Cell::new(Allocations::ZERO) };
+}
+
+/// Everything `TrackingAllocator` counted on this thread so far.
+///
+/// Allocations of the profiler itself are left out, by putting back what this returned
+/// with [`set_thread_allocations`] once the profiler is done.
+#[cfg(feature = "alloc-tracking")]
+#[inline]
+pub(crate) fn thread_allocations() -> Allocations {
+    THREAD_ALLOCATIONS
+        .try_with(std::cell::Cell::get)
+        .unwrap_or_default()
+}
+
+#[cfg(feature = "alloc-tracking")]
+#[inline]
+pub(crate) fn set_thread_allocations(allocations: Allocations) {
+    let _ = THREAD_ALLOCATIONS.try_with(|cell| cell.set(allocations));
+}
+
+#[cfg(feature = "alloc-tracking")]
+#[inline]
+fn count(f: impl FnOnce(&mut Allocations)) {
+    // Threads that are shutting down have no thread locals left to count in:
+    let _ = THREAD_ALLOCATIONS.try_with(|cell| {
+        let mut allocations = cell.get();
+        f(&mut allocations);
+        cell.set(allocations);
+    });
+}
+
+/// A [`std::alloc::GlobalAlloc`] that counts the allocations of each thread,
+/// so that they are recorded in the [`crate::Scope::allocations`] of the scope open at the time.
+///
+/// Wraps another allocator, e.g. [`std::alloc::System`]:
+///
+/// ```
+/// #[global_allocator]
+/// static ALLOCATOR: puffin::TrackingAllocator = puffin::TrackingAllocator::new(std::alloc::System);
+/// ```
+///
+/// Counting costs a thread local lookup for each allocation, even when profiling is off.
+#[cfg(feature = "alloc-tracking")]
+#[derive(Default)]
+pub struct TrackingAllocator<A = std::alloc::System> {
+    inner: A,
+}
+
+#[cfg(feature = "alloc-tracking")]
+impl<A> TrackingAllocator<A> {
+    /// Count the allocations made with `inner`.
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+#[cfg(feature = "alloc-tracking")]
+#[allow(unsafe_code)]
+// SAFETY: All calls are forwarded to the wrapped allocator as is.
+unsafe impl<A: std::alloc::GlobalAlloc> std::alloc::GlobalAlloc for TrackingAllocator<A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        let ptr = unsafe { self.inner.alloc(layout) };
+        if !ptr.is_null() {
+            count(|allocations| {
+                allocations.alloc_count += 1;
+                allocations.alloc_bytes += layout.size() as u64;
+            });
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: std::alloc::Layout) -> *mut u8 {
+        let ptr = unsafe { self.inner.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            count(|allocations| {
+                allocations.alloc_count += 1;
+                allocations.alloc_bytes += layout.size() as u64;
+            });
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        unsafe { self.inner.dealloc(ptr, layout) };
+        count(|allocations| {
+            allocations.dealloc_count += 1;
+            allocations.dealloc_bytes += layout.size() as u64;
+        });
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: std::alloc::Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { self.inner.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            count(|allocations| {
+                allocations.alloc_count += 1;
+                allocations.alloc_bytes += new_size as u64;
+                allocations.dealloc_count += 1;
+                allocations.dealloc_bytes += layout.size() as u64;
+            });
+        }
+        new_ptr
+    }
+}
@@ -0,0 +1,328 @@
+use std::sync::{
+    atomic::{AtomicI64, Ordering},
+    Arc, OnceLock,
+};
+
+use crate::NanoSecond;
+
+/// A source of time for the whole profiler, set with [`set_clock`].
+pub trait Clock: Send + Sync {
+    /// Nanoseconds since some event, preferably the unix epoch.
+    ///
+    /// Should never go backwards.
+    fn now_ns(&self) -> NanoSecond;
+
+    /// Recorded in each frame as [`crate::FrameMeta::clock`].
+    fn kind(&self) -> ClockKind {
+        ClockKind::Custom
+    }
+}
+
+/// Which [`Clock`] timed a frame.
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ClockKind {
+    /// Recorded before puffin kept track of its clock.
+    #[default]
+    Unknown,
+
+    /// [`InstantClock`], the default.
+    Instant,
+
+    /// `TscClock`, with the `quanta` feature.
+    Tsc,
+
+    /// `MonotonicRawClock`, on Linux and Android.
+    MonotonicRaw,
+
+    /// [`MockClock`].
+    Mock,
+
+    /// A [`Clock`] implemented outside of puffin.
+    Custom,
+}
+
+impl std::fmt::Display for ClockKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Unknown => "unknown",
+            Self::Instant => "Instant",
+            Self::Tsc => "TSC",
+            Self::MonotonicRaw => "CLOCK_MONOTONIC_RAW",
+            Self::Mock => "mock",
+            Self::Custom => "custom",
+        })
+    }
+}
+
+static CLOCK: OnceLock<Box<dyn Clock>> = OnceLock::new();
+
+/// Returned by [`set_clock`] when the profiler already has a clock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClockAlreadySet;
+
+impl std::fmt::Display for ClockAlreadySet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("the puffin clock was already set, or already used")
+    }
+}
+
+impl std::error::Error for ClockAlreadySet {}
+
+/// Time the whole profiler with this clock, instead of the default [`InstantClock`].
+///
+/// Mixing clocks would mix up the time lines, so this can only be done once,
+/// and only before anything is profiled. Otherwise [`ClockAlreadySet`] is returned.
+///
+/// [`crate::ThreadProfiler::initialize`] can still override the clock of a single thread.
+pub fn set_clock(clock: impl Clock + 'static) -> Result<(), ClockAlreadySet> {
+    CLOCK.set(Box::new(clock)).map_err(|_| ClockAlreadySet)
+}
+
+/// The kind of clock the profiler uses, see [`set_clock`].
+pub fn clock_kind() -> ClockKind {
+    // Don't settle on the default clock just because someone asked:
+    CLOCK.get().map_or(ClockKind::Instant, |clock| clock.kind())
+}
+
+#[inline]
+pub(crate) fn clock() -> &'static dyn Clock {
+    CLOCK.get_or_init(|| Box::new(InstantClock::new())).as_ref()
+}
+
+#[cfg(any(not(target_arch = "wasm32"), feature = "web"))]
+fn nanos_since_epoch() -> NanoSecond {
+    #[cfg(target_arch = "wasm32")]
+    {
+        (js_sys::Date::new_0().get_time() * 1e6) as _
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        if let Ok(duration_since_epoch) = std::time::UNIX_EPOCH.elapsed() {
+            duration_since_epoch.as_nanos() as NanoSecond
+        } else {
+            0 // system time is set before 1970. this should be quite rare.
+        }
+    }
+}
+
+#[cfg(all(feature = "quanta", not(target_arch = "wasm32")))]
+use quanta::Instant;
+#[cfg(all(not(feature = "quanta"), not(target_arch = "wasm32")))]
+use std::time::Instant;
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+use web_time::Instant;
+
+/// The default [`Clock`]: the time since the unix epoch, measured with `std::time::Instant`.
+///
+/// Uses `quanta::Instant` with the `quanta` feature, and `web_time::Instant` with the `web` feature.
+/// Wasm without the `web` feature has no clock, so it needs [`set_clock`].
+pub struct InstantClock {
+    #[cfg(any(not(target_arch = "wasm32"), feature = "web"))]
+    start: (NanoSecond, Instant),
+}
+
+impl Default for InstantClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InstantClock {
+    /// Starts counting from the current time since the unix epoch.
+    pub fn new() -> Self {
+        Self {
+            #[cfg(any(not(target_arch = "wasm32"), feature = "web"))]
+            start: (nanos_since_epoch(), Instant::now()),
+        }
+    }
+}
+
+impl Clock for InstantClock {
+    #[inline]
+    #[cfg(any(not(target_arch = "wasm32"), feature = "web"))]
+    fn now_ns(&self) -> NanoSecond {
+        self.start.0 + self.start.1.elapsed().as_nanos() as NanoSecond
+    }
+
+    #[cfg(all(target_arch = "wasm32", not(feature = "web")))]
+    fn now_ns(&self) -> NanoSecond {
+        panic!("Wasm without the `web` feature requires passing a custom source of time via `puffin::set_clock`");
+    }
+
+    fn kind(&self) -> ClockKind {
+        ClockKind::Instant
+    }
+}
+
+/// A [`Clock`] reading the time stamp counter of the CPU, calibrated against the OS clock.
+///
+/// This is the cheapest clock to read, but only trustworthy on CPUs with an invariant TSC.
+/// Without one, it falls back to the OS monotonic clock. Built on `quanta::Clock`.
+#[cfg(all(feature = "quanta", not(target_arch = "wasm32")))]
+pub struct TscClock {
+    clock: quanta::Clock,
+    start_raw: u64,
+    start_ns: NanoSecond,
+}
+
+#[cfg(all(feature = "quanta", not(target_arch = "wasm32")))]
+impl Default for TscClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(feature = "quanta", not(target_arch = "wasm32")))]
+impl TscClock {
+    /// Calibrates the time stamp counter the first time it is called, which can take up to 200 ms.
+    pub fn new() -> Self {
+        let clock = quanta::Clock::new();
+        let start_raw = clock.raw();
+        Self {
+            clock,
+            start_raw,
+            start_ns: nanos_since_epoch(),
+        }
+    }
+}
+
+#[cfg(all(feature = "quanta", not(target_arch = "wasm32")))]
+impl Clock for TscClock {
+    #[inline]
+    fn now_ns(&self) -> NanoSecond {
+        let elapsed_ns = self.clock.delta_as_nanos(self.start_raw, self.clock.raw());
+        self.start_ns + elapsed_ns as NanoSecond
+    }
+
+    fn kind(&self) -> ClockKind {
+        ClockKind::Tsc
+    }
+}
+
+/// A [`Clock`] reading `CLOCK_MONOTONIC_RAW`, which unlike `std::time::Instant`
+/// is not slewed by NTP, so durations are never stretched or squeezed.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub struct MonotonicRawClock {
+    offset_ns: NanoSecond,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Default for MonotonicRawClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl MonotonicRawClock {
+    /// Starts counting from the current time since the unix epoch.
+    pub fn new() -> Self {
+        Self {
+            offset_ns: nanos_since_epoch() - Self::raw_ns(),
+        }
+    }
+
+    #[inline]
+    fn raw_ns() -> NanoSecond {
+        let time = rustix::time::clock_gettime(rustix::time::ClockId::MonotonicRaw);
+        time.tv_sec as NanoSecond * 1_000_000_000 + time.tv_nsec as NanoSecond
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Clock for MonotonicRawClock {
+    #[inline]
+    fn now_ns(&self) -> NanoSecond {
+        self.offset_ns + Self::raw_ns()
+    }
+
+    fn kind(&self) -> ClockKind {
+        ClockKind::MonotonicRaw
+    }
+}
+
+/// A deterministic [`Clock`] for tests, which only moves when told to.
+///
+/// Clones share the same time, so keep one to control the clock given to [`set_clock`].
+///
+/// ```
+/// let clock = puffin::MockClock::new(1_000);
+/// clock.set_step(10);
+/// # use puffin::Clock as _;
+/// assert_eq!(clock.now_ns(), 1_000);
+/// assert_eq!(clock.now_ns(), 1_010);
+/// clock.advance(100);
+/// assert_eq!(clock.now_ns(), 1_120);
+/// ```
+#[derive(Clone, Default)]
+pub struct MockClock(Arc<MockClockState>);
+
+#[derive(Default)]
+struct MockClockState {
+    now_ns: AtomicI64,
+    step_ns: AtomicI64,
+}
+
+impl MockClock {
+    /// A clock standing still at `start_ns`.
+    pub fn new(start_ns: NanoSecond) -> Self {
+        let clock = Self::default();
+        clock.set(start_ns);
+        clock
+    }
+
+    /// Move the clock to this time.
+    pub fn set(&self, now_ns: NanoSecond) {
+        self.0.now_ns.store(now_ns, Ordering::Relaxed);
+    }
+
+    /// Move the clock forward.
+    pub fn advance(&self, ns: NanoSecond) {
+        self.0.now_ns.fetch_add(ns, Ordering::Relaxed);
+    }
+
+    /// Move the clock forward by this much after each reading, so that every scope takes time.
+    pub fn set_step(&self, step_ns: NanoSecond) {
+        self.0.step_ns.store(step_ns, Ordering::Relaxed);
+    }
+}
+
+impl Clock for MockClock {
+    fn now_ns(&self) -> NanoSecond {
+        let step_ns = self.0.step_ns.load(Ordering::Relaxed);
+        self.0.now_ns.fetch_add(step_ns, Ordering::Relaxed)
+    }
+
+    fn kind(&self) -> ClockKind {
+        ClockKind::Mock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_counts_from_epoch(clock: &impl Clock) {
+        let before = InstantClock::new().now_ns();
+        let a = clock.now_ns();
+        let b = clock.now_ns();
+        let after = InstantClock::new().now_ns();
+        assert!(a <= b);
+        // Allow for some drift between the clocks:
+        assert!(before - 1_000_000_000 < a && b < after + 1_000_000_000);
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn monotonic_raw_clock() {
+        assert_counts_from_epoch(&MonotonicRawClock::new());
+    }
+
+    #[test]
+    #[cfg(all(feature = "quanta", not(target_arch = "wasm32")))]
+    fn tsc_clock() {
+        assert_counts_from_epoch(&TscClock::new());
+    }
+}
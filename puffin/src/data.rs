@@ -21,16 +21,62 @@
 //! ```
 //!
 //! Integers are encoded in little endian.
-//! Strings are encoded as a single u8 length + that many bytes of UTF8.
-//! At the moment strings may be at most 127 bytes long.
+//! Strings are encoded as a varint (unsigned LEB128) length + that many bytes of UTF8.
+//! Older streams used a single u8 length and truncated strings to 127 bytes,
+//! which is the same thing as a one-byte varint, so those can still be read.
+//!
+//! The layout above is what is written while profiling, since the sizes and end times
+//! can then be filled in when a scope closes.
+//! Finished frames are re-encoded with [`Stream::to_compact`] into a more compact layout:
+//!
+//! ```ignore
+//!    '{'          byte       Sentinel
+//!    scope id     varint     Unique monolithic identifier for a scope
+//!    start_ns     zigzag     Delta from the start of the parent (first child) or end of the previous sibling
+//!    data         str        Same as above
+//!    duration_ns  varint     How long the scope lasted
+//!    scope_size   varint     Number of bytes of child scopes, which follow directly
+//! ```
+//!
+//! Varints are unsigned LEB128, and zigzag is a zigzag-encoded signed varint.
+//! Top-level scopes store their start time as an absolute delta from zero,
+//! so compact streams can be concatenated, and read from the start without any context.
+//! Child scopes must be read with [`Reader::with_parent`].
+//!
+//! Scope data can also be interned with [`crate::Interned`], in which case it is stored once
+//! in the string table of the [`Stream`], and referenced by index.
+//! Such scopes start with `'['` (with a u32 index) or `'<'` (with a varint index) instead of `'('` or `'{'`.
+//!
+//! A scope that had not ended yet when its data was reported (see [`Scope::in_progress`])
+//! ends with `'~'` instead of `')'`, or in the compact layout is preceded by a `'~'`.
+//!
+//! The [`Scope::allocations`] of a scope, if any, follow directly after it in both layouts:
+//!
+//! ```ignore
+//!    '@'           byte       Sentinel
+//!    alloc_count   varint     Number of allocations
+//!    alloc_bytes   varint     Number of bytes allocated
+//!    dealloc_count varint     Number of deallocations
+//!    dealloc_bytes varint     Number of bytes freed
+//! ```
+//!
+//! All of these can be read by [`Reader`].
 
 use super::*;
 use anyhow::Context;
 use byteorder::{LittleEndian as LE, ReadBytesExt, WriteBytesExt};
-use std::mem::size_of;
+use std::{collections::HashMap, mem::size_of};
 
 const SCOPE_BEGIN: u8 = b'(';
 const SCOPE_END: u8 = b')';
+const COMPACT_SCOPE_BEGIN: u8 = b'{';
+const INTERNED_SCOPE_BEGIN: u8 = b'[';
+const INTERNED_COMPACT_SCOPE_BEGIN: u8 = b'<';
+const SCOPE_IN_PROGRESS: u8 = b'~';
+const SCOPE_ALLOCATIONS: u8 = b'@';
+
+/// Max number of bytes of a LEB128-encoded [`u64`].
+const MAX_VARINT_LENGTH: usize = 10;
 
 /// Used when parsing a Stream.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -68,46 +114,95 @@ pub struct Scope<'s> {
     pub child_end_position: u64,
     /// Stream offset for next sibling (if any).
     pub next_sibling_position: u64,
+    /// The scope had not ended yet at [`ScopeRecord::stop_ns`], but continues after it.
+    ///
+    /// This happens when a thread reports the data of its open scopes early,
+    /// e.g. to limit its memory use (see [`crate::ThreadMemoryLimit`]).
+    pub in_progress: bool,
+    /// The memory allocated and freed during the scope, including its children.
+    pub allocations: Allocations,
 }
 
 /// Stream of profiling events from one thread.
 #[derive(Clone, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
-pub struct Stream(Vec<u8>);
+pub struct Stream {
+    bytes: Vec<u8>,
+
+    /// Scope data interned with [`crate::Interned`], referenced by index from the stream.
+    strings: Vec<String>,
+
+    /// Where each string is in [`Self::strings`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    string_indices: HashMap<String, u32>,
+}
 
 impl Stream {
     /// Returns if stream is empty.
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.bytes.is_empty()
     }
 
     /// Returns the length in bytes of this steam.
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.bytes.len()
     }
 
     /// Returns the bytes of this steam
     pub fn bytes(&self) -> &[u8] {
-        &self.0
+        &self.bytes
+    }
+
+    /// Returns the string table of this stream, containing scope data interned with [`crate::Interned`].
+    pub fn strings(&self) -> &[String] {
+        &self.strings
+    }
+
+    /// Returns the length in bytes of this stream, plus the length of its string table.
+    pub fn num_bytes_with_strings(&self) -> usize {
+        self.bytes.len() + self.strings.iter().map(String::len).sum::<usize>()
     }
 
     /// Clears the steam of all bytes.
     pub fn clear(&mut self) {
-        self.0.clear();
+        self.bytes.clear();
+        self.strings.clear();
+        self.string_indices.clear();
     }
 
     /// Extends the stream with the given bytes.
     fn extend(&mut self, bytes: &[u8]) {
-        self.0.extend(bytes);
+        self.bytes.extend(bytes);
+    }
+
+    /// Returns the index of `s` in the string table, adding it if needed.
+    fn intern(&mut self, s: &str) -> u32 {
+        if let Some(&index) = self.string_indices.get(s) {
+            return index;
+        }
+        let index = self.strings.len() as u32;
+        self.strings.push(s.to_owned());
+        self.string_indices.insert(s.to_owned(), index);
+        index
     }
 }
 
 impl From<Vec<u8>> for Stream {
     fn from(v: Vec<u8>) -> Self {
-        Self(v)
+        Self {
+            bytes: v,
+            ..Default::default()
+        }
     }
 }
 
+/// The data of a scope, as written in the stream.
+#[derive(Clone, Copy)]
+enum DataRef<'a> {
+    Inline(&'a str),
+    Interned(u32),
+}
+
 impl Stream {
     /// Marks the beginning of the scope.
     /// Returns position where to write scope size once the scope is closed
@@ -118,22 +213,53 @@ impl Stream {
         scope_id: ScopeId,
         data: &str,
     ) -> (usize, NanoSecond) {
-        self.0.push(SCOPE_BEGIN);
+        self.begin_scope_with_data(now_ns, scope_id, DataRef::Inline(data))
+    }
+
+    /// Like [`Self::begin_scope`], but `data` is only stored once in the string table of the stream,
+    /// no matter how many scopes use it.
+    #[inline]
+    pub fn begin_scope_interned<F: Fn() -> i64>(
+        &mut self,
+        now_ns: F,
+        scope_id: ScopeId,
+        data: &str,
+    ) -> (usize, NanoSecond) {
+        let data_index = self.intern(data);
+        self.begin_scope_with_data(now_ns, scope_id, DataRef::Interned(data_index))
+    }
+
+    #[inline]
+    fn begin_scope_with_data<F: Fn() -> i64>(
+        &mut self,
+        now_ns: F,
+        scope_id: ScopeId,
+        data: DataRef<'_>,
+    ) -> (usize, NanoSecond) {
+        self.bytes.push(match data {
+            DataRef::Inline(_) => SCOPE_BEGIN,
+            DataRef::Interned(_) => INTERNED_SCOPE_BEGIN,
+        });
 
         self.write_scope_id(scope_id);
-        let time_stamp_offset = self.0.len();
-        self.0
+        let time_stamp_offset = self.bytes.len();
+        self.bytes
             .write_i64::<LE>(NanoSecond::default())
             .expect("can't fail");
 
-        self.write_str(data);
+        match data {
+            DataRef::Inline(data) => self.write_str(data),
+            DataRef::Interned(data_index) => {
+                self.bytes.write_u32::<LE>(data_index).expect("can't fail")
+            }
+        }
         // Put place-holder value for total scope size.
-        let offset = self.0.len();
+        let offset = self.bytes.len();
         self.write_scope_size(ScopeSize::unfinished());
 
         // Do the timing last such that it doesn't include serialization
         let mut time_stamp_dest =
-            &mut self.0[time_stamp_offset..time_stamp_offset + size_of::<NanoSecond>()];
+            &mut self.bytes[time_stamp_offset..time_stamp_offset + size_of::<NanoSecond>()];
         let start_ns = now_ns();
         time_stamp_dest
             .write_i64::<LE>(start_ns)
@@ -144,48 +270,288 @@ impl Stream {
     /// Marks the end of the scope.
     #[inline]
     pub fn end_scope(&mut self, start_offset: usize, stop_ns: NanoSecond) {
+        self.end_scope_with_sentinel(start_offset, stop_ns, SCOPE_END);
+    }
+
+    /// Like [`Self::end_scope`], and records what the scope allocated.
+    #[inline]
+    pub fn end_scope_with_allocations(
+        &mut self,
+        start_offset: usize,
+        stop_ns: NanoSecond,
+        allocations: Allocations,
+    ) {
+        self.end_scope_with_sentinel(start_offset, stop_ns, SCOPE_END);
+        self.write_allocations(allocations);
+    }
+
+    #[inline]
+    fn end_scope_with_sentinel(&mut self, start_offset: usize, stop_ns: NanoSecond, sentinel: u8) {
         // Write total scope size where scope was started:
-        let scope_size = self.0.len() - (start_offset + size_of::<ScopeSize>());
-        debug_assert!(start_offset + size_of::<ScopeSize>() <= self.0.len());
-        let mut dest_range = &mut self.0[start_offset..start_offset + size_of::<ScopeSize>()];
+        let scope_size = self.bytes.len() - (start_offset + size_of::<ScopeSize>());
+        debug_assert!(start_offset + size_of::<ScopeSize>() <= self.bytes.len());
+        let mut dest_range = &mut self.bytes[start_offset..start_offset + size_of::<ScopeSize>()];
         dest_range
             .write_u64::<LE>(scope_size as u64)
             .expect("can't fail");
         debug_assert!(dest_range.is_empty());
 
         // Write scope end:
-        self.0.push(SCOPE_END);
+        self.bytes.push(sentinel);
         self.write_nanos(stop_ns);
     }
 
+    #[inline]
+    fn write_allocations(&mut self, allocations: Allocations) {
+        if !allocations.is_empty() {
+            self.bytes.push(SCOPE_ALLOCATIONS);
+            self.write_varint(allocations.alloc_count);
+            self.write_varint(allocations.alloc_bytes);
+            self.write_varint(allocations.dealloc_count);
+            self.write_varint(allocations.dealloc_bytes);
+        }
+    }
+
     #[inline]
     fn write_nanos(&mut self, nanos: NanoSecond) {
-        self.0.write_i64::<LE>(nanos).expect("can't fail");
+        self.bytes.write_i64::<LE>(nanos).expect("can't fail");
     }
 
     #[inline]
     fn write_scope_size(&mut self, nanos: ScopeSize) {
-        self.0.write_u64::<LE>(nanos.0).expect("can't fail");
+        self.bytes.write_u64::<LE>(nanos.0).expect("can't fail");
     }
 
     #[inline]
     fn write_scope_id(&mut self, scope_id: ScopeId) {
         // Could potentially use varint encoding.
-        self.0
+        self.bytes
             .write_u32::<LE>(scope_id.0.get())
             .expect("can't fail");
     }
 
+    /// Re-encode all the scopes in this stream in a compact layout,
+    /// using varints and time stamps relative to the parent or previous sibling.
+    ///
+    /// This is done for each finished frame, and typically makes the stream less than half the size.
+    /// All scopes must be finished.
+    pub fn to_compact(&self) -> Result<Stream> {
+        let string_indices: HashMap<&str, u32> = self
+            .strings
+            .iter()
+            .enumerate()
+            .map(|(index, s)| (s.as_str(), index as u32))
+            .collect();
+        let mut compact = Stream {
+            bytes: Vec::with_capacity(self.len() / 2),
+            strings: self.strings.clone(),
+            string_indices: Default::default(),
+        };
+        compact.write_compact_scopes(Reader::from_start(self), None, &string_indices)?;
+        Ok(compact)
+    }
+
+    /// `base_ns` is the start of the parent scope, or `None` for top-level scopes.
+    fn write_compact_scopes(
+        &mut self,
+        mut reader: Reader<'_>,
+        mut base_ns: Option<NanoSecond>,
+        string_indices: &HashMap<&str, u32>,
+    ) -> Result<()> {
+        while let Some(scope) = reader.parse_scope()? {
+            if scope.in_progress {
+                self.bytes.push(SCOPE_IN_PROGRESS);
+            }
+            let data_index = if scope.record.data.is_empty() {
+                None
+            } else {
+                string_indices.get(scope.record.data).copied()
+            };
+
+            self.bytes.push(if data_index.is_some() {
+                INTERNED_COMPACT_SCOPE_BEGIN
+            } else {
+                COMPACT_SCOPE_BEGIN
+            });
+            self.write_varint(scope.id.0.get() as u64);
+            self.write_varint(zigzag_encode(
+                scope.record.start_ns.wrapping_sub(base_ns.unwrap_or(0)),
+            ));
+            if let Some(data_index) = data_index {
+                self.write_varint(data_index as u64);
+            } else {
+                self.write_str(scope.record.data);
+            }
+            self.write_varint(scope.record.duration_ns as u64);
+
+            // We don't know the size of the children until we have written them,
+            // so reserve room for the largest size and then move the children back.
+            let size_offset = self.bytes.len();
+            self.bytes.extend([0; MAX_VARINT_LENGTH]);
+            self.write_compact_scopes(
+                reader.child_reader(&scope)?,
+                Some(scope.record.start_ns),
+                string_indices,
+            )?;
+            let children_offset = size_offset + MAX_VARINT_LENGTH;
+            let children_size = self.bytes.len() - children_offset;
+
+            let mut size = Stream::default();
+            size.write_varint(children_size as u64);
+            let size = size.bytes();
+            self.bytes
+                .copy_within(children_offset.., size_offset + size.len());
+            self.bytes[size_offset..size_offset + size.len()].copy_from_slice(size);
+            self.bytes
+                .truncate(self.bytes.len() - (MAX_VARINT_LENGTH - size.len()));
+            self.write_allocations(scope.allocations);
+
+            if base_ns.is_some() {
+                base_ns = Some(scope.record.stop_ns());
+            }
+        }
+        Ok(())
+    }
+
+    /// Append the scopes of `reader`, interning all their data in our string table.
+    fn append_interned(&mut self, mut reader: Reader<'_>) -> Result<()> {
+        while let Some(scope) = reader.parse_scope()? {
+            let start_ns = || scope.record.start_ns;
+            let (offset, _) = if scope.record.data.is_empty() {
+                self.begin_scope(start_ns, scope.id, "")
+            } else {
+                self.begin_scope_interned(start_ns, scope.id, scope.record.data)
+            };
+            self.append_interned(reader.child_reader(&scope)?)?;
+            let sentinel = if scope.in_progress {
+                SCOPE_IN_PROGRESS
+            } else {
+                SCOPE_END
+            };
+            self.end_scope_with_sentinel(offset, scope.record.stop_ns(), sentinel);
+            self.write_allocations(scope.allocations);
+        }
+        Ok(())
+    }
+
+    /// Split off everything recorded so far, ending the open scopes at `now_ns` as [`Scope::in_progress`].
+    ///
+    /// The open scopes are then begun again at `now_ns` in this stream,
+    /// which is left with nothing else, and `open_scopes` is updated to match.
+    /// `allocations` are what each open scope allocated so far, if known.
+    pub(crate) fn split_off_in_progress(
+        &mut self,
+        open_scopes: &mut [OpenScope],
+        allocations: &[Allocations],
+        now_ns: NanoSecond,
+    ) -> Stream {
+        let mut finished = Stream {
+            bytes: std::mem::take(&mut self.bytes),
+            strings: self.strings.clone(),
+            string_indices: Default::default(),
+        };
+
+        for (i, open_scope) in open_scopes.iter().enumerate().rev() {
+            finished.end_scope_with_sentinel(open_scope.size_offset, now_ns, SCOPE_IN_PROGRESS);
+            finished.write_allocations(allocations.get(i).copied().unwrap_or_default());
+        }
+
+        for open_scope in open_scopes.iter_mut() {
+            // Copy the sentinel, scope id, start time and data:
+            let begin_offset = self.bytes.len();
+            self.bytes.extend_from_slice(
+                &finished.bytes[open_scope.begin_offset..open_scope.size_offset],
+            );
+            let time_stamp_offset = begin_offset + 1 + size_of::<u32>();
+            self.bytes[time_stamp_offset..time_stamp_offset + size_of::<NanoSecond>()]
+                .copy_from_slice(&now_ns.to_le_bytes());
+            let size_offset = self.bytes.len();
+            self.write_scope_size(ScopeSize::unfinished());
+
+            *open_scope = OpenScope {
+                begin_offset,
+                size_offset,
+            };
+        }
+        finished
+    }
+
+    /// The id of a scope that has not ended yet.
+    pub(crate) fn open_scope_id(&self, open_scope: &OpenScope) -> Option<ScopeId> {
+        let id_offset = open_scope.begin_offset + 1;
+        let bytes = self.bytes.get(id_offset..id_offset + size_of::<u32>())?;
+        NonZeroU32::new(u32::from_le_bytes(bytes.try_into().ok()?)).map(ScopeId)
+    }
+
+    /// Remove the oldest finished scopes (the earliest children of the outermost open scopes)
+    /// until the stream is at most `max_bytes` long, or there are no more finished scopes.
+    ///
+    /// `open_scopes` is updated to match. Returns the number of removed scopes.
+    pub(crate) fn drop_oldest_scopes(
+        &mut self,
+        open_scopes: &mut [OpenScope],
+        max_bytes: usize,
+    ) -> Result<usize> {
+        let mut num_dropped = 0;
+        for level in 0..open_scopes.len() {
+            if self.bytes.len() <= max_bytes {
+                break;
+            }
+
+            let children_begin = open_scopes[level].size_offset + size_of::<ScopeSize>();
+            let children_end = open_scopes
+                .get(level + 1)
+                .map_or(self.bytes.len(), |open_scope| open_scope.begin_offset);
+
+            let mut drop_end = children_begin;
+            let mut reader = Reader::from_parts(&self.bytes, &self.strings);
+            reader.cursor.set_position(children_begin as u64);
+            reader.end_position = children_end as u64;
+            while self.bytes.len() - (drop_end - children_begin) > max_bytes {
+                let Some(scope) = reader.parse_scope()? else {
+                    break;
+                };
+                num_dropped +=
+                    1 + Reader::count_all_scopes(reader.child_reader(&scope)?, 0, &mut 0)?;
+                drop_end = scope.next_sibling_position as usize;
+            }
+
+            let num_bytes = drop_end - children_begin;
+            self.bytes.drain(children_begin..drop_end);
+            for open_scope in &mut open_scopes[level + 1..] {
+                open_scope.begin_offset -= num_bytes;
+                open_scope.size_offset -= num_bytes;
+            }
+        }
+        Ok(num_dropped)
+    }
+
+    /// Unsigned LEB128.
+    #[inline]
+    fn write_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
     #[inline]
     fn write_str(&mut self, s: &str) {
-        // Future-proof: we may want to use VLQs later.
-        const MAX_STRING_LENGTH: usize = 127;
-        let len = s.len().min(MAX_STRING_LENGTH);
-        self.0.write_u8(len as u8).expect("can't fail");
-        self.0.extend(s[0..len].as_bytes()); // This may split a character in two. The parser should handle that.
+        self.write_varint(s.len() as u64);
+        self.bytes.extend(s.as_bytes());
     }
 }
 
+/// Where a scope that has not ended yet is in a [`Stream`].
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct OpenScope {
+    /// Where the scope begins.
+    pub begin_offset: usize,
+    /// Where to write the scope size once the scope is closed, as returned by [`Stream::begin_scope`].
+    pub size_offset: usize,
+}
+
 /// A [`Stream`] plus some info about it.
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
@@ -204,6 +570,13 @@ pub struct StreamInfo {
     ///
     /// The default value is ([`NanoSecond::MAX`], [`NanoSecond::MIN`]) which indicates an empty stream.
     pub range_ns: (NanoSecond, NanoSecond),
+
+    /// Number of scopes that were dropped from the stream to limit its memory use,
+    /// see [`crate::ThreadMemoryPolicy::DropOldest`].
+    ///
+    /// Not serialized, but summed up in [`crate::FrameMeta::num_dropped_scopes`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub num_dropped_scopes: usize,
 }
 
 impl Default for StreamInfo {
@@ -213,6 +586,7 @@ impl Default for StreamInfo {
             num_scopes: 0,
             depth: 0,
             range_ns: (NanoSecond::MAX, NanoSecond::MIN),
+            num_dropped_scopes: 0,
         }
     }
 }
@@ -229,6 +603,7 @@ impl StreamInfo {
                 num_scopes: 0,
                 depth: 0,
                 range_ns: (NanoSecond::MAX, NanoSecond::MIN),
+                num_dropped_scopes: 0,
             })
         } else {
             let (num_scopes, depth) = Reader::count_scope_and_depth(&stream)?;
@@ -240,17 +615,32 @@ impl StreamInfo {
                 num_scopes,
                 depth,
                 range_ns: (min_ns, max_ns),
+                num_dropped_scopes: 0,
             })
         }
     }
 
     /// Extends this [`StreamInfo`] with another [`StreamInfo`].
     pub fn extend(&mut self, other: &StreamInfoRef<'_>) {
-        self.stream.extend(other.stream);
+        if other.strings.is_empty() {
+            self.stream.extend(other.stream);
+        } else if self.stream.strings.is_empty() {
+            self.stream.extend(other.stream);
+            for s in other.strings {
+                self.stream.intern(s);
+            }
+        } else {
+            // The string indices in `other` don't match ours, so we need to re-encode it:
+            let reader = Reader::from_parts(other.stream, other.strings);
+            if let Err(err) = self.stream.append_interned(reader) {
+                eprintln!("puffin ERROR: Failed to merge profile streams: {err:?}");
+            }
+        }
         self.num_scopes += other.num_scopes;
         self.depth = self.depth.max(other.depth);
         self.range_ns.0 = self.range_ns.0.min(other.range_ns.0);
         self.range_ns.1 = self.range_ns.1.max(other.range_ns.1);
+        self.num_dropped_scopes += other.num_dropped_scopes;
     }
 
     /// Clears the contents of this [`StreamInfo`].
@@ -260,20 +650,24 @@ impl StreamInfo {
             num_scopes,
             depth,
             range_ns,
+            num_dropped_scopes,
         } = self;
         stream.clear();
         *num_scopes = 0;
         *depth = 0;
         *range_ns = (NanoSecond::MAX, NanoSecond::MIN);
+        *num_dropped_scopes = 0;
     }
 
     /// Returns a reference to the contents of this [`StreamInfo`].
     pub fn as_stream_into_ref(&self) -> StreamInfoRef<'_> {
         StreamInfoRef {
             stream: self.stream.bytes(),
+            strings: self.stream.strings(),
             num_scopes: self.num_scopes,
             depth: self.depth,
             range_ns: self.range_ns,
+            num_dropped_scopes: self.num_dropped_scopes,
         }
     }
 }
@@ -284,6 +678,9 @@ pub struct StreamInfoRef<'a> {
     /// The raw profile data.
     pub stream: &'a [u8],
 
+    /// The string table of the stream, see [`Stream::strings`].
+    pub strings: &'a [String],
+
     /// Total number of scopes in the stream.
     pub num_scopes: usize,
 
@@ -295,6 +692,9 @@ pub struct StreamInfoRef<'a> {
     ///
     /// The default value is ([`NanoSecond::MAX`], [`NanoSecond::MIN`]) which indicates an empty stream.
     pub range_ns: (NanoSecond, NanoSecond),
+
+    /// Number of scopes that were dropped from the stream to limit its memory use.
+    pub num_dropped_scopes: usize,
 }
 
 /// Used to encode number of bytes covered by a scope.
@@ -308,6 +708,14 @@ impl ScopeSize {
     }
 }
 
+fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
 /// Errors that can happen when parsing a [`Stream`] of profile data.
 #[derive(Debug)]
 pub enum Error {
@@ -326,54 +734,133 @@ pub enum Error {
 /// Custom puffin result type.
 pub type Result<T> = std::result::Result<T, Error>;
 /// Parses a [`Stream`] of profiler data.
-pub struct Reader<'s>(std::io::Cursor<&'s [u8]>);
+pub struct Reader<'s> {
+    cursor: std::io::Cursor<&'s [u8]>,
+
+    /// For resolving interned scope data.
+    strings: &'s [String],
+
+    /// Where to stop reading. Child scopes in the compact layout have no end sentinel.
+    end_position: u64,
+
+    /// What the start of the next compact scope is relative to.
+    /// `None` for top-level scopes, which have absolute start times.
+    base_ns: Option<NanoSecond>,
+}
 
 impl<'s> Reader<'s> {
     /// Returns a reader that starts reading from the start of the stream.
     pub fn from_start(stream: &'s Stream) -> Self {
-        Self(std::io::Cursor::new(&stream.0[..]))
+        Self::from_parts(&stream.bytes, &stream.strings)
+    }
+
+    fn from_parts(bytes: &'s [u8], strings: &'s [String]) -> Self {
+        Self {
+            cursor: std::io::Cursor::new(bytes),
+            strings,
+            end_position: bytes.len() as u64,
+            base_ns: None,
+        }
     }
 
     /// Returns a reader that starts reading from an offset into the stream.
+    ///
+    /// In the compact layout the start times of child scopes are relative to their parent,
+    /// and the last child has no end sentinel, so this misreads the child scopes of finished frames.
+    #[deprecated = "Use `Reader::with_parent`, which also reads child scopes in the compact layout"]
     pub fn with_offset(stream: &'s Stream, offset: u64) -> Result<Self> {
         if offset <= stream.len() as u64 {
-            let mut cursor = std::io::Cursor::new(&stream.0[..]);
-            cursor.set_position(offset);
-            Ok(Self(cursor))
+            let mut reader = Self::from_start(stream);
+            reader.cursor.set_position(offset);
+            Ok(reader)
         } else {
             Err(Error::InvalidOffset)
         }
     }
 
+    /// Returns a reader of the children of `parent`, which must have been read from `stream`.
+    pub fn with_parent(stream: &'s Stream, parent: &Scope<'_>) -> Result<Self> {
+        Self::from_start(stream).child_reader(parent)
+    }
+
+    /// Returns a reader of the children of `parent`, which must have been read from the same stream.
+    fn child_reader(&self, parent: &Scope<'_>) -> Result<Self> {
+        let mut reader = Self::from_parts(self.cursor.get_ref(), self.strings);
+        if reader.end_position < parent.child_end_position
+            || parent.child_end_position < parent.child_begin_position
+        {
+            return Err(Error::InvalidOffset);
+        }
+        reader.cursor.set_position(parent.child_begin_position);
+        reader.end_position = parent.child_end_position;
+        reader.base_ns = Some(parent.record.start_ns);
+        Ok(reader)
+    }
+
     /// Parse the next scope in the stream, if any,
     /// and advance to the next sibling scope (if any).
     fn parse_scope(&mut self) -> Result<Option<Scope<'s>>> {
-        match self.peek_u8() {
-            Some(SCOPE_BEGIN) => {
+        if self.end_position <= self.cursor.position() {
+            return Ok(None);
+        }
+        let interned = match self.peek_u8() {
+            Some(SCOPE_BEGIN) => false,
+            Some(INTERNED_SCOPE_BEGIN) => true,
+            Some(COMPACT_SCOPE_BEGIN) => {
+                self.parse_u8()
+                    .expect("swallowing already peeked COMPACT_SCOPE_BEGIN");
+                return self.parse_compact_scope(false, false).map(Some);
+            }
+            Some(INTERNED_COMPACT_SCOPE_BEGIN) => {
                 self.parse_u8()
-                    .expect("swallowing already peeked SCOPE_BEGIN");
+                    .expect("swallowing already peeked INTERNED_COMPACT_SCOPE_BEGIN");
+                return self.parse_compact_scope(true, false).map(Some);
+            }
+            Some(SCOPE_IN_PROGRESS) => {
+                self.parse_u8()
+                    .expect("swallowing already peeked SCOPE_IN_PROGRESS");
+                let interned = match self.parse_u8()? {
+                    COMPACT_SCOPE_BEGIN => false,
+                    INTERNED_COMPACT_SCOPE_BEGIN => true,
+                    _ => return Err(Error::InvalidStream),
+                };
+                return self.parse_compact_scope(interned, true).map(Some);
             }
             Some(_) | None => return Ok(None),
-        }
+        };
+        self.parse_u8()
+            .expect("swallowing already peeked SCOPE_BEGIN");
 
         let scope_id = self.parse_scope_id()?;
         let start_ns = self.parse_nanos()?;
-        let data = self.parse_string()?;
+        let data = if interned {
+            let data_index = self
+                .cursor
+                .read_u32::<LE>()
+                .map_err(|_err| Error::PrematureEnd)?;
+            self.interned_string(data_index as u64)?
+        } else {
+            self.parse_string()?
+        };
         let scope_size = self.parse_scope_size()?;
         if scope_size == ScopeSize::unfinished() {
             return Err(Error::ScopeNeverEnded);
         }
-        let child_begin_position = self.0.position();
-        self.0.set_position(child_begin_position + scope_size.0);
-        let child_end_position = self.0.position();
-
-        if self.parse_u8()? != SCOPE_END {
-            return Err(Error::InvalidStream);
-        }
+        let child_begin_position = self.cursor.position();
+        self.cursor
+            .set_position(child_begin_position + scope_size.0);
+        let child_end_position = self.cursor.position();
+
+        let in_progress = match self.parse_u8()? {
+            SCOPE_END => false,
+            SCOPE_IN_PROGRESS => true,
+            _ => return Err(Error::InvalidStream),
+        };
         let stop_ns = self.parse_nanos()?;
         if stop_ns < start_ns {
             return Err(Error::InvalidStream);
         }
+        let allocations = self.parse_allocations()?;
 
         Ok(Some(Scope {
             id: scope_id,
@@ -384,10 +871,61 @@ impl<'s> Reader<'s> {
             },
             child_begin_position,
             child_end_position,
-            next_sibling_position: self.0.position(),
+            next_sibling_position: self.cursor.position(),
+            in_progress,
+            allocations,
         }))
     }
 
+    /// Parse a scope in the compact layout, after its sentinel.
+    fn parse_compact_scope(&mut self, interned: bool, in_progress: bool) -> Result<Scope<'s>> {
+        let scope_id = u32::try_from(self.parse_varint()?)
+            .ok()
+            .and_then(NonZeroU32::new)
+            .map(ScopeId)
+            .ok_or(Error::InvalidStream)?;
+        let start_ns = self
+            .base_ns
+            .unwrap_or(0)
+            .wrapping_add(zigzag_decode(self.parse_varint()?));
+        let data = if interned {
+            let data_index = self.parse_varint()?;
+            self.interned_string(data_index)?
+        } else {
+            self.parse_string()?
+        };
+        let duration_ns =
+            NanoSecond::try_from(self.parse_varint()?).map_err(|_err| Error::InvalidStream)?;
+        let scope_size = self.parse_varint()?;
+
+        let child_begin_position = self.cursor.position();
+        let child_end_position = child_begin_position
+            .checked_add(scope_size)
+            .filter(|&end| end <= self.end_position)
+            .ok_or(Error::PrematureEnd)?;
+        self.cursor.set_position(child_end_position);
+        let allocations = self.parse_allocations()?;
+
+        let record = ScopeRecord {
+            start_ns,
+            duration_ns,
+            data,
+        };
+        if self.base_ns.is_some() {
+            self.base_ns = Some(record.stop_ns());
+        }
+
+        Ok(Scope {
+            id: scope_id,
+            record,
+            child_begin_position,
+            child_end_position,
+            next_sibling_position: self.cursor.position(),
+            in_progress,
+            allocations,
+        })
+    }
+
     /// Read all the top-level scopes (non-recursive) until the end of the stream.
     pub fn read_top_scopes(self) -> Result<Vec<Scope<'s>>> {
         let mut scopes = vec![];
@@ -397,20 +935,49 @@ impl<'s> Reader<'s> {
         Ok(scopes)
     }
 
+    /// The allocations following a scope, if any.
+    fn parse_allocations(&mut self) -> Result<Allocations> {
+        if self.cursor.position() < self.end_position && self.peek_u8() == Some(SCOPE_ALLOCATIONS) {
+            self.parse_u8()
+                .expect("swallowing already peeked SCOPE_ALLOCATIONS");
+            Ok(Allocations {
+                alloc_count: self.parse_varint()?,
+                alloc_bytes: self.parse_varint()?,
+                dealloc_count: self.parse_varint()?,
+                dealloc_bytes: self.parse_varint()?,
+            })
+        } else {
+            Ok(Allocations::default())
+        }
+    }
+
     /// [`None`] if at end of stream
     fn peek_u8(&mut self) -> Option<u8> {
-        let position = self.0.position();
-        let value = self.0.read_u8().ok();
-        self.0.set_position(position);
+        let position = self.cursor.position();
+        let value = self.cursor.read_u8().ok();
+        self.cursor.set_position(position);
         value
     }
 
     fn parse_u8(&mut self) -> Result<u8> {
-        self.0.read_u8().map_err(|_err| Error::PrematureEnd)
+        self.cursor.read_u8().map_err(|_err| Error::PrematureEnd)
+    }
+
+    /// Unsigned LEB128.
+    fn parse_varint(&mut self) -> Result<u64> {
+        let mut value = 0_u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.parse_u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::InvalidStream)
     }
 
     fn parse_scope_id(&mut self) -> Result<ScopeId> {
-        self.0
+        self.cursor
             .read_u32::<LE>()
             .context("Can not parse scope id")
             .and_then(|x| NonZeroU32::new(x).context("Not a `NonZeroU32` scope id"))
@@ -419,51 +986,63 @@ impl<'s> Reader<'s> {
     }
 
     fn parse_nanos(&mut self) -> Result<NanoSecond> {
-        self.0.read_i64::<LE>().map_err(|_err| Error::PrematureEnd)
+        self.cursor
+            .read_i64::<LE>()
+            .map_err(|_err| Error::PrematureEnd)
     }
 
     fn parse_scope_size(&mut self) -> Result<ScopeSize> {
-        self.0
+        self.cursor
             .read_u64::<LE>()
             .map_err(|_err| Error::PrematureEnd)
             .map(ScopeSize)
     }
 
     fn parse_string(&mut self) -> Result<&'s str> {
-        let len = self.parse_u8().map_err(|_err| Error::PrematureEnd)? as usize;
-        let data = self.0.get_ref();
-        let begin = self.0.position() as usize;
-        let end = begin + len;
+        // Older streams used a u8 length of at most 127, which parses the same.
+        let len = self.parse_varint()?;
+        let data = self.cursor.get_ref();
+        let begin = self.cursor.position() as usize;
+        let end = usize::try_from(len)
+            .ok()
+            .and_then(|len| begin.checked_add(len))
+            .ok_or(Error::PrematureEnd)?;
         if end <= data.len() {
             let s = longest_valid_utf8_prefix(&data[begin..end]);
-            self.0.set_position(end as u64);
+            self.cursor.set_position(end as u64);
             Ok(s)
         } else {
             Err(Error::PrematureEnd)
         }
     }
 
+    fn interned_string(&self, index: u64) -> Result<&'s str> {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.strings.get(index))
+            .map(String::as_str)
+            .ok_or(Error::InvalidStream)
+    }
+
     /// Recursively count all profile scopes in a stream.
     /// Returns total number of scopes and maximum recursion depth.
     pub fn count_scope_and_depth(stream: &Stream) -> Result<(usize, usize)> {
         let mut max_depth = 0;
-        let num_scopes = Self::count_all_scopes_at_offset(stream, 0, 0, &mut max_depth)?;
+        let num_scopes = Self::count_all_scopes(Reader::from_start(stream), 0, &mut max_depth)?;
         Ok((num_scopes, max_depth))
     }
 
-    fn count_all_scopes_at_offset(
-        stream: &Stream,
-        offset: u64,
+    fn count_all_scopes(
+        mut reader: Reader<'_>,
         depth: usize,
         max_depth: &mut usize,
     ) -> Result<usize> {
         *max_depth = (*max_depth).max(depth);
 
         let mut num_scopes = 0;
-        for child_scope in Reader::with_offset(stream, offset)? {
-            num_scopes += 1 + Self::count_all_scopes_at_offset(
-                stream,
-                child_scope?.child_begin_position,
+        while let Some(child_scope) = reader.parse_scope()? {
+            num_scopes += 1 + Self::count_all_scopes(
+                reader.child_reader(&child_scope)?,
                 depth + 1,
                 max_depth,
             )?;
@@ -477,7 +1056,7 @@ fn longest_valid_utf8_prefix(data: &[u8]) -> &str {
     match std::str::from_utf8(data) {
         Ok(s) => s,
         Err(error) => {
-            // The string may be been truncated to fit a max length of 255.
+            // Older streams truncated strings to fit a max length of 127.
             // This truncation may have happened in the middle of a unicode character.
             std::str::from_utf8(&data[..error.valid_up_to()]).expect("We can trust valid_up_to")
         }
@@ -510,6 +1089,38 @@ fn write_scope() {
     );
 }
 
+#[test]
+fn long_data_string() {
+    let long = "some/long/path/".repeat(20) + "shader.wgsl";
+    assert!(long.len() > 255);
+
+    let mut stream = Stream::default();
+    let (start, _) = stream.begin_scope(|| 100, ScopeId::new(1), &long);
+    stream.end_scope(start, 300);
+    let (start, _) = stream.begin_scope(|| 400, ScopeId::new(1), "short");
+    stream.end_scope(start, 500);
+
+    for stream in [stream.clone(), stream.to_compact().unwrap()] {
+        let scopes = Reader::from_start(&stream).read_top_scopes().unwrap();
+        assert_eq!(scopes.len(), 2);
+        assert_eq!(scopes[0].record.data, long);
+        assert_eq!(scopes[1].record.data, "short");
+    }
+
+    // Streams written with a u8 length prefix can still be read:
+    let mut old_stream = vec![SCOPE_BEGIN];
+    old_stream.extend(1_u32.to_le_bytes());
+    old_stream.extend(100_i64.to_le_bytes());
+    old_stream.push(127);
+    old_stream.extend(&long.as_bytes()[..127]);
+    old_stream.extend(0_u64.to_le_bytes());
+    old_stream.push(SCOPE_END);
+    old_stream.extend(300_i64.to_le_bytes());
+    let old_stream = Stream::from(old_stream);
+    let scopes = Reader::from_start(&old_stream).read_top_scopes().unwrap();
+    assert_eq!(scopes[0].record.data, &long[..127]);
+}
+
 #[test]
 fn test_profile_data() {
     let stream = {
@@ -534,6 +1145,7 @@ fn test_profile_data() {
         }
     );
 
+    #[allow(deprecated)] // Still works for streams that are not compacted.
     let middle_scopes = Reader::with_offset(&stream, top_scopes[0].child_begin_position)
         .unwrap()
         .read_top_scopes()
@@ -558,3 +1170,274 @@ fn test_profile_data() {
         }
     );
 }
+
+#[test]
+fn compact_stream() {
+    fn collect_tree(
+        stream: &Stream,
+        reader: Reader<'_>,
+        depth: usize,
+        out: &mut Vec<(usize, u32, i64, i64)>,
+    ) {
+        for scope in reader {
+            let scope = scope.unwrap();
+            out.push((
+                depth,
+                scope.id.0.get(),
+                scope.record.start_ns,
+                scope.record.stop_ns(),
+            ));
+            collect_tree(
+                stream,
+                Reader::with_parent(stream, &scope).unwrap(),
+                depth + 1,
+                out,
+            );
+        }
+    }
+
+    let mut stream = Stream::default();
+    for frame in 0..2 {
+        let ns = 1_700_000_000_000_000_000 + frame * 10_000;
+        let (t0, _) = stream.begin_scope(|| ns + 100, ScopeId::new(1), "data_top");
+        let (m0, _) = stream.begin_scope(|| ns + 200, ScopeId::new(2), "");
+        let (l0, _) = stream.begin_scope(|| ns + 250, ScopeId::new(3), "leaf");
+        stream.end_scope(l0, ns + 260);
+        stream.end_scope(m0, ns + 300);
+        // Starts before the previous sibling ended:
+        let (m1, _) = stream.begin_scope(|| ns + 290, ScopeId::new(300), "");
+        stream.end_scope(m1, ns + 400);
+        stream.end_scope(t0, ns + 400);
+    }
+
+    let compact = stream.to_compact().unwrap();
+    assert!(compact.len() < stream.len() / 2);
+
+    let mut expected = vec![];
+    collect_tree(&stream, Reader::from_start(&stream), 0, &mut expected);
+    let mut actual = vec![];
+    collect_tree(&compact, Reader::from_start(&compact), 0, &mut actual);
+    assert_eq!(expected.len(), 8);
+    assert_eq!(actual, expected);
+
+    assert_eq!(
+        Reader::count_scope_and_depth(&compact).unwrap(),
+        Reader::count_scope_and_depth(&stream).unwrap()
+    );
+
+    // Compact streams can be concatenated:
+    let mut both = compact.clone();
+    both.extend(compact.bytes());
+    let top_scopes = Reader::from_start(&both).read_top_scopes().unwrap();
+    assert_eq!(top_scopes.len(), 4);
+    assert_eq!(top_scopes[3].record.start_ns, expected[4].2);
+}
+
+#[test]
+fn interned_data() {
+    fn chunk(start_ns: NanoSecond, data: &[&str]) -> StreamInfo {
+        let mut stream = Stream::default();
+        let (top, _) = stream.begin_scope(|| start_ns, ScopeId::new(1), "top");
+        for (i, data) in data.iter().enumerate() {
+            let ns = start_ns + 10 * i as NanoSecond;
+            let (child, _) = stream.begin_scope_interned(|| ns, ScopeId::new(2), data);
+            stream.end_scope(child, ns + 5);
+        }
+        stream.end_scope(top, start_ns + 1000);
+        StreamInfo::parse(stream).unwrap()
+    }
+
+    fn all_data(stream: &Stream) -> Vec<&str> {
+        let mut data = vec![];
+        for top in Reader::from_start(stream) {
+            let top = top.unwrap();
+            data.push(top.record.data);
+            for child in Reader::with_parent(stream, &top).unwrap() {
+                data.push(child.unwrap().record.data);
+            }
+        }
+        data
+    }
+
+    let first = chunk(0, &["tree.png", "rock.png", "tree.png", "tree.png"]);
+    assert_eq!(first.stream.strings(), ["tree.png", "rock.png"]);
+    assert_eq!(
+        all_data(&first.stream),
+        ["top", "tree.png", "rock.png", "tree.png", "tree.png"]
+    );
+
+    let compact = first.stream.to_compact().unwrap();
+    assert_eq!(all_data(&compact), all_data(&first.stream));
+    assert_eq!(compact.strings(), first.stream.strings());
+
+    // Merging streams with different string tables interns all data of the second one:
+    let second = chunk(2000, &["sky.png", "tree.png"]);
+    let mut merged = StreamInfo::default();
+    merged.extend(&first.as_stream_into_ref());
+    merged.extend(&second.as_stream_into_ref());
+    assert_eq!(
+        merged.stream.strings(),
+        ["tree.png", "rock.png", "top", "sky.png"]
+    );
+    assert_eq!(
+        all_data(&merged.stream),
+        ["top", "tree.png", "rock.png", "tree.png", "tree.png", "top", "sky.png", "tree.png"]
+    );
+    assert_eq!(merged.num_scopes, 8);
+}
+
+#[test]
+fn scope_allocations() {
+    let allocations = |count: u64| Allocations {
+        alloc_count: count,
+        alloc_bytes: 100 * count,
+        dealloc_count: 1,
+        dealloc_bytes: 50,
+    };
+
+    let mut stream = Stream::default();
+    let (top, _) = stream.begin_scope(|| 100, ScopeId::new(1), "");
+    let (child, _) = stream.begin_scope_interned(|| 200, ScopeId::new(2), "interned");
+    stream.end_scope_with_allocations(child, 300, allocations(2));
+    let (child, _) = stream.begin_scope(|| 300, ScopeId::new(3), "");
+    stream.end_scope(child, 400);
+    stream.end_scope_with_allocations(top, 500, allocations(3));
+    let (last, _) = stream.begin_scope(|| 600, ScopeId::new(4), "");
+    stream.end_scope_with_allocations(last, 700, allocations(1));
+
+    let mut merged = StreamInfo::default();
+    merged.extend(
+        &StreamInfo::parse(stream.clone())
+            .unwrap()
+            .as_stream_into_ref(),
+    );
+
+    for stream in [stream.to_compact().unwrap(), merged.stream, stream] {
+        let top = Reader::from_start(&stream).read_top_scopes().unwrap();
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].allocations, allocations(3));
+        assert_eq!(top[1].allocations, allocations(1));
+        assert_eq!(top[1].record.start_ns, 600);
+
+        let children = Reader::with_parent(&stream, &top[0])
+            .unwrap()
+            .read_top_scopes()
+            .unwrap();
+        assert_eq!(children.len(), 2);
+        assert_eq!(children[0].allocations, allocations(2));
+        assert_eq!(children[0].record.data, "interned");
+        assert_eq!(children[1].allocations, Allocations::default());
+        assert_eq!(children[1].record.stop_ns(), 400);
+        assert_eq!(Reader::count_scope_and_depth(&stream).unwrap(), (4, 2));
+    }
+}
+
+#[cfg(test)]
+fn begin_open_scope(
+    stream: &mut Stream,
+    open_scopes: &mut Vec<OpenScope>,
+    start_ns: NanoSecond,
+    scope_id: u32,
+) {
+    let begin_offset = stream.len();
+    let (size_offset, _) = stream.begin_scope(|| start_ns, ScopeId::new(scope_id), "");
+    open_scopes.push(OpenScope {
+        begin_offset,
+        size_offset,
+    });
+}
+
+#[test]
+fn in_progress_scopes() {
+    let mut stream = Stream::default();
+    let mut open_scopes = vec![];
+    begin_open_scope(&mut stream, &mut open_scopes, 100, 1);
+    let (child, _) = stream.begin_scope(|| 200, ScopeId::new(2), "child");
+    stream.end_scope(child, 300);
+    begin_open_scope(&mut stream, &mut open_scopes, 350, 3);
+
+    let allocations = [
+        Allocations::default(),
+        Allocations {
+            alloc_count: 1,
+            alloc_bytes: 16,
+            ..Default::default()
+        },
+    ];
+    let finished = stream.split_off_in_progress(&mut open_scopes, &allocations, 400);
+    for finished in [finished.clone(), finished.to_compact().unwrap()] {
+        let top = Reader::from_start(&finished).read_top_scopes().unwrap();
+        assert_eq!(top.len(), 1);
+        assert!(top[0].in_progress);
+        assert_eq!(top[0].record.start_ns, 100);
+        assert_eq!(top[0].record.stop_ns(), 400);
+
+        let children = Reader::with_parent(&finished, &top[0])
+            .unwrap()
+            .read_top_scopes()
+            .unwrap();
+        assert_eq!(children.len(), 2);
+        assert!(!children[0].in_progress);
+        assert_eq!(children[0].record.data, "child");
+        assert!(children[1].in_progress);
+        assert_eq!(children[1].record.start_ns, 350);
+        assert_eq!(children[1].allocations, allocations[1]);
+    }
+
+    // The open scopes continue in the stream:
+    stream.end_scope(open_scopes[1].size_offset, 450);
+    stream.end_scope(open_scopes[0].size_offset, 500);
+    let top = Reader::from_start(&stream).read_top_scopes().unwrap();
+    assert_eq!(top.len(), 1);
+    assert!(!top[0].in_progress);
+    assert_eq!(top[0].id, ScopeId::new(1));
+    assert_eq!(
+        (top[0].record.start_ns, top[0].record.stop_ns()),
+        (400, 500)
+    );
+    assert_eq!(Reader::count_scope_and_depth(&stream).unwrap(), (2, 2));
+}
+
+#[test]
+fn drop_oldest_scopes() {
+    let mut stream = Stream::default();
+    let mut open_scopes = vec![];
+    begin_open_scope(&mut stream, &mut open_scopes, 0, 1);
+    for i in 0..10 {
+        let (child, _) = stream.begin_scope(|| 100 * i, ScopeId::new(2), "");
+        let (grandchild, _) = stream.begin_scope(|| 100 * i + 10, ScopeId::new(3), "");
+        stream.end_scope(grandchild, 100 * i + 20);
+        stream.end_scope(child, 100 * i + 50);
+    }
+    let one_child_size = stream.len() / 10;
+    begin_open_scope(&mut stream, &mut open_scopes, 2000, 4);
+    let (inner_child, _) = stream.begin_scope(|| 2100, ScopeId::new(5), "");
+    stream.end_scope(inner_child, 2200);
+
+    // Drop the three oldest children, with their grandchildren:
+    let max_bytes = stream.len() - 2 * one_child_size - 1;
+    let num_dropped = stream
+        .drop_oldest_scopes(&mut open_scopes, max_bytes)
+        .unwrap();
+    assert_eq!(num_dropped, 6);
+    assert!(stream.len() <= max_bytes);
+
+    stream.end_scope(open_scopes[1].size_offset, 2300);
+    stream.end_scope(open_scopes[0].size_offset, 2400);
+    let top = Reader::from_start(&stream).read_top_scopes().unwrap();
+    let children = Reader::with_parent(&stream, &top[0])
+        .unwrap()
+        .read_top_scopes()
+        .unwrap();
+    assert_eq!(children.len(), 8);
+    assert_eq!(children[0].record.start_ns, 300);
+    assert_eq!(children[7].id, ScopeId::new(4));
+    assert_eq!(
+        Reader::count_scope_and_depth(&stream).unwrap(),
+        (1 + 14 + 2, 3)
+    );
+
+    // Nothing more to drop than the finished scopes:
+    let num_dropped = stream.drop_oldest_scopes(&mut [], 0).unwrap();
+    assert_eq!(num_dropped, 0);
+}
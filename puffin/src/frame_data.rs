@@ -1,5 +1,7 @@
 use crate::ScopeDetails;
-use crate::{Error, FrameIndex, NanoSecond, Result, StreamInfo, ThreadInfo};
+use crate::{
+    ClockKind, Error, FrameIndex, NanoSecond, Result, StreamInfo, SystemMetrics, ThreadInfo,
+};
 #[cfg(feature = "packing")]
 use parking_lot::RwLock;
 
@@ -23,8 +25,23 @@ pub struct FrameMeta {
     /// The unpacked size of all streams.
     pub num_bytes: usize,
 
+    /// The size all streams had before they were re-encoded with [`crate::Stream::to_compact`].
+    ///
+    /// The same as [`Self::num_bytes`] for frames recorded before the compact layout was introduced.
+    pub num_bytes_uncompacted: usize,
+
     /// Total number of scopes.
     pub num_scopes: usize,
+
+    /// Number of scopes that threads dropped to limit their memory use,
+    /// see [`crate::ThreadMemoryPolicy::DropOldest`].
+    pub num_dropped_scopes: usize,
+
+    /// The clock the frame was timed with, see [`crate::set_clock`].
+    pub clock: ClockKind,
+
+    /// What the process used during the frame, see [`crate::GlobalProfiler::set_system_metrics`].
+    pub system_metrics: Option<SystemMetrics>,
 }
 
 /// One frame worth of profile data, collected from many sources.
@@ -43,19 +60,29 @@ impl UnpackedFrameData {
         frame_index: FrameIndex,
         thread_streams: BTreeMap<ThreadInfo, StreamInfo>,
     ) -> Result<Self> {
+        let mut num_bytes_uncompacted = 0;
         let thread_streams: BTreeMap<_, _> = thread_streams
             .into_iter()
-            .map(|(info, stream_info)| (info, Arc::new(stream_info)))
+            .map(|(info, mut stream_info)| {
+                num_bytes_uncompacted += stream_info.stream.num_bytes_with_strings();
+                // Fall back to the original layout if there are e.g. unfinished scopes:
+                if let Ok(compact) = stream_info.stream.to_compact() {
+                    stream_info.stream = compact;
+                }
+                (info, Arc::new(stream_info))
+            })
             .collect();
 
         let mut num_bytes = 0;
         let mut num_scopes = 0;
+        let mut num_dropped_scopes = 0;
 
         let mut min_ns = NanoSecond::MAX;
         let mut max_ns = NanoSecond::MIN;
         for stream_info in thread_streams.values() {
-            num_bytes += stream_info.stream.len();
+            num_bytes += stream_info.stream.num_bytes_with_strings();
             num_scopes += stream_info.num_scopes;
+            num_dropped_scopes += stream_info.num_dropped_scopes;
             min_ns = min_ns.min(stream_info.range_ns.0);
             max_ns = max_ns.max(stream_info.range_ns.1);
         }
@@ -66,7 +93,11 @@ impl UnpackedFrameData {
                     frame_index,
                     range_ns: (min_ns, max_ns),
                     num_bytes,
+                    num_bytes_uncompacted,
                     num_scopes,
+                    num_dropped_scopes,
+                    clock: crate::clock_kind(),
+                    system_metrics: None,
                 },
                 thread_streams,
             })
@@ -121,8 +152,21 @@ impl FrameData {
         scope_delta: Vec<Arc<ScopeDetails>>,
         full_delta: bool,
     ) -> Result<Self> {
+        Self::with_system_metrics(frame_index, thread_streams, scope_delta, full_delta, None)
+    }
+
+    /// Like [`Self::new`], with the [`FrameMeta::system_metrics`] sampled during the frame.
+    pub(crate) fn with_system_metrics(
+        frame_index: FrameIndex,
+        thread_streams: BTreeMap<ThreadInfo, StreamInfo>,
+        scope_delta: Vec<Arc<ScopeDetails>>,
+        full_delta: bool,
+        system_metrics: Option<SystemMetrics>,
+    ) -> Result<Self> {
+        let mut unpacked_frame = UnpackedFrameData::new(frame_index, thread_streams)?;
+        unpacked_frame.meta.system_metrics = system_metrics;
         Ok(Self::from_unpacked(
-            Arc::new(UnpackedFrameData::new(frame_index, thread_streams)?),
+            Arc::new(unpacked_frame),
             scope_delta,
             full_delta,
         ))
@@ -229,6 +273,61 @@ impl CompressionKind {
 struct PackedStreams {
     compression_kind: CompressionKind,
     bytes: Vec<u8>,
+
+    /// Serialized before `PFD5`, with the [`LegacyThreadInfo`] and [`LegacyStreamInfo`] layout.
+    legacy: bool,
+}
+
+/// [`ThreadInfo`] as serialized before `PFD5`.
+#[cfg(feature = "packing")]
+#[derive(serde::Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+struct LegacyThreadInfo {
+    start_time_ns: Option<NanoSecond>,
+    name: String,
+}
+
+#[cfg(feature = "packing")]
+impl From<LegacyThreadInfo> for ThreadInfo {
+    fn from(info: LegacyThreadInfo) -> Self {
+        let LegacyThreadInfo {
+            start_time_ns,
+            name,
+        } = info;
+        Self {
+            start_time_ns,
+            name,
+            ..Default::default()
+        }
+    }
+}
+
+/// [`StreamInfo`] as serialized before `PFD5`, when streams had no string table.
+#[cfg(feature = "packing")]
+#[derive(serde::Deserialize)]
+struct LegacyStreamInfo {
+    stream: Vec<u8>,
+    num_scopes: usize,
+    depth: usize,
+    range_ns: (NanoSecond, NanoSecond),
+}
+
+#[cfg(feature = "packing")]
+impl From<LegacyStreamInfo> for StreamInfo {
+    fn from(stream_info: LegacyStreamInfo) -> Self {
+        let LegacyStreamInfo {
+            stream,
+            num_scopes,
+            depth,
+            range_ns,
+        } = stream_info;
+        Self {
+            stream: stream.into(),
+            num_scopes,
+            depth,
+            range_ns,
+            num_dropped_scopes: 0,
+        }
+    }
 }
 
 #[cfg(feature = "packing")]
@@ -237,6 +336,17 @@ impl PackedStreams {
         Self {
             compression_kind,
             bytes,
+            legacy: false,
+        }
+    }
+
+    /// Streams packed by puffin before `PFD5`.
+    #[cfg(feature = "serialization")]
+    fn new_legacy(compression_kind: CompressionKind, bytes: Vec<u8>) -> Self {
+        Self {
+            compression_kind,
+            bytes,
+            legacy: true,
         }
     }
 
@@ -249,23 +359,14 @@ impl PackedStreams {
 
         cfg_if::cfg_if! {
             if #[cfg(feature = "lz4")] {
-                Self {
-                    compression_kind: CompressionKind::Lz4,
-                    bytes: lz4_flex::compress_prepend_size(&serialized),
-                }
+                Self::new(CompressionKind::Lz4, lz4_flex::compress_prepend_size(&serialized))
             } else if #[cfg(feature = "zstd")] {
                 let level = 3;
                 let bytes = zstd::encode_all(std::io::Cursor::new(&serialized), level)
                     .expect("zstd failed to compress");
-                Self {
-                    compression_kind: CompressionKind::Zstd,
-                    bytes,
-                }
+                Self::new(CompressionKind::Zstd, bytes)
             } else {
-                Self {
-                    compression_kind: CompressionKind::Uncompressed,
-                    bytes: serialized,
-                }
+                Self::new(CompressionKind::Uncompressed, serialized)
             }
         }
     }
@@ -280,12 +381,22 @@ impl PackedStreams {
         use anyhow::Context as _;
         use bincode::Options as _;
 
-        fn deserialize(bytes: &[u8]) -> anyhow::Result<ThreadStreams> {
+        let deserialize = |bytes: &[u8]| -> anyhow::Result<ThreadStreams> {
             crate::profile_scope!("bincode deserialize");
-            bincode::options()
-                .deserialize(bytes)
-                .context("bincode deserialize")
-        }
+            if self.legacy {
+                let streams: BTreeMap<LegacyThreadInfo, LegacyStreamInfo> = bincode::options()
+                    .deserialize(bytes)
+                    .context("bincode deserialize")?;
+                Ok(streams
+                    .into_iter()
+                    .map(|(info, stream_info)| (info.into(), Arc::new(stream_info.into())))
+                    .collect())
+            } else {
+                bincode::options()
+                    .deserialize(bytes)
+                    .context("bincode deserialize")
+            }
+        };
 
         match self.compression_kind {
             CompressionKind::Uncompressed => deserialize(&self.bytes),
@@ -346,7 +457,7 @@ pub struct FrameData {
 impl Clone for FrameData {
     fn clone(&self) -> Self {
         Self {
-            meta: self.meta.clone(),
+            meta: self.meta,
             data: RwLock::new((*self.data.read()).clone()),
             scope_delta: self.scope_delta.clone(),
             full_delta: self.full_delta,
@@ -475,8 +586,21 @@ impl FrameData {
         scope_delta: Vec<Arc<ScopeDetails>>,
         full_delta: bool,
     ) -> Result<Self> {
+        Self::with_system_metrics(frame_index, thread_streams, scope_delta, full_delta, None)
+    }
+
+    /// Like [`Self::new`], with the [`FrameMeta::system_metrics`] sampled during the frame.
+    pub(crate) fn with_system_metrics(
+        frame_index: FrameIndex,
+        thread_streams: BTreeMap<ThreadInfo, StreamInfo>,
+        scope_delta: Vec<Arc<ScopeDetails>>,
+        full_delta: bool,
+        system_metrics: Option<SystemMetrics>,
+    ) -> Result<Self> {
+        let mut unpacked_frame = UnpackedFrameData::new(frame_index, thread_streams)?;
+        unpacked_frame.meta.system_metrics = system_metrics;
         Ok(Self::from_unpacked(
-            Arc::new(UnpackedFrameData::new(frame_index, thread_streams)?),
+            Arc::new(unpacked_frame),
             scope_delta,
             full_delta,
         ))
@@ -587,10 +711,20 @@ impl FrameData {
 
         let meta_serialized = bincode::options().serialize(&self.meta)?;
 
-        write.write_all(b"PFD4")?;
+        write.write_all(b"PFD5")?;
         write.write_all(&(meta_serialized.len() as u32).to_le_bytes())?;
         write.write_all(&meta_serialized)?;
 
+        // Streams read from an older file are packed in their old format, so repack them:
+        if self
+            .data
+            .read()
+            .packed()
+            .is_some_and(|packed| packed.legacy)
+        {
+            let unpacked = self.unpacked()?;
+            *self.data.write() = FrameDataState::Unpacked(unpacked);
+        }
         self.create_packed();
         let packed_streams_lock = self.data.read();
         let packed_streams = packed_streams_lock.packed().unwrap(); // We just called create_packed
@@ -653,7 +787,11 @@ impl FrameData {
                         frame_index,
                         range_ns,
                         num_bytes,
+                        num_bytes_uncompacted: num_bytes,
                         num_scopes,
+                        num_dropped_scopes: 0,
+                        clock: ClockKind::Unknown,
+                        system_metrics: None,
                     },
                     thread_streams,
                 }
@@ -705,8 +843,9 @@ impl FrameData {
                 read.read_exact(&mut meta)?;
 
                 let meta: FrameMeta = bincode::options()
-                    .deserialize(&meta)
-                    .context("bincode deserialize")?;
+                    .deserialize::<LegacyFrameMeta>(&meta)
+                    .context("bincode deserialize")?
+                    .into();
 
                 let mut streams_compressed_length = [0_u8; 4];
                 read.read_exact(&mut streams_compressed_length)?;
@@ -716,7 +855,8 @@ impl FrameData {
                 let mut streams_compressed = vec![0_u8; streams_compressed_length];
                 read.read_exact(&mut streams_compressed)?;
 
-                let packed_streams = PackedStreams::new(compression_kind, streams_compressed);
+                let packed_streams =
+                    PackedStreams::new_legacy(compression_kind, streams_compressed);
 
                 // Don't unpack now - do it if/when needed!
 
@@ -735,8 +875,9 @@ impl FrameData {
                 read.read_exact(&mut meta)?;
 
                 let meta: FrameMeta = bincode::options()
-                    .deserialize(&meta)
-                    .context("bincode deserialize")?;
+                    .deserialize::<LegacyFrameMeta>(&meta)
+                    .context("bincode deserialize")?
+                    .into();
 
                 let mut streams_compressed_length = [0_u8; 4];
                 read.read_exact(&mut streams_compressed_length)?;
@@ -747,7 +888,8 @@ impl FrameData {
                 let mut streams_compressed = vec![0_u8; streams_compressed_length];
                 read.read_exact(&mut streams_compressed)?;
 
-                let packed_streams = PackedStreams::new(compression_kind, streams_compressed);
+                let packed_streams =
+                    PackedStreams::new_legacy(compression_kind, streams_compressed);
 
                 // Don't unpack now - do it if/when needed!
 
@@ -757,15 +899,26 @@ impl FrameData {
                     scope_delta: Default::default(),
                     full_delta: false,
                 }))
-            } else if &header == b"PFD4" {
-                // Added 2024-01-08: Split up stream scope details from the record stream.
+            } else if &header == b"PFD4" || &header == b"PFD5" {
+                // PFD4 added 2024-01-08: Split up stream scope details from the record stream.
+                // PFD5: Compact streams with string tables, scopes in progress, dropped scopes,
+                // scope categories, allocations, the clock, thread identity and system metrics.
+                let legacy = &header == b"PFD4";
+
                 let meta_length = read.read_u32::<LE>()? as usize;
                 let meta = {
                     let mut meta = vec![0_u8; meta_length];
                     read.read_exact(&mut meta)?;
-                    bincode::options()
-                        .deserialize(&meta)
-                        .context("bincode deserialize")?
+                    if legacy {
+                        bincode::options()
+                            .deserialize::<LegacyFrameMeta>(&meta)
+                            .context("bincode deserialize")?
+                            .into()
+                    } else {
+                        bincode::options()
+                            .deserialize(&meta)
+                            .context("bincode deserialize")?
+                    }
                 };
 
                 let streams_compressed_length = read.read_u32::<LE>()? as usize;
@@ -773,16 +926,31 @@ impl FrameData {
                 let streams_compressed = {
                     let mut streams_compressed = vec![0_u8; streams_compressed_length];
                     read.read_exact(&mut streams_compressed)?;
-                    PackedStreams::new(compression_kind, streams_compressed)
+                    if legacy {
+                        PackedStreams::new_legacy(compression_kind, streams_compressed)
+                    } else {
+                        PackedStreams::new(compression_kind, streams_compressed)
+                    }
                 };
 
                 let serialized_scope_len = read.read_u32::<LE>()?;
                 let deserialized_scopes: Vec<crate::ScopeDetails> = {
                     let mut serialized_scopes = vec![0; serialized_scope_len as usize];
                     read.read_exact(&mut serialized_scopes)?;
-                    bincode::options()
-                        .deserialize_from(serialized_scopes.as_slice())
-                        .context("Can not deserialize scope details")?
+                    if legacy {
+                        bincode::options()
+                            .deserialize_from::<_, Vec<LegacyScopeDetails>>(
+                                serialized_scopes.as_slice(),
+                            )
+                            .context("Can not deserialize scope details")?
+                            .into_iter()
+                            .map(Into::into)
+                            .collect()
+                    } else {
+                        bincode::options()
+                            .deserialize_from(serialized_scopes.as_slice())
+                            .context("Can not deserialize scope details")?
+                    }
                 };
 
                 let new_scopes: Vec<_> = deserialized_scopes
@@ -813,6 +981,71 @@ impl FrameData {
     }
 }
 
+/// [`FrameMeta`] as serialized before `PFD5`.
+#[cfg(feature = "serialization")]
+#[derive(serde::Deserialize)]
+struct LegacyFrameMeta {
+    frame_index: FrameIndex,
+    range_ns: (NanoSecond, NanoSecond),
+    num_bytes: usize,
+    num_scopes: usize,
+}
+
+#[cfg(feature = "serialization")]
+impl From<LegacyFrameMeta> for FrameMeta {
+    fn from(meta: LegacyFrameMeta) -> Self {
+        let LegacyFrameMeta {
+            frame_index,
+            range_ns,
+            num_bytes,
+            num_scopes,
+        } = meta;
+        Self {
+            frame_index,
+            range_ns,
+            num_bytes,
+            // Streams were never compacted before PFD5.
+            num_bytes_uncompacted: num_bytes,
+            num_scopes,
+            num_dropped_scopes: 0,
+            clock: ClockKind::Unknown,
+            system_metrics: None,
+        }
+    }
+}
+
+/// [`ScopeDetails`] as serialized in `PFD4`, before scopes had categories.
+#[cfg(feature = "serialization")]
+#[derive(serde::Deserialize)]
+struct LegacyScopeDetails {
+    scope_id: Option<crate::ScopeId>,
+    scope_name: Option<std::borrow::Cow<'static, str>>,
+    function_name: std::borrow::Cow<'static, str>,
+    file_path: std::borrow::Cow<'static, str>,
+    line_nr: u32,
+}
+
+#[cfg(feature = "serialization")]
+impl From<LegacyScopeDetails> for ScopeDetails {
+    fn from(scope_details: LegacyScopeDetails) -> Self {
+        let LegacyScopeDetails {
+            scope_id,
+            scope_name,
+            function_name,
+            file_path,
+            line_nr,
+        } = scope_details;
+        Self {
+            scope_id,
+            scope_name,
+            function_name,
+            file_path,
+            line_nr,
+            category: None,
+        }
+    }
+}
+
 // ----------------------------------------------------------------------------
 
 impl FrameData {
@@ -857,3 +1090,54 @@ fn decode_zstd(mut bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
         .context("zstd decompress")?;
     Ok(decoded)
 }
+
+#[cfg(test)]
+#[cfg(feature = "serialization")]
+mod tests {
+    use crate::testing::{assert_frame_shape, ScopeTree};
+
+    /// Recorded with puffin 0.19.1, before the compact stream layout.
+    const PFD4_FILE: &[u8] = include_bytes!("../tests/data/pfd4.puffin");
+
+    #[test]
+    fn read_pfd4() {
+        assert_eq!(&PFD4_FILE[4..8], b"PFD4");
+        let frame_view = crate::FrameView::read(&mut &PFD4_FILE[..]).unwrap();
+        let scopes = frame_view.scope_collection();
+
+        let frames: Vec<_> = frame_view.all_uniq().cloned().collect();
+        assert_eq!(frames.len(), 2);
+        for frame in &frames {
+            assert_eq!(frame.meta().num_scopes, 4);
+            assert_eq!(frame.meta().num_dropped_scopes, 0);
+            assert_eq!(frame.meta().clock, crate::ClockKind::Unknown);
+            assert_eq!(frame.meta().system_metrics, None);
+            assert_frame_shape(frame, scopes, "main", "pfd4::update(physics, render)");
+            assert_frame_shape(frame, scopes, "worker", "load");
+        }
+
+        let threads = ScopeTree::read_frame(&frames[0], scopes);
+        assert_eq!(threads["main"][0].children[0].data, "2 bodies");
+        assert_eq!(threads["worker"][0].data, "level.bin");
+        let unpacked = frames[0].unpacked().unwrap();
+        let main = unpacked
+            .thread_streams
+            .keys()
+            .find(|info| info.name == "main");
+        assert_eq!(main.unwrap().index, None);
+
+        // Written back in the current format:
+        let mut bytes = vec![];
+        frame_view.write(&mut bytes).unwrap();
+        assert_eq!(&bytes[4..8], b"PFD5");
+        let frame_view = crate::FrameView::read(&mut bytes.as_slice()).unwrap();
+        for frame in frame_view.all_uniq() {
+            assert_frame_shape(
+                frame,
+                frame_view.scope_collection(),
+                "main",
+                "pfd4::update(physics, render)",
+            );
+        }
+    }
+}
@@ -1,23 +1,128 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use once_cell::sync::Lazy;
 
 use crate::{
-    fetch_add_scope_id, Error, FrameData, FrameIndex, FrameSinkId, ScopeCollection, ScopeDetails,
-    ScopeId, StreamInfo, StreamInfoRef, ThreadInfo,
+    are_open_scopes_reported, fetch_add_scope_id,
+    profiler::{call_thread_profiler_by_id, ProfilerId},
+    scope_filter::{self, ScopeFilter},
+    system_metrics::SystemMetricsSampler,
+    Error, FrameData, FrameIndex, FrameSinkId, ScopeCollection, ScopeDetails, ScopeId, StreamInfo,
+    StreamInfoRef, ThreadInfo, ThreadProfiler,
 };
 
 /// Add these to [`GlobalProfiler`] with [`GlobalProfiler::add_sink()`].
 pub type FrameSink = Box<dyn Fn(Arc<FrameData>) + Send>;
 
+/// A report from a thread, waiting for the next [`GlobalProfiler::new_frame`].
+pub(crate) struct QueuedReport {
+    info: ThreadInfo,
+    scope_details: Vec<ScopeDetails>,
+    stream_info: StreamInfo,
+}
+
+pub(crate) type ReportReceiver = crossbeam_channel::Receiver<QueuedReport>;
+
+/// Lets threads report to a [`GlobalProfiler`] without locking it.
+#[derive(Clone)]
+pub(crate) struct ReportSender {
+    sender: crossbeam_channel::Sender<QueuedReport>,
+    /// Cleared by [`Self::disconnect`].
+    connected: Arc<AtomicBool>,
+}
+
+impl ReportSender {
+    pub(crate) fn send(
+        &self,
+        info: ThreadInfo,
+        scope_details: &[ScopeDetails],
+        stream_scope_times: &StreamInfoRef<'_>,
+    ) {
+        if !self.is_connected() {
+            return; // Nobody wants the report, so don't bother copying it.
+        }
+        let mut stream_info = StreamInfo::default();
+        stream_info.extend(stream_scope_times);
+        let report = QueuedReport {
+            info,
+            scope_details: scope_details.to_vec(),
+            stream_info,
+        };
+        // Fails if the profiler is gone, in which case nobody wants the report anyway.
+        let _ = self.sender.send(report);
+    }
+
+    /// Drop all reports from now on, e.g. because the [`crate::Profiler`] is gone.
+    pub(crate) fn disconnect(&self) {
+        self.connected.store(false, Ordering::Relaxed);
+    }
+
+    /// Are the reports still wanted? See [`Self::disconnect`].
+    pub(crate) fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+}
+
+pub(crate) fn report_queue() -> (ReportSender, ReportReceiver) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let report_sender = ReportSender {
+        sender,
+        connected: Arc::new(AtomicBool::new(true)),
+    };
+    (report_sender, receiver)
+}
+
+/// Reports from all threads to the singleton, so that they don't need to lock it.
+static GLOBAL_REPORT_QUEUE: Lazy<(ReportSender, ReportReceiver)> = Lazy::new(report_queue);
+
+/// Counts the calls to [`GlobalProfiler::new_frame`] of one profiler,
+/// so that the threads reporting to it can notice that a new frame has started.
+#[derive(Clone, Default)]
+pub(crate) struct NewFrameCounter(Arc<AtomicU64>);
+
+impl NewFrameCounter {
+    pub(crate) fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn increment(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts the new frames of the singleton.
+pub(crate) static GLOBAL_NEW_FRAMES: Lazy<NewFrameCounter> = Lazy::new(Default::default);
+
+type Sinks = Arc<parking_lot::Mutex<HashMap<FrameSinkId, FrameSink>>>;
+
+type SharedSystemMetricsSampler = Arc<parking_lot::Mutex<SystemMetricsSampler>>;
+
 /// Singleton. Collects profiling data from multiple threads
 /// and passes them on to different [`FrameSink`]s.
 pub struct GlobalProfiler {
     current_frame_index: FrameIndex,
     current_frame: BTreeMap<ThreadInfo, StreamInfo>,
+    // Reports that are added to `current_frame` in `new_frame`.
+    reports: ReportReceiver,
+    // Set when owned by a `Profiler`, to find the thread profilers that report to us.
+    profiler_id: Option<ProfilerId>,
+    // Counts our calls to `new_frame`, for the thread profilers that report to us.
+    new_frames: NewFrameCounter,
 
     next_sink_id: FrameSinkId,
-    sinks: std::collections::HashMap<FrameSinkId, FrameSink>,
+    // Shared with the dispatcher thread, if any.
+    sinks: Sinks,
+    // Builds the frames and calls the sinks, see `set_background_dispatch`.
+    dispatcher: Option<Dispatcher>,
+    // Samples `FrameMeta::system_metrics`, see `set_system_metrics`.
+    // Shared with the dispatcher thread, if any.
+    system_metrics: Option<SharedSystemMetricsSampler>,
     // When true will propagate a full snapshot from `scope_collection` to every sink.
     propagate_all_scope_details: bool,
     // The new scopes' details, or also the first time macro or external library detected a scope.
@@ -32,8 +137,13 @@ impl Default for GlobalProfiler {
         Self {
             current_frame_index: 0,
             current_frame: Default::default(),
+            reports: crossbeam_channel::never(),
+            profiler_id: None,
+            new_frames: Default::default(),
             next_sink_id: FrameSinkId(1),
             sinks: Default::default(),
+            dispatcher: None,
+            system_metrics: None,
             propagate_all_scope_details: Default::default(),
             new_scopes: Default::default(),
             scope_collection: Default::default(),
@@ -44,18 +154,55 @@ impl Default for GlobalProfiler {
 impl GlobalProfiler {
     /// Access to the global profiler singleton.
     pub fn lock() -> parking_lot::MutexGuard<'static, Self> {
-        static GLOBAL_PROFILER: Lazy<parking_lot::Mutex<GlobalProfiler>> =
-            Lazy::new(Default::default);
+        static GLOBAL_PROFILER: Lazy<parking_lot::Mutex<GlobalProfiler>> = Lazy::new(|| {
+            parking_lot::Mutex::new(GlobalProfiler::with_reports(
+                GLOBAL_REPORT_QUEUE.1.clone(),
+                None,
+                GLOBAL_NEW_FRAMES.clone(),
+            ))
+        });
         GLOBAL_PROFILER.lock()
     }
 
+    /// A profiler that also collects the reports sent to `reports` in [`Self::new_frame`].
+    ///
+    /// `profiler_id` is set for the profiler of a [`crate::Profiler`], and `None` for the singleton.
+    /// `new_frames` is shared with the [`ThreadProfiler`]s that report to it.
+    pub(crate) fn with_reports(
+        reports: ReportReceiver,
+        profiler_id: Option<ProfilerId>,
+        new_frames: NewFrameCounter,
+    ) -> Self {
+        Self {
+            reports,
+            profiler_id,
+            new_frames,
+            ..Default::default()
+        }
+    }
+
     /// You need to call this once at the start of every frame.
     ///
     /// It is fine to call this from within a profile scope.
     ///
     /// This takes all completed profiling scopes from all threads,
     /// and sends it to the sinks.
+    ///
+    /// With [`Self::set_background_dispatch`] the frame is built and sent to the sinks
+    /// on a background thread instead.
+    ///
+    /// With [`crate::set_report_open_scopes`] the scopes that are still open on the calling thread
+    /// are added to the frame as in progress.
+    /// Other threads report theirs later, see [`crate::set_report_open_scopes`].
+    /// Reporting them calls the [`ThreadProfiler`]'s reporter, which must then not lock the [`GlobalProfiler`].
     pub fn new_frame(&mut self) {
+        self.new_frames.increment();
+        if are_open_scopes_reported() {
+            // Other threads do this once they notice the new frame.
+            self.report_open_scopes_of_this_thread();
+        }
+        self.drain_queued_reports();
+
         let current_frame_index = self.current_frame_index;
         self.current_frame_index += 1;
 
@@ -76,23 +223,17 @@ impl GlobalProfiler {
             scope_deltas.extend(self.scope_collection.scopes_by_id().values().cloned());
         }
 
-        let new_frame = match FrameData::new(
-            current_frame_index,
-            current_frame_scope,
-            scope_deltas,
-            propagate_full_delta,
-        ) {
-            Ok(new_frame) => Arc::new(new_frame),
-            Err(Error::Empty) => {
-                return; // don't warn about empty frames, just ignore them
-            }
-            Err(err) => {
-                eprintln!("puffin ERROR: Bad frame: {err:?}");
-                return;
-            }
-        };
+        for delta in &scope_deltas {
+            self.scope_collection.insert(delta.clone());
+        }
 
-        self.add_frame(new_frame);
+        self.dispatch(DispatchJob::NewFrame {
+            frame_index: current_frame_index,
+            thread_streams: current_frame_scope,
+            scope_delta: scope_deltas,
+            full_delta: propagate_full_delta,
+            system_metrics_sampler: self.system_metrics.clone(),
+        });
     }
 
     /// Manually add frame data.
@@ -101,11 +242,73 @@ impl GlobalProfiler {
             self.scope_collection.insert(delta.clone());
         }
 
-        for sink in self.sinks.values() {
-            sink(new_frame.clone());
+        self.dispatch(DispatchJob::AddFrame(new_frame));
+    }
+
+    /// Build frames and call the [`FrameSink`]s on a background thread called `puffin_dispatcher`,
+    /// so that [`Self::new_frame`] only needs to hand over the collected scopes.
+    ///
+    /// This moves the cost of compacting and packing the frames
+    /// (e.g. in [`crate::FrameView::add_frame`]) off the calling thread.
+    /// The sinks still see the frames in order.
+    ///
+    /// Turning it off waits for the background thread to finish all frames it was given.
+    /// Since the sinks may then still be called, they must not lock the [`GlobalProfiler`].
+    ///
+    /// This is off by default, and stays off on platforms without threads, e.g. the web.
+    pub fn set_background_dispatch(&mut self, enabled: bool) {
+        if !enabled {
+            self.dispatcher = None;
+        } else if self.dispatcher.is_none() {
+            match Dispatcher::start(self.sinks.clone()) {
+                Ok(dispatcher) => self.dispatcher = Some(dispatcher),
+                Err(err) => eprintln!("puffin ERROR: Failed to start dispatcher thread: {err}"),
+            }
+        }
+    }
+
+    /// Are frames built and sent to the sinks on a background thread?
+    ///
+    /// See [`Self::set_background_dispatch`].
+    pub fn background_dispatch(&self) -> bool {
+        self.dispatcher.is_some()
+    }
+
+    /// Sample what the process uses in each [`Self::new_frame`], and record it in
+    /// [`crate::FrameMeta::system_metrics`]: CPU time, resident memory, page faults and context switches.
+    ///
+    /// The first frame after turning this on has no metrics, since there is nothing to compare them to.
+    ///
+    /// This reads `/proc/self/stat` and `/proc/self/status`, so it only records anything on Linux and Android.
+    /// It is read while building the frame, so with [`Self::set_background_dispatch`] it happens
+    /// on the background thread instead of while the profiler is locked.
+    /// Off by default.
+    pub fn set_system_metrics(&mut self, enabled: bool) {
+        if !enabled {
+            self.system_metrics = None;
+        } else if self.system_metrics.is_none() {
+            self.system_metrics = Some(Default::default());
         }
     }
 
+    /// Are system metrics sampled in each frame?
+    ///
+    /// See [`Self::set_system_metrics`].
+    pub fn system_metrics(&self) -> bool {
+        self.system_metrics.is_some()
+    }
+
+    fn dispatch(&self, job: DispatchJob) {
+        let job = match &self.dispatcher {
+            Some(dispatcher) => match dispatcher.send(job) {
+                Ok(()) => return,
+                Err(job) => job, // The thread is gone (a sink panicked?), so do it ourselves.
+            },
+            None => job,
+        };
+        job.run(&self.sinks);
+    }
+
     /// Inserts user scopes into puffin.
     /// Returns the scope id for every inserted scope in the same order as input slice.
     ///
@@ -145,6 +348,43 @@ impl GlobalProfiler {
             .extend(stream_scope_times);
     }
 
+    /// Reports some profiling data without locking the [`GlobalProfiler`].
+    /// Called from [`crate::internal_profile_reporter`].
+    ///
+    /// The data is queued up, and added to the current frame in the next call to [`Self::new_frame`].
+    pub fn enqueue_report(
+        info: ThreadInfo,
+        scope_details: &[ScopeDetails],
+        stream_scope_times: &StreamInfoRef<'_>,
+    ) {
+        GLOBAL_REPORT_QUEUE
+            .0
+            .send(info, scope_details, stream_scope_times);
+    }
+
+    fn report_open_scopes_of_this_thread(&self) {
+        if let Some(profiler_id) = self.profiler_id {
+            call_thread_profiler_by_id(profiler_id, |tp| tp.report_open_scopes_on_new_frame());
+        } else if self.reports.same_channel(&GLOBAL_REPORT_QUEUE.1) {
+            ThreadProfiler::call(|tp| tp.report_open_scopes_on_new_frame());
+        }
+    }
+
+    /// Move everything reported with [`Self::enqueue_report`] (or to a [`crate::Profiler`]) into the current frame.
+    fn drain_queued_reports(&mut self) {
+        for report in self.reports.try_iter() {
+            self.new_scopes
+                .extend(report.scope_details.into_iter().map(Arc::new));
+
+            let stream_info = self.current_frame.entry(report.info).or_default();
+            if stream_info.stream.is_empty() {
+                *stream_info = report.stream_info; // No need to copy
+            } else {
+                stream_info.extend(&report.stream_info.as_stream_into_ref());
+            }
+        }
+    }
+
     /// Reports user scopes to puffin profiler.
     /// Every scope reported should first be registered by [`Self::register_user_scopes`].
     pub fn report_user_scopes(&mut self, info: ThreadInfo, stream_scope_times: &StreamInfoRef<'_>) {
@@ -161,13 +401,13 @@ impl GlobalProfiler {
     pub fn add_sink(&mut self, sink: FrameSink) -> FrameSinkId {
         let id = self.next_sink_id;
         self.next_sink_id.0 += 1;
-        self.sinks.insert(id, sink);
+        self.sinks.lock().insert(id, sink);
         id
     }
 
     /// Removes a sink from the global profiler.
     pub fn remove_sink(&mut self, id: FrameSinkId) -> Option<FrameSink> {
-        self.sinks.remove(&id)
+        self.sinks.lock().remove(&id)
     }
 
     /// Sends a snapshot of all scopes to all sinks via the frame data.
@@ -175,4 +415,176 @@ impl GlobalProfiler {
     pub fn emit_scope_snapshot(&mut self) {
         self.propagate_all_scope_details = true;
     }
+
+    /// Turn the scopes selected by `filter` on or off, e.g. to only profile the renderer.
+    ///
+    /// Scopes are on unless turned off by a filter, and later calls take precedence,
+    /// so you can turn off a whole crate with [`ScopeFilter::FunctionPrefix`] and then turn on some of its scopes.
+    /// Calling this again with the same filter replaces it.
+    ///
+    /// This applies to all scopes of the process, including those of each [`crate::Profiler`].
+    /// Scopes that are turned off cost as little as when [`crate::are_scopes_on`] is `false`.
+    /// This doesn't affect scopes reported with [`Self::report_user_scopes`].
+    pub fn set_scopes_enabled(filter: ScopeFilter, enabled: bool) {
+        scope_filter::set_scopes_enabled(filter, enabled);
+    }
+
+    /// Remove a filter added with [`Self::set_scopes_enabled`].
+    pub fn remove_scope_filter(filter: &ScopeFilter) {
+        scope_filter::remove_scope_filter(filter);
+    }
+
+    /// Remove all filters added with [`Self::set_scopes_enabled`], turning all scopes back on.
+    pub fn clear_scope_filters() {
+        scope_filter::clear_scope_filters();
+    }
+
+    /// The filters added with [`Self::set_scopes_enabled`], and whether they turn scopes on, in order.
+    pub fn scope_filters() -> Vec<(ScopeFilter, bool)> {
+        scope_filter::scope_filters()
+    }
+
+    /// Is the given scope turned on by the filters of [`Self::set_scopes_enabled`]?
+    pub fn is_scope_enabled(scope_details: &ScopeDetails) -> bool {
+        scope_filter::is_scope_enabled(scope_details)
+    }
+}
+
+/// Work for the sinks, done either by [`GlobalProfiler::new_frame`] or the [`Dispatcher`].
+enum DispatchJob {
+    NewFrame {
+        frame_index: FrameIndex,
+        thread_streams: BTreeMap<ThreadInfo, StreamInfo>,
+        scope_delta: Vec<Arc<ScopeDetails>>,
+        full_delta: bool,
+        system_metrics_sampler: Option<SharedSystemMetricsSampler>,
+    },
+    AddFrame(Arc<FrameData>),
+}
+
+impl DispatchJob {
+    fn run(self, sinks: &Sinks) {
+        let new_frame = match self {
+            Self::NewFrame {
+                frame_index,
+                thread_streams,
+                scope_delta,
+                full_delta,
+                system_metrics_sampler,
+            } => match FrameData::with_system_metrics(
+                frame_index,
+                thread_streams,
+                scope_delta,
+                full_delta,
+                system_metrics_sampler.and_then(|sampler| sampler.lock().sample()),
+            ) {
+                Ok(new_frame) => Arc::new(new_frame),
+                Err(Error::Empty) => {
+                    return; // don't warn about empty frames, just ignore them
+                }
+                Err(err) => {
+                    eprintln!("puffin ERROR: Bad frame: {err:?}");
+                    return;
+                }
+            },
+            Self::AddFrame(new_frame) => new_frame,
+        };
+
+        for sink in sinks.lock().values() {
+            sink(new_frame.clone());
+        }
+    }
+}
+
+/// A background thread running [`DispatchJob`]s in order.
+///
+/// Dropping it waits for all jobs to finish.
+struct Dispatcher {
+    jobs: Option<crossbeam_channel::Sender<DispatchJob>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Dispatcher {
+    fn start(sinks: Sinks) -> std::io::Result<Self> {
+        let (jobs, job_rx) = crossbeam_channel::unbounded::<DispatchJob>();
+        let thread = std::thread::Builder::new()
+            .name("puffin_dispatcher".to_owned())
+            .spawn(move || {
+                for job in job_rx {
+                    job.run(&sinks);
+                }
+            })?;
+        Ok(Self {
+            jobs: Some(jobs),
+            thread: Some(thread),
+        })
+    }
+
+    /// Gives the job back if the thread is gone.
+    fn send(&self, job: DispatchJob) -> Result<(), DispatchJob> {
+        match &self.jobs {
+            Some(jobs) => jobs.send(job).map_err(|err| err.into_inner()),
+            None => Err(job),
+        }
+    }
+}
+
+impl Drop for Dispatcher {
+    fn drop(&mut self) {
+        self.jobs = None; // Lets the thread finish the remaining jobs and then stop.
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                eprintln!("puffin ERROR: The dispatcher thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{FrameData, FrameIndex, GlobalProfiler, ScopeId, Stream, StreamInfo, ThreadInfo};
+
+    fn test_frame(frame_index: FrameIndex) -> FrameData {
+        let mut stream = Stream::default();
+        let (start, _) = stream.begin_scope(|| 100, ScopeId::new(1), "");
+        stream.end_scope(start, 200);
+        let thread_info = ThreadInfo {
+            name: "main".to_owned(),
+            ..Default::default()
+        };
+        let thread_streams = [(thread_info, StreamInfo::parse(stream).unwrap())].into();
+        FrameData::new(frame_index, thread_streams, vec![], false).unwrap()
+    }
+
+    #[test]
+    fn background_dispatch() {
+        let mut profiler = GlobalProfiler::default();
+        profiler.set_background_dispatch(true);
+        assert!(profiler.background_dispatch());
+
+        let seen = Arc::new(parking_lot::Mutex::new(vec![]));
+        profiler.add_sink(Box::new({
+            let seen = seen.clone();
+            move |frame| {
+                let thread = std::thread::current();
+                seen.lock()
+                    .push((frame.frame_index(), thread.name().unwrap().to_owned()));
+            }
+        }));
+
+        for frame_index in 0..3 {
+            profiler.add_frame(Arc::new(test_frame(frame_index)));
+        }
+        profiler.set_background_dispatch(false);
+        assert!(!profiler.background_dispatch());
+
+        let seen = seen.lock();
+        assert_eq!(seen.len(), 3);
+        for (frame_index, (seen_index, thread_name)) in seen.iter().enumerate() {
+            assert_eq!(*seen_index, frame_index as u64);
+            assert_eq!(thread_name, "puffin_dispatcher");
+        }
+    }
 }
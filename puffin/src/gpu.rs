@@ -0,0 +1,368 @@
+//! Profile work on the GPU, with any graphics API that has timestamp queries.
+//!
+//! A [`GpuTimeline`] is a synthetic thread for a GPU queue. Each scope gets a pair of query
+//! indices from [`GpuTimeline::begin_scope`]: have the GPU write a timestamp to the first one at
+//! the start of the work, and to the second one at the end. Once a frame worth of commands has
+//! been recorded, [`GpuTimeline::finish`] hands out the [`GpuQueries`], which are resolved with the
+//! timestamps when the GPU is done, and reported to the [`GlobalProfiler`] as their own thread.
+//!
+//! GPU timestamps count from some other point in time than the profiler clock,
+//! so they are moved onto the profiler's time line with a [`GpuCalibration`].
+//!
+//! ```
+//! use puffin::{GlobalProfiler, ScopeDetails};
+//! use puffin::gpu::{GpuTimeline, MockGpu};
+//!
+//! let scope_ids = GlobalProfiler::lock().register_user_scopes(&[
+//!     ScopeDetails::from_scope_name("render"),
+//!     ScopeDetails::from_scope_name("shadows"),
+//! ]);
+//!
+//! let mut gpu = MockGpu::new(0);
+//! let mut timeline = GpuTimeline::new("GPU");
+//!
+//! let render = timeline.begin_scope(scope_ids[0], "");
+//! gpu.write_timestamp(render.begin_query);
+//! let shadows = timeline.begin_scope(scope_ids[1], "");
+//! gpu.write_timestamp(shadows.begin_query);
+//! gpu.execute(1_000);
+//! gpu.write_timestamp(shadows.end_query);
+//! timeline.end_scope(shadows);
+//! gpu.write_timestamp(render.end_query);
+//! timeline.end_scope(render);
+//!
+//! // Later, once the GPU is done:
+//! let queries = timeline.finish();
+//! let timestamps = gpu.read_timestamps(queries.num_queries()).unwrap();
+//! queries
+//!     .report(&mut GlobalProfiler::lock(), &timestamps, gpu.calibration())
+//!     .unwrap();
+//! ```
+
+use crate::{Error, GlobalProfiler, NanoSecond, Result, ScopeId, Stream, StreamInfo, ThreadInfo};
+
+/// Relates the GPU clock to the profiler clock, from a reading of both at the same moment.
+///
+/// Graphics APIs offer this as e.g. `vkGetCalibratedTimestampsEXT`
+/// or `ID3D12CommandQueue::GetClockCalibration`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GpuCalibration {
+    /// A GPU timestamp, in nanoseconds.
+    pub gpu_ns: NanoSecond,
+
+    /// The profiler time ([`crate::now_ns`]) at the same moment.
+    pub cpu_ns: NanoSecond,
+}
+
+impl GpuCalibration {
+    /// Pairs this GPU timestamp with the current profiler time.
+    pub fn now(gpu_ns: NanoSecond) -> Self {
+        Self {
+            gpu_ns,
+            cpu_ns: crate::now_ns(),
+        }
+    }
+
+    /// Converts a GPU timestamp to profiler time.
+    #[inline]
+    pub fn to_cpu_ns(self, gpu_ns: NanoSecond) -> NanoSecond {
+        gpu_ns - self.gpu_ns + self.cpu_ns
+    }
+}
+
+/// The timestamp queries of a GPU scope, handed out by [`GpuTimeline::begin_scope`].
+#[must_use = "Give the scope back to GpuTimeline::end_scope"]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GpuScope {
+    /// Write the timestamp for the start of the scope to this query.
+    pub begin_query: u32,
+
+    /// Write the timestamp for the end of the scope to this query.
+    pub end_query: u32,
+}
+
+#[derive(Clone, Debug)]
+enum GpuEvent {
+    Begin {
+        scope_id: ScopeId,
+        data: String,
+        query: u32,
+    },
+    End {
+        query: u32,
+    },
+}
+
+/// A synthetic thread for the work of a GPU queue, shown next to the CPU threads.
+///
+/// Scopes must be registered with [`GlobalProfiler::register_user_scopes`],
+/// and nest like the CPU scopes: each [`Self::end_scope`] ends the last scope that was begun.
+pub struct GpuTimeline {
+    thread_info: ThreadInfo,
+    events: Vec<GpuEvent>,
+    num_open_scopes: usize,
+    num_queries: u32,
+}
+
+impl GpuTimeline {
+    /// A timeline shown as a thread with this name, e.g. `"GPU"` or `"GPU: compute queue"`.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            thread_info: ThreadInfo {
+                name: name.into(),
+                ..Default::default()
+            },
+            events: Vec::new(),
+            num_open_scopes: 0,
+            num_queries: 0,
+        }
+    }
+
+    /// The thread the scopes are reported as.
+    pub fn thread_info(&self) -> &ThreadInfo {
+        &self.thread_info
+    }
+
+    /// The number of queries handed out since the last [`Self::finish`].
+    pub fn num_queries(&self) -> u32 {
+        self.num_queries
+    }
+
+    /// Begin a GPU scope, and get the queries to write its timestamps to.
+    ///
+    /// The queries are counted from zero after each [`Self::finish`],
+    /// so use a separate query set for each frame the GPU can be behind.
+    pub fn begin_scope(&mut self, scope_id: ScopeId, data: &str) -> GpuScope {
+        let scope = GpuScope {
+            begin_query: self.num_queries,
+            end_query: self.num_queries + 1,
+        };
+        self.num_queries += 2;
+        self.num_open_scopes += 1;
+        self.events.push(GpuEvent::Begin {
+            scope_id,
+            data: data.to_owned(),
+            query: scope.begin_query,
+        });
+        scope
+    }
+
+    /// End the last scope that was begun.
+    pub fn end_scope(&mut self, scope: GpuScope) {
+        debug_assert!(self.num_open_scopes > 0, "GPU scope ended twice");
+        self.num_open_scopes = self.num_open_scopes.saturating_sub(1);
+        self.events.push(GpuEvent::End {
+            query: scope.end_query,
+        });
+    }
+
+    /// Take the scopes recorded so far, to be resolved once the GPU has written their timestamps.
+    ///
+    /// Should be called once per frame, when no scope is open.
+    pub fn finish(&mut self) -> GpuQueries {
+        debug_assert_eq!(self.num_open_scopes, 0, "GPU scope was never ended");
+        self.num_open_scopes = 0;
+        GpuQueries {
+            thread_info: self.thread_info.clone(),
+            events: std::mem::take(&mut self.events),
+            num_queries: std::mem::take(&mut self.num_queries),
+        }
+    }
+}
+
+/// The scopes of a [`GpuTimeline`], waiting for the GPU to write their timestamps.
+pub struct GpuQueries {
+    thread_info: ThreadInfo,
+    events: Vec<GpuEvent>,
+    num_queries: u32,
+}
+
+impl GpuQueries {
+    /// The thread the scopes are reported as.
+    pub fn thread_info(&self) -> &ThreadInfo {
+        &self.thread_info
+    }
+
+    /// The number of timestamps needed by [`Self::resolve`].
+    pub fn num_queries(&self) -> u32 {
+        self.num_queries
+    }
+
+    /// Build the scopes, from the GPU timestamps of each query in nanoseconds.
+    ///
+    /// Returns [`Error::PrematureEnd`] if there are fewer than [`Self::num_queries`] timestamps.
+    pub fn resolve(
+        &self,
+        gpu_timestamps_ns: &[NanoSecond],
+        calibration: GpuCalibration,
+    ) -> Result<StreamInfo> {
+        if gpu_timestamps_ns.len() < self.num_queries as usize {
+            return Err(Error::PrematureEnd);
+        }
+        let time_ns = |query: u32| calibration.to_cpu_ns(gpu_timestamps_ns[query as usize]);
+
+        let mut stream = Stream::default();
+        let mut open_scopes = Vec::new();
+        for event in &self.events {
+            match event {
+                GpuEvent::Begin {
+                    scope_id,
+                    data,
+                    query,
+                } => {
+                    let (offset, _) = stream.begin_scope(|| time_ns(*query), *scope_id, data);
+                    open_scopes.push(offset);
+                }
+                GpuEvent::End { query } => {
+                    let offset = open_scopes.pop().ok_or(Error::InvalidStream)?;
+                    stream.end_scope(offset, time_ns(*query));
+                }
+            }
+        }
+        if !open_scopes.is_empty() {
+            return Err(Error::ScopeNeverEnded);
+        }
+        StreamInfo::parse(stream)
+    }
+
+    /// [`Self::resolve`] the scopes, and add them to the current frame of the profiler.
+    ///
+    /// The GPU is usually a frame or two behind, so the scopes end up in a later frame
+    /// than the CPU scopes that recorded the work.
+    pub fn report(
+        &self,
+        profiler: &mut GlobalProfiler,
+        gpu_timestamps_ns: &[NanoSecond],
+        calibration: GpuCalibration,
+    ) -> Result<()> {
+        let stream_info = self.resolve(gpu_timestamps_ns, calibration)?;
+        profiler.report_user_scopes(self.thread_info.clone(), &stream_info.as_stream_into_ref());
+        Ok(())
+    }
+}
+
+/// A software stand-in for a GPU with timestamp queries, for testing without a graphics API.
+///
+/// It has its own clock, which only moves when told to with [`Self::execute`].
+pub struct MockGpu {
+    now_ns: NanoSecond,
+    timestamps: Vec<Option<NanoSecond>>,
+}
+
+impl MockGpu {
+    /// A GPU whose clock starts at `start_ns`.
+    pub fn new(start_ns: NanoSecond) -> Self {
+        Self {
+            now_ns: start_ns,
+            timestamps: Vec::new(),
+        }
+    }
+
+    /// The current GPU time.
+    pub fn now_ns(&self) -> NanoSecond {
+        self.now_ns
+    }
+
+    /// Do some work, which takes this long.
+    pub fn execute(&mut self, duration_ns: NanoSecond) {
+        self.now_ns += duration_ns;
+    }
+
+    /// Write the current GPU time to this query.
+    pub fn write_timestamp(&mut self, query: u32) {
+        let query = query as usize;
+        if self.timestamps.len() <= query {
+            self.timestamps.resize(query + 1, None);
+        }
+        self.timestamps[query] = Some(self.now_ns);
+    }
+
+    /// Read back the first `num_queries` timestamps, and reset them for the next frame.
+    ///
+    /// `None` if any of them was never written, like a GPU that is not done yet.
+    pub fn read_timestamps(&mut self, num_queries: u32) -> Option<Vec<NanoSecond>> {
+        let timestamps = self
+            .timestamps
+            .get(..num_queries as usize)?
+            .iter()
+            .copied()
+            .collect::<Option<Vec<_>>>()?;
+        self.timestamps.clear();
+        Some(timestamps)
+    }
+
+    /// Pairs the current GPU time with the current profiler time.
+    pub fn calibration(&self) -> GpuCalibration {
+        GpuCalibration::now(self.now_ns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Reader;
+
+    #[test]
+    fn resolve_nested_scopes() {
+        let mut gpu = MockGpu::new(500);
+        let mut timeline = GpuTimeline::new("GPU");
+
+        let outer = timeline.begin_scope(ScopeId::new(1), "pass");
+        gpu.write_timestamp(outer.begin_query);
+        gpu.execute(10);
+        let inner = timeline.begin_scope(ScopeId::new(2), "");
+        gpu.write_timestamp(inner.begin_query);
+        gpu.execute(30);
+        gpu.write_timestamp(inner.end_query);
+        timeline.end_scope(inner);
+        gpu.execute(5);
+        gpu.write_timestamp(outer.end_query);
+        timeline.end_scope(outer);
+
+        let queries = timeline.finish();
+        assert_eq!(queries.num_queries(), 4);
+        assert_eq!(timeline.num_queries(), 0);
+
+        let timestamps = gpu.read_timestamps(queries.num_queries()).unwrap();
+        let calibration = GpuCalibration {
+            gpu_ns: 500,
+            cpu_ns: 10_000,
+        };
+        let stream_info = queries.resolve(&timestamps, calibration).unwrap();
+        assert_eq!(stream_info.range_ns, (10_000, 10_045));
+
+        let top = Reader::from_start(&stream_info.stream)
+            .read_top_scopes()
+            .unwrap();
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].id, ScopeId::new(1));
+        assert_eq!(top[0].record.data, "pass");
+        assert_eq!(top[0].record.duration_ns, 45);
+
+        let children = Reader::with_parent(&stream_info.stream, &top[0])
+            .unwrap()
+            .read_top_scopes()
+            .unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].id, ScopeId::new(2));
+        assert_eq!(children[0].record.start_ns, 10_010);
+        assert_eq!(children[0].record.duration_ns, 30);
+    }
+
+    #[test]
+    fn missing_timestamps() {
+        let mut gpu = MockGpu::new(0);
+        let mut timeline = GpuTimeline::new("GPU");
+        let scope = timeline.begin_scope(ScopeId::new(1), "");
+        gpu.write_timestamp(scope.begin_query);
+        timeline.end_scope(scope);
+        let queries = timeline.finish();
+
+        // The GPU never got to the end of the scope:
+        assert_eq!(gpu.read_timestamps(queries.num_queries()), None);
+        assert!(matches!(
+            queries.resolve(&[0], GpuCalibration::default()),
+            Err(Error::PrematureEnd)
+        ));
+    }
+}
@@ -19,29 +19,62 @@
 //! # fn slow_code(){}
 //! ```
 
-#![forbid(unsafe_code)]
+#![cfg_attr(not(feature = "alloc-tracking"), forbid(unsafe_code))]
+#![cfg_attr(feature = "alloc-tracking", deny(unsafe_code))] // Only for `TrackingAllocator`.
 #![deny(missing_docs)]
 
+mod allocations;
+mod clock;
 mod data;
 mod frame_data;
 mod global_profiler;
+pub mod gpu;
 mod merge;
 mod profile_view;
+mod profiler;
 mod scope_details;
+mod scope_filter;
+pub mod sync;
+mod system_metrics;
+pub mod testing;
 mod thread_profiler;
 mod utils;
 
 use std::num::NonZeroU32;
 use std::sync::atomic::{AtomicBool, Ordering};
 
+pub use allocations::Allocations;
+#[cfg(feature = "alloc-tracking")]
+pub use allocations::TrackingAllocator;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use clock::MonotonicRawClock;
+#[cfg(all(feature = "quanta", not(target_arch = "wasm32")))]
+pub use clock::TscClock;
+pub use clock::{
+    clock_kind, set_clock, Clock, ClockAlreadySet, ClockKind, InstantClock, MockClock,
+};
 /// TODO: Improve encapsulation.
 pub use data::{Error, Reader, Result, Scope, ScopeRecord, Stream, StreamInfo, StreamInfoRef};
 pub use frame_data::{FrameData, FrameMeta, UnpackedFrameData};
 pub use global_profiler::{FrameSink, GlobalProfiler};
 pub use merge::{merge_scopes_for_thread, MergeScope};
 pub use profile_view::{select_slowest, FrameStats, FrameView, GlobalFrameView};
+pub use profiler::Profiler;
+#[cfg(feature = "macros")]
+pub use puffin_macros::profile;
+
+#[cfg(feature = "serialization")]
+#[cfg(not(target_arch = "wasm32"))]
+pub use profile_view::FrameWriter;
 pub use scope_details::{ScopeCollection, ScopeDetails, ScopeType};
-pub use thread_profiler::{internal_profile_reporter, ThreadInfo, ThreadProfiler};
+pub use scope_filter::{ScopeFilter, ScopeSwitch};
+pub use system_metrics::SystemMetrics;
+pub use thread_profiler::{
+    are_open_scopes_reported, default_thread_memory_limit, internal_profile_reporter,
+    set_default_thread_memory_limit, set_report_open_scopes, set_thread_group,
+    set_thread_memory_limit, set_thread_name, set_thread_sort_key, ThreadInfo, ThreadMemoryLimit,
+    ThreadMemoryPolicy, ThreadProfiler,
+};
 pub use utils::{clean_function_name, short_file_name, shorten_rust_function_name, type_name_of};
 
 static MACROS_ON: AtomicBool = AtomicBool::new(false);
@@ -84,44 +117,11 @@ fn fetch_add_scope_id() -> ScopeId {
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub struct FrameSinkId(u64);
 
-/// Returns a high-precision, monotonically increasing nanosecond count since unix epoch.
-#[inline]
-#[cfg(any(not(target_arch = "wasm32"), feature = "web"))]
-pub fn now_ns() -> NanoSecond {
-    #[cfg(target_arch = "wasm32")]
-    fn nanos_since_epoch() -> NanoSecond {
-        (js_sys::Date::new_0().get_time() * 1e6) as _
-    }
-
-    #[cfg(not(target_arch = "wasm32"))]
-    fn nanos_since_epoch() -> NanoSecond {
-        if let Ok(duration_since_epoch) = std::time::UNIX_EPOCH.elapsed() {
-            duration_since_epoch.as_nanos() as NanoSecond
-        } else {
-            0 // system time is set before 1970. this should be quite rare.
-        }
-    }
-
-    // This can maybe be optimized
-
-    #[cfg(all(not(feature="quanta"), not(target_arch = "wasm32")))]
-    use std::time::Instant;
-    #[cfg(all(feature="quanta", not(target_arch = "wasm32")))]
-    use quanta::Instant;
-    #[cfg(target_arch = "wasm32")]
-    use web_time::Instant;
-
-    static START_TIME: once_cell::sync::Lazy<(NanoSecond, Instant)> =
-        once_cell::sync::Lazy::new(|| (nanos_since_epoch(), Instant::now()));
-    START_TIME.0 + START_TIME.1.elapsed().as_nanos() as NanoSecond
-}
-
-/// Should not be used.
+/// Returns a high-precision, monotonically increasing nanosecond count since unix epoch,
+/// from the [`Clock`] of the profiler (see [`set_clock`]).
 #[inline]
-#[cfg(all(target_arch = "wasm32", not(feature = "web")))]
 pub fn now_ns() -> NanoSecond {
-    // This should be unused.
-    panic!("Wasm without the `web` feature requires passing a custom source of time via `ThreadProfiler::initialize`");
+    clock::clock().now_ns()
 }
 
 // We currently store an Option<ProfilerScope> on the stack (None when profiling is off).
@@ -130,6 +130,9 @@ pub fn now_ns() -> NanoSecond {
 pub struct ProfilerScope {
     start_stream_offset: usize,
 
+    /// `None` for the [`GlobalProfiler`].
+    profiler: Option<profiler::ProfilerId>,
+
     /// Prevent the scope from being sent between threads.
     /// The scope must start/stop on the same thread.
     /// In particular, we do NOT want this to migrate threads in some async code.
@@ -140,19 +143,85 @@ pub struct ProfilerScope {
 impl ProfilerScope {
     /// The scope id identifies which scopes' time is being reported.
     /// `data` can be changing, i.e. a name of a mesh or a texture.
+    /// Wrap it in [`Interned`] if it often repeats.
     #[inline]
-    pub fn new(scope_id: ScopeId, data: impl AsRef<str>) -> Self {
+    pub fn new(scope_id: ScopeId, data: impl ScopeData) -> Self {
         Self {
-            start_stream_offset: ThreadProfiler::call(|tp| tp.begin_scope(scope_id, data.as_ref())),
+            start_stream_offset: ThreadProfiler::call(|tp| data.begin_scope(tp, scope_id)),
+            profiler: None,
+            _dont_send_me: Default::default(),
+        }
+    }
+
+    /// Like [`Self::new`], but for the given [`Profiler`] instead of the [`GlobalProfiler`].
+    ///
+    /// The scope details are sent to each profiler the first time it sees the scope.
+    #[inline]
+    pub fn new_in(profiler: &Profiler, scope_details: &ScopeDetails, data: impl ScopeData) -> Self {
+        Self {
+            start_stream_offset: profiler.call_thread_profiler(|tp| {
+                let scope_id = tp.register_scope_once(scope_details);
+                data.begin_scope(tp, scope_id)
+            }),
+            profiler: Some(profiler.id()),
             _dont_send_me: Default::default(),
         }
     }
 }
 
+/// Scope data that is stored once per frame and thread, no matter how many scopes use it.
+///
+/// Use this for data that repeats a lot, like the names of assets or systems.
+/// Each scope then only stores a 4 byte index instead of the whole string,
+/// which saves memory and bandwidth, at the cost of a hash map lookup when the scope begins:
+///
+/// ```
+/// # let asset_name = "tree.png";
+/// puffin::profile_scope!("load_asset", puffin::Interned(asset_name));
+/// ```
+///
+/// Don't use it for data that is different each time, like a counter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Interned<'a>(pub &'a str);
+
+/// The `data` of a profile scope: any string, or an [`Interned`] one.
+pub trait ScopeData {
+    /// Begin a scope with this data on the given thread.
+    ///
+    /// Returns the position where to write the scope size once the scope is closed.
+    fn begin_scope(&self, tp: &mut ThreadProfiler, scope_id: ScopeId) -> usize;
+}
+
+impl<T: AsRef<str> + ?Sized> ScopeData for T {
+    #[inline]
+    fn begin_scope(&self, tp: &mut ThreadProfiler, scope_id: ScopeId) -> usize {
+        tp.begin_scope(scope_id, self.as_ref())
+    }
+}
+
+impl ScopeData for &Interned<'_> {
+    #[inline]
+    fn begin_scope(&self, tp: &mut ThreadProfiler, scope_id: ScopeId) -> usize {
+        tp.begin_scope_interned(scope_id, self.0)
+    }
+}
+
+impl ScopeData for Interned<'_> {
+    #[inline]
+    fn begin_scope(&self, tp: &mut ThreadProfiler, scope_id: ScopeId) -> usize {
+        tp.begin_scope_interned(scope_id, self.0)
+    }
+}
+
 impl Drop for ProfilerScope {
     #[inline]
     fn drop(&mut self) {
-        ThreadProfiler::call(|tp| tp.end_scope(self.start_stream_offset));
+        match self.profiler {
+            None => ThreadProfiler::call(|tp| tp.end_scope(self.start_stream_offset)),
+            Some(id) => profiler::call_thread_profiler_by_id(id, |tp| {
+                tp.end_scope(self.start_stream_offset);
+            }),
+        }
     }
 }
 
@@ -210,17 +279,28 @@ macro_rules! current_function_name {
 /// }
 /// ```
 ///
+/// The scope can be given a [`ScopeDetails::category`] before the data,
+/// e.g. `profile_function!(category = "io", path)`, which the flamegraph can color by.
+///
 /// Overhead: around 54 ns on Macbook Pro with Apple M1 Max.
 ///
 /// If the puffin profiler is turned off ([`crate::are_scopes_on`] is `false`),
 /// the cost is only checking an `AtomicBool`, which is less than 1ns.
+/// With the `disable` feature of puffin, the macro compiles to nothing.
 ///
-/// You can conditionally profile a function with [`profile_function_if`].
+/// You can conditionally profile a function with [`profile_function_if`],
+/// or profile it with the `#[puffin::profile]` attribute of the `macros` feature.
 #[macro_export]
 macro_rules! profile_function {
     () => {
         $crate::profile_function_if!(true, "");
     };
+    (category = $category:expr) => {
+        $crate::profile_function_if!(true, category = $category, "");
+    };
+    (category = $category:expr, $data:expr) => {
+        $crate::profile_function_if!(true, category = $category, $data);
+    };
     ($data:expr) => {
         $crate::profile_function_if!(true, $data);
     };
@@ -255,27 +335,20 @@ macro_rules! profile_function {
 /// If [`crate::are_scopes_on`] is `false`, the condition is not evaluated.
 #[macro_export]
 macro_rules! profile_function_if {
+    (@[$($category:expr)?] $condition:expr, $data:expr) => {
+        let _profiler_scope = $crate::__profiler_scope!(function [$($category)?] $condition, $data);
+    };
     ($condition:expr) => {
-        $crate::profile_function_if!($condition, "");
+        $crate::profile_function_if!(@[] $condition, "");
+    };
+    ($condition:expr, category = $category:expr) => {
+        $crate::profile_function_if!(@[$category] $condition, "");
+    };
+    ($condition:expr, category = $category:expr, $data:expr) => {
+        $crate::profile_function_if!(@[$category] $condition, $data);
     };
     ($condition:expr, $data:expr) => {
-        let _profiler_scope = if $crate::are_scopes_on() && ($condition) {
-            static SCOPE_ID: std::sync::OnceLock<$crate::ScopeId> = std::sync::OnceLock::new();
-            let scope_id = SCOPE_ID.get_or_init(|| {
-                $crate::ThreadProfiler::call(|tp| {
-                    let id = tp.register_function_scope(
-                        $crate::clean_function_name($crate::current_function_name!()),
-                        $crate::short_file_name(file!()),
-                        line!(),
-                    );
-                    id
-                })
-            });
-
-            Some($crate::ProfilerScope::new(*scope_id, $data))
-        } else {
-            None
-        };
+        $crate::profile_function_if!(@[] $condition, $data);
     };
 }
 
@@ -317,6 +390,12 @@ macro_rules! profile_scope_custom {
     ($name:expr) => {
         $crate::profile_scope_custom_if!(true, $name, "")
     };
+    ($name:expr, category = $category:expr) => {
+        $crate::profile_scope_custom_if!(true, $name, category = $category, "")
+    };
+    ($name:expr, category = $category:expr, $data:expr) => {{
+        $crate::profile_scope_custom_if!(true, $name, category = $category, $data)
+    }};
     ($name:expr, $data:expr) => {{
         $crate::profile_scope_custom_if!(true, $name, $data)
     }};
@@ -329,28 +408,18 @@ macro_rules! profile_scope_custom {
 /// See [`profile_function_if`] for a motivating example.
 #[macro_export]
 macro_rules! profile_scope_custom_if {
-    ($condition:expr, $name:expr) => {
-        $crate::profile_scope_custom_if!($condition, $name, "")
+    (@[$($category:expr)?] $condition:expr, $name:expr, $data:expr) => {
+        $crate::__profiler_scope!(named [$($category)?] $condition, $name, $data)
+    };
+    ($condition:expr, $name:expr, category = $category:expr) => {
+        $crate::profile_scope_custom_if!(@[$category] $condition, $name, "")
+    };
+    ($condition:expr, $name:expr, category = $category:expr, $data:expr) => {
+        $crate::profile_scope_custom_if!(@[$category] $condition, $name, $data)
+    };
+    ($condition:expr, $name:expr, $data:expr) => {
+        $crate::profile_scope_custom_if!(@[] $condition, $name, $data)
     };
-    ($condition:expr, $name:expr, $data:expr) => {{
-        if $crate::are_scopes_on() && ($condition) {
-            static SCOPE_ID: std::sync::OnceLock<$crate::ScopeId> = std::sync::OnceLock::new();
-            let scope_id = SCOPE_ID.get_or_init(|| {
-                $crate::ThreadProfiler::call(|tp| {
-                    let id = tp.register_named_scope(
-                        $name,
-                        $crate::clean_function_name($crate::current_function_name!()),
-                        $crate::short_file_name(file!()),
-                        line!(),
-                    );
-                    id
-                })
-            });
-            Some($crate::ProfilerScope::new(*scope_id, $data))
-        } else {
-            None
-        }
-    }};
 }
 
 /// Profile the current scope with the given name (unique in the parent scope).
@@ -362,6 +431,11 @@ macro_rules! profile_scope_custom_if {
 /// An optional second argument can be a string (e.g. a mesh name) to help diagnose what was slow.
 /// Example: `profile_scope!("load_mesh", mesh_name);`
 ///
+/// If the same data is used by many scopes, wrap it in [`Interned`] to only store it once per frame.
+///
+/// The scope can be given a [`ScopeDetails::category`] before the data,
+/// e.g. `profile_scope!("load_mesh", category = "io", mesh_name);`
+///
 /// Overhead: around 54 ns on Macbook Pro with Apple M1 Max.
 ///
 /// If the puffin profiler is turned off ([`crate::are_scopes_on`] is `false`),
@@ -373,6 +447,12 @@ macro_rules! profile_scope {
     ($name:expr) => {
         $crate::profile_scope_if!(true, $name, "");
     };
+    ($name:expr, category = $category:expr) => {
+        $crate::profile_scope_if!(true, $name, category = $category, "");
+    };
+    ($name:expr, category = $category:expr, $data:expr) => {
+        $crate::profile_scope_if!(true, $name, category = $category, $data);
+    };
     ($name:expr, $data:expr) => {
         $crate::profile_scope_if!(true, $name, $data);
     };
@@ -388,12 +468,296 @@ macro_rules! profile_scope_if {
     ($condition:expr, $name:expr) => {
         $crate::profile_scope_if!($condition, $name, "");
     };
+    ($condition:expr, $name:expr, category = $category:expr) => {
+        $crate::profile_scope_if!($condition, $name, category = $category, "");
+    };
+    ($condition:expr, $name:expr, category = $category:expr, $data:expr) => {
+        let _profiler_scope =
+            $crate::profile_scope_custom_if!($condition, $name, category = $category, $data);
+    };
     ($condition:expr, $name:expr, $data:expr) => {
         let _profiler_scope = $crate::profile_scope_custom_if!($condition, $name, $data);
     };
 }
 
-#[cfg(test)]
+/// Like [`profile_function`], but for the given [`Profiler`] instead of the [`GlobalProfiler`].
+///
+/// ```
+/// fn render(profiler: &puffin::Profiler, mesh_name: &str) {
+///     puffin::profile_function_in!(profiler, mesh_name);
+///     /* … */
+/// }
+/// ```
+///
+/// Like the other macros, this does nothing unless [`crate::are_scopes_on`].
+#[macro_export]
+macro_rules! profile_function_in {
+    (@[$($category:expr)?] $profiler:expr, $data:expr) => {
+        let _profiler_scope = $crate::__profiler_scope!(function_in [$($category)?] $profiler, $data);
+    };
+    ($profiler:expr) => {
+        $crate::profile_function_in!(@[] $profiler, "");
+    };
+    ($profiler:expr, category = $category:expr) => {
+        $crate::profile_function_in!(@[$category] $profiler, "");
+    };
+    ($profiler:expr, category = $category:expr, $data:expr) => {
+        $crate::profile_function_in!(@[$category] $profiler, $data);
+    };
+    ($profiler:expr, $data:expr) => {
+        $crate::profile_function_in!(@[] $profiler, $data);
+    };
+}
+
+/// Like [`profile_scope`], but for the given [`Profiler`] instead of the [`GlobalProfiler`].
+///
+/// ```
+/// # let profiler = puffin::Profiler::new();
+/// # let mesh_name = "tree";
+/// puffin::profile_scope_in!(profiler, "load_mesh", mesh_name);
+/// ```
+///
+/// Like the other macros, this does nothing unless [`crate::are_scopes_on`].
+#[macro_export]
+macro_rules! profile_scope_in {
+    (@[$($category:expr)?] $profiler:expr, $name:expr, $data:expr) => {
+        let _profiler_scope =
+            $crate::__profiler_scope!(named_in [$($category)?] $profiler, $name, $data);
+    };
+    ($profiler:expr, $name:expr) => {
+        $crate::profile_scope_in!(@[] $profiler, $name, "");
+    };
+    ($profiler:expr, $name:expr, category = $category:expr) => {
+        $crate::profile_scope_in!(@[$category] $profiler, $name, "");
+    };
+    ($profiler:expr, $name:expr, category = $category:expr, $data:expr) => {
+        $crate::profile_scope_in!(@[$category] $profiler, $name, $data);
+    };
+    ($profiler:expr, $name:expr, $data:expr) => {
+        $crate::profile_scope_in!(@[] $profiler, $name, $data);
+    };
+}
+
+/// The [`ProfilerScope`] of the profiler macros, as an `Option`.
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "disable"))]
+macro_rules! __profiler_scope {
+    (function [$($category:expr)?] $condition:expr, $data:expr) => {
+        if $crate::are_scopes_on() && ($condition) {
+            static SCOPE_ID: std::sync::OnceLock<$crate::ScopeId> = std::sync::OnceLock::new();
+            static SCOPE_SWITCH: $crate::ScopeSwitch = $crate::ScopeSwitch::new();
+            let scope_id = SCOPE_ID.get_or_init(|| {
+                let scope_details = $crate::ScopeDetails::new_function_scope(
+                    $crate::clean_function_name($crate::current_function_name!()),
+                    $crate::short_file_name(file!()),
+                    line!(),
+                )$(.with_category($category))?;
+                SCOPE_SWITCH.register(&scope_details);
+                $crate::ThreadProfiler::call(|tp| tp.register_scope(scope_details))
+            });
+            if SCOPE_SWITCH.is_on() {
+                Some($crate::ProfilerScope::new(*scope_id, $data))
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    };
+    (named [$($category:expr)?] $condition:expr, $name:expr, $data:expr) => {
+        if $crate::are_scopes_on() && ($condition) {
+            static SCOPE_ID: std::sync::OnceLock<$crate::ScopeId> = std::sync::OnceLock::new();
+            static SCOPE_SWITCH: $crate::ScopeSwitch = $crate::ScopeSwitch::new();
+            let scope_id = SCOPE_ID.get_or_init(|| {
+                let scope_details = $crate::ScopeDetails::new_named_scope(
+                    $name,
+                    $crate::clean_function_name($crate::current_function_name!()),
+                    $crate::short_file_name(file!()),
+                    line!(),
+                )$(.with_category($category))?;
+                SCOPE_SWITCH.register(&scope_details);
+                $crate::ThreadProfiler::call(|tp| tp.register_scope(scope_details))
+            });
+            if SCOPE_SWITCH.is_on() {
+                Some($crate::ProfilerScope::new(*scope_id, $data))
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    };
+    (function_in [$($category:expr)?] $profiler:expr, $data:expr) => {
+        if $crate::are_scopes_on() {
+            static SCOPE_DETAILS: std::sync::OnceLock<$crate::ScopeDetails> =
+                std::sync::OnceLock::new();
+            static SCOPE_SWITCH: $crate::ScopeSwitch = $crate::ScopeSwitch::new();
+            let scope_details = SCOPE_DETAILS.get_or_init(|| {
+                let scope_details = $crate::ScopeDetails::new_function_scope(
+                    $crate::clean_function_name($crate::current_function_name!()),
+                    $crate::short_file_name(file!()),
+                    line!(),
+                )$(.with_category($category))?;
+                SCOPE_SWITCH.register(&scope_details);
+                scope_details
+            });
+            if SCOPE_SWITCH.is_on() {
+                Some($crate::ProfilerScope::new_in(
+                    &$profiler,
+                    scope_details,
+                    $data,
+                ))
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    };
+    (named_in [$($category:expr)?] $profiler:expr, $name:expr, $data:expr) => {
+        if $crate::are_scopes_on() {
+            static SCOPE_DETAILS: std::sync::OnceLock<$crate::ScopeDetails> =
+                std::sync::OnceLock::new();
+            static SCOPE_SWITCH: $crate::ScopeSwitch = $crate::ScopeSwitch::new();
+            let scope_details = SCOPE_DETAILS.get_or_init(|| {
+                let scope_details = $crate::ScopeDetails::new_named_scope(
+                    $name,
+                    $crate::clean_function_name($crate::current_function_name!()),
+                    $crate::short_file_name(file!()),
+                    line!(),
+                )$(.with_category($category))?;
+                SCOPE_SWITCH.register(&scope_details);
+                scope_details
+            });
+            if SCOPE_SWITCH.is_on() {
+                Some($crate::ProfilerScope::new_in(
+                    &$profiler,
+                    scope_details,
+                    $data,
+                ))
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    };
+}
+
+/// Awaits the future, with a profile scope around each poll of it.
+///
+/// Used by `#[puffin::profile]` on an `async fn`, since a scope must not span an `.await`.
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "disable"))]
+macro_rules! __profile_future {
+    (function [$($category:expr)?] $condition:expr, $data:expr, $future:expr) => {{
+        // Like for the other scopes, only evaluated when profiling:
+        let data = ($crate::are_scopes_on() && ($condition)).then(|| $data);
+        let mut future = std::pin::pin!($future);
+        match data {
+            Some(data) => {
+                std::future::poll_fn(|cx| {
+                    let _profiler_scope =
+                        $crate::__profiler_scope!(function [$($category)?] true, &data);
+                    std::future::Future::poll(future.as_mut(), cx)
+                })
+                .await
+            }
+            None => future.await,
+        }
+    }};
+    (named [$($category:expr)?] $condition:expr, $name:expr, $data:expr, $future:expr) => {{
+        // Like for the other scopes, only evaluated when profiling:
+        let data = ($crate::are_scopes_on() && ($condition)).then(|| $data);
+        let mut future = std::pin::pin!($future);
+        match data {
+            Some(data) => {
+                std::future::poll_fn(|cx| {
+                    let _profiler_scope =
+                        $crate::__profiler_scope!(named [$($category)?] true, $name, &data);
+                    std::future::Future::poll(future.as_mut(), cx)
+                })
+                .await
+            }
+            None => future.await,
+        }
+    }};
+}
+
+/// With the `disable` feature, the profiler macros only type-check their arguments,
+/// in a closure that is never called, so nothing is left of them in the binary.
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "disable")]
+macro_rules! __profiler_scope {
+    (function [$($category:expr)?] $condition:expr, $data:expr) => {{
+        let _ = || {
+            let _: bool = $condition;
+            $($crate::__check_category($category);)?
+            $crate::__check_data(&$data);
+        };
+        None::<$crate::ProfilerScope>
+    }};
+    (named [$($category:expr)?] $condition:expr, $name:expr, $data:expr) => {{
+        let _ = || {
+            let _: bool = $condition;
+            $crate::__check_name($name);
+            $($crate::__check_category($category);)?
+            $crate::__check_data(&$data);
+        };
+        None::<$crate::ProfilerScope>
+    }};
+    (function_in [$($category:expr)?] $profiler:expr, $data:expr) => {{
+        let _ = || {
+            let _: &$crate::Profiler = &$profiler;
+            $($crate::__check_category($category);)?
+            $crate::__check_data(&$data);
+        };
+        None::<$crate::ProfilerScope>
+    }};
+    (named_in [$($category:expr)?] $profiler:expr, $name:expr, $data:expr) => {{
+        let _ = || {
+            let _: &$crate::Profiler = &$profiler;
+            $crate::__check_name($name);
+            $($crate::__check_category($category);)?
+            $crate::__check_data(&$data);
+        };
+        None::<$crate::ProfilerScope>
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "disable")]
+macro_rules! __profile_future {
+    (function [$($category:expr)?] $condition:expr, $data:expr, $future:expr) => {{
+        let _ = $crate::__profiler_scope!(function [$($category)?] $condition, $data);
+        $future.await
+    }};
+    (named [$($category:expr)?] $condition:expr, $name:expr, $data:expr, $future:expr) => {{
+        let _ = $crate::__profiler_scope!(named [$($category)?] $condition, $name, $data);
+        $future.await
+    }};
+}
+
+#[doc(hidden)]
+#[cfg(feature = "disable")]
+#[inline(always)]
+pub fn __check_name(_name: impl Into<std::borrow::Cow<'static, str>>) {}
+
+#[doc(hidden)]
+#[cfg(feature = "disable")]
+#[inline(always)]
+pub fn __check_category(_category: impl Into<std::borrow::Cow<'static, str>>) {}
+
+#[doc(hidden)]
+#[cfg(feature = "disable")]
+#[inline(always)]
+pub fn __check_data(_data: &impl ScopeData) {}
+
+#[cfg(all(test, not(feature = "disable")))]
 mod tests {
     use std::borrow::Cow;
 
@@ -409,11 +773,13 @@ mod tests {
             if data.frame_index() == 0 {
                 assert_eq!(data.frame_index(), 0);
                 assert_eq!(data.meta().num_scopes, 2);
-                assert_eq!(data.meta().num_bytes, 62);
+                assert_eq!(data.meta().num_bytes_uncompacted, 62);
+                assert!(data.meta().num_bytes < 62);
             } else if data.frame_index() == 1 {
                 assert_eq!(data.frame_index(), 1);
                 assert_eq!(data.meta().num_scopes, 2);
-                assert_eq!(data.meta().num_bytes, 62);
+                assert_eq!(data.meta().num_bytes_uncompacted, 62);
+                assert!(data.meta().num_bytes < 62);
             } else {
                 panic!("Only two frames in this test");
             }
@@ -1,5 +1,5 @@
 use crate::{
-    NanoSecond, Reader, Result, Scope, ScopeCollection, ScopeId, Stream, ThreadInfo,
+    Allocations, NanoSecond, Reader, Result, Scope, ScopeCollection, ScopeId, Stream, ThreadInfo,
     UnpackedFrameData,
 };
 use std::{collections::BTreeMap, hash::Hash};
@@ -40,6 +40,8 @@ pub struct MergeScope<'s> {
     pub duration_per_frame_ns: NanoSecond,
     /// The slowest individual piece.
     pub max_duration_ns: NanoSecond,
+    /// Sum of the allocations of all pieces, over all frames.
+    pub allocations: Allocations,
     /// Number of pieces that got merged together to us.
     pub num_pieces: usize,
     /// The common identifier that we merged using.
@@ -58,6 +60,7 @@ impl<'s> MergeScope<'s> {
             total_duration_ns: self.total_duration_ns,
             duration_per_frame_ns: self.duration_per_frame_ns,
             max_duration_ns: self.max_duration_ns,
+            allocations: self.allocations,
             num_pieces: self.num_pieces,
             id: self.id,
             data: std::borrow::Cow::Owned(self.data.into_owned()),
@@ -70,7 +73,7 @@ impl<'s> MergeNode<'s> {
     fn add<'slf>(&'slf mut self, stream: &'s Stream, piece: MergePiece<'s>) -> Result<()> {
         self.pieces.push(piece);
 
-        for child in Reader::with_offset(stream, piece.scope.child_begin_position)? {
+        for child in Reader::with_parent(stream, &piece.scope)? {
             let child = child?;
 
             self.children
@@ -96,6 +99,7 @@ impl<'s> MergeNode<'s> {
         let mut relative_start_ns = self.pieces[0].relative_start_ns;
         let mut total_duration_ns = 0;
         let mut slowest_ns = 0;
+        let mut allocations = Allocations::default();
         let num_pieces = self.pieces.len();
         let id = self.pieces[0].scope.id;
         let mut data = self.pieces[0].scope.record.data;
@@ -105,6 +109,7 @@ impl<'s> MergeNode<'s> {
             relative_start_ns = relative_start_ns.min(piece.relative_start_ns);
             total_duration_ns += piece.scope.record.duration_ns;
             slowest_ns = slowest_ns.max(piece.scope.record.duration_ns);
+            allocations += piece.scope.allocations;
 
             assert_eq!(id, piece.scope.id);
             if data != piece.scope.record.data {
@@ -117,6 +122,7 @@ impl<'s> MergeNode<'s> {
             total_duration_ns,
             duration_per_frame_ns: total_duration_ns / num_frames,
             max_duration_ns: slowest_ns,
+            allocations,
             num_pieces,
             id,
             data: data.into(),
@@ -239,6 +245,7 @@ mod tests {
         let thread_info = ThreadInfo {
             start_time_ns: Some(0),
             name: "main".to_owned(),
+            ..Default::default()
         };
         thread_streams.insert(thread_info.clone(), stream_info);
         let frame = UnpackedFrameData::new(0, thread_streams).unwrap();
@@ -251,6 +258,7 @@ mod tests {
                 total_duration_ns: 2 * 100,
                 duration_per_frame_ns: 2 * 100,
                 max_duration_ns: 100,
+                allocations: Default::default(),
                 num_pieces: 2,
                 id: ScopeId::new(1),
                 data: "".into(),
@@ -261,6 +269,7 @@ mod tests {
                 total_duration_ns: 2 * 700,
                 duration_per_frame_ns: 2 * 700,
                 max_duration_ns: 700,
+                allocations: Default::default(),
                 num_pieces: 2,
                 id: ScopeId::new(2),
                 data: "".into(),
@@ -270,6 +279,7 @@ mod tests {
                         total_duration_ns: 2 * 200,
                         duration_per_frame_ns: 2 * 200,
                         max_duration_ns: 200,
+                        allocations: Default::default(),
                         num_pieces: 2,
                         id: ScopeId::new(3),
                         data: "".into(),
@@ -280,6 +290,7 @@ mod tests {
                         total_duration_ns: 2 * 200,
                         duration_per_frame_ns: 2 * 200,
                         max_duration_ns: 200,
+                        allocations: Default::default(),
                         num_pieces: 2,
                         id: ScopeId::new(4),
                         data: "".into(),
@@ -288,6 +299,7 @@ mod tests {
                             total_duration_ns: 2 * 100,
                             duration_per_frame_ns: 2 * 100,
                             max_duration_ns: 100,
+                            allocations: Default::default(),
                             num_pieces: 2,
                             id: ScopeId::new(5),
                             data: "".into(),
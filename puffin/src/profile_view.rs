@@ -233,10 +233,9 @@ impl FrameView {
     #[cfg(feature = "serialization")]
     #[cfg(not(target_arch = "wasm32"))] // compression not supported on wasm
     pub fn write(&self, write: &mut impl std::io::Write) -> anyhow::Result<()> {
-        write.write_all(b"PUF0")?;
-
+        let mut writer = FrameWriter::new(write)?;
         for frame in self.all_uniq() {
-            frame.write_into(&self.scope_collection, false, write)?;
+            writer.write_frame(frame, &self.scope_collection)?;
         }
         Ok(())
     }
@@ -264,6 +263,69 @@ impl FrameView {
 
 // ----------------------------------------------------------------------------
 
+/// Writes frames to a `.puffin` file/stream one at a time, e.g. as they are received.
+///
+/// Unlike [`FrameView::write`] this doesn't need to keep all the frames in memory,
+/// so it can be used to record long sessions.
+/// The result can be read with [`FrameView::read`].
+#[cfg(feature = "serialization")]
+#[cfg(not(target_arch = "wasm32"))] // compression not supported on wasm
+pub struct FrameWriter<W: std::io::Write> {
+    write: W,
+    num_frames: usize,
+}
+
+#[cfg(feature = "serialization")]
+#[cfg(not(target_arch = "wasm32"))] // compression not supported on wasm
+impl<W: std::io::Write> FrameWriter<W> {
+    /// Writes the `.puffin` header.
+    pub fn new(mut write: W) -> anyhow::Result<Self> {
+        write.write_all(b"PUF0")?;
+        Ok(Self {
+            write,
+            num_frames: 0,
+        })
+    }
+
+    /// Write the next frame.
+    ///
+    /// The `scope_collection` must contain all scopes of the frame (see [`FrameView::scope_collection`]).
+    /// The first frame is written together with all known scopes, so the recording
+    /// can start at any frame.
+    pub fn write_frame(
+        &mut self,
+        frame: &FrameData,
+        scope_collection: &ScopeCollection,
+    ) -> anyhow::Result<()> {
+        let send_all_scopes = self.num_frames == 0;
+        frame.write_into(scope_collection, send_all_scopes, &mut self.write)?;
+        self.num_frames += 1;
+        Ok(())
+    }
+
+    /// Number of frames written so far.
+    pub fn num_frames(&self) -> usize {
+        self.num_frames
+    }
+
+    /// The underlying stream.
+    pub fn get_ref(&self) -> &W {
+        &self.write
+    }
+
+    /// The underlying stream.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.write
+    }
+
+    /// Stop writing, returning the underlying stream.
+    pub fn into_inner(self) -> W {
+        self.write
+    }
+}
+
+// ----------------------------------------------------------------------------
+
 /// Select the slowest frames, up to a certain count.
 pub fn select_slowest(frames: &[Arc<FrameData>], max: usize) -> Vec<Arc<FrameData>> {
     let mut slowest: std::collections::BinaryHeap<OrderedByDuration> = Default::default();
@@ -393,6 +455,8 @@ pub struct FrameStats {
     unique_frames: usize,
     total_ram_used: usize,
     unpacked_frames: usize,
+    stream_bytes: usize,
+    uncompacted_stream_bytes: usize,
 }
 
 impl FrameStats {
@@ -414,6 +478,10 @@ impl FrameStats {
         self.total_ram_used = self.total_ram_used.saturating_add(total);
         self.unpacked_frames = self.unpacked_frames.saturating_add(unpacked);
         self.unique_frames = self.unique_frames.saturating_add(1);
+        self.stream_bytes = self.stream_bytes.saturating_add(frame.meta().num_bytes);
+        self.uncompacted_stream_bytes = self
+            .uncompacted_stream_bytes
+            .saturating_add(frame.meta().num_bytes_uncompacted);
     }
 
     /// Removes a frame's statistics from the `FrameStats`.
@@ -423,6 +491,10 @@ impl FrameStats {
         self.total_ram_used = self.total_ram_used.saturating_sub(total);
         self.unpacked_frames = self.unpacked_frames.saturating_sub(unpacked);
         self.unique_frames = self.unique_frames.saturating_sub(1);
+        self.stream_bytes = self.stream_bytes.saturating_sub(frame.meta().num_bytes);
+        self.uncompacted_stream_bytes = self
+            .uncompacted_stream_bytes
+            .saturating_sub(frame.meta().num_bytes_uncompacted);
     }
 
     /// Returns the number of unique frames.
@@ -440,10 +512,26 @@ impl FrameStats {
         self.total_ram_used
     }
 
+    /// Returns the total unpacked size of the profiling streams.
+    pub fn stream_bytes(&self) -> usize {
+        self.stream_bytes
+    }
+
+    /// Returns how many bytes the compact stream layout saves,
+    /// compared to [`Self::stream_bytes`] without it.
+    ///
+    /// See [`crate::Stream::to_compact`].
+    pub fn bytes_saved_by_compaction(&self) -> usize {
+        self.uncompacted_stream_bytes
+            .saturating_sub(self.stream_bytes)
+    }
+
     /// Clears all statistics in `FrameStats`.
     pub fn clear(&mut self) {
         self.unique_frames = 0;
         self.unpacked_frames = 0;
         self.total_ram_used = 0;
+        self.stream_bytes = 0;
+        self.uncompacted_stream_bytes = 0;
     }
 }
@@ -0,0 +1,202 @@
+use std::{
+    cell::RefCell,
+    num::NonZeroU32,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    global_profiler::{report_queue, NewFrameCounter, ReportSender},
+    FrameSink, FrameSinkId, GlobalProfiler, ScopeDetails, StreamInfoRef, ThreadInfo,
+    ThreadMemoryLimit, ThreadProfiler,
+};
+
+/// Identifies a [`Profiler`], so that each thread can keep one [`ThreadProfiler`] for it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ProfilerId(NonZeroU32);
+
+impl ProfilerId {
+    fn next() -> Self {
+        static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        Self(
+            NonZeroU32::new(id)
+                .expect("safe because integer is retrieved from fetch-add atomic operation"),
+        )
+    }
+}
+
+/// A profiler with its own scopes and sinks, separate from the [`GlobalProfiler`] singleton.
+///
+/// Use it to profile e.g. a render thread and a simulation server separately in one process.
+/// Scopes are recorded with [`crate::profile_function_in`] and [`crate::profile_scope_in`],
+/// and like with the [`GlobalProfiler`], you need to call [`Self::new_frame`] once per frame.
+///
+/// This is a cheap handle, and all clones refer to the same profiler.
+///
+/// ```
+/// let render_profiler = puffin::Profiler::new();
+///
+/// fn render(profiler: &puffin::Profiler) {
+///     puffin::profile_function_in!(profiler);
+///     // …
+/// }
+///
+/// render(&render_profiler);
+/// render_profiler.new_frame();
+/// ```
+#[derive(Clone)]
+pub struct Profiler {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    id: ProfilerId,
+    report_sender: ReportSender,
+    new_frames: NewFrameCounter,
+    profiler: parking_lot::Mutex<GlobalProfiler>,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        // Let the threads know, so that they stop reporting and forget their thread profilers:
+        self.report_sender.disconnect();
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    /// A new profiler, without any sinks.
+    pub fn new() -> Self {
+        let (report_sender, reports) = report_queue();
+        let id = ProfilerId::next();
+        let new_frames = NewFrameCounter::default();
+        Self {
+            inner: Arc::new(Inner {
+                id,
+                report_sender,
+                new_frames: new_frames.clone(),
+                profiler: parking_lot::Mutex::new(GlobalProfiler::with_reports(
+                    reports,
+                    Some(id),
+                    new_frames,
+                )),
+            }),
+        }
+    }
+
+    /// Access the frames, sinks and scopes of this profiler.
+    pub fn lock(&self) -> parking_lot::MutexGuard<'_, GlobalProfiler> {
+        self.inner.profiler.lock()
+    }
+
+    /// You need to call this once at the start of every frame.
+    ///
+    /// See [`GlobalProfiler::new_frame`].
+    pub fn new_frame(&self) {
+        self.lock().new_frame();
+    }
+
+    /// See [`GlobalProfiler::add_sink`].
+    pub fn add_sink(&self, sink: FrameSink) -> FrameSinkId {
+        self.lock().add_sink(sink)
+    }
+
+    /// See [`GlobalProfiler::remove_sink`].
+    pub fn remove_sink(&self, id: FrameSinkId) -> Option<FrameSink> {
+        self.lock().remove_sink(id)
+    }
+
+    /// Reports some profiling data without locking the profiler,
+    /// like [`crate::internal_profile_reporter`] does for the [`GlobalProfiler`].
+    ///
+    /// The data is added to the current frame in the next call to [`Self::new_frame`].
+    pub fn report(
+        &self,
+        info: ThreadInfo,
+        scope_details: &[ScopeDetails],
+        stream_scope_times: &StreamInfoRef<'_>,
+    ) {
+        self.inner
+            .report_sender
+            .send(info, scope_details, stream_scope_times);
+    }
+
+    /// Like [`crate::set_thread_memory_limit`], for the current thread when it reports to this profiler.
+    pub fn set_thread_memory_limit(&self, limit: Option<ThreadMemoryLimit>) {
+        self.call_thread_profiler(|tp| tp.set_memory_limit(limit));
+    }
+
+    pub(crate) fn id(&self) -> ProfilerId {
+        self.inner.id
+    }
+
+    /// Do something with the [`ThreadProfiler`] of this thread for this profiler.
+    pub(crate) fn call_thread_profiler<R>(&self, f: impl FnOnce(&mut ThreadProfiler) -> R) -> R {
+        THREAD_PROFILERS.with(|thread_profilers| {
+            let mut thread_profilers = thread_profilers.borrow_mut();
+            thread_profilers.retain(|(_, thread_profiler)| !thread_profiler.is_disconnected());
+            let index = match thread_profilers.iter().position(|(id, _)| *id == self.id()) {
+                Some(index) => index,
+                None => {
+                    let thread_profiler = ThreadProfiler::with_report_sender(
+                        self.inner.report_sender.clone(),
+                        self.inner.new_frames.clone(),
+                    );
+                    thread_profilers.push((self.id(), thread_profiler));
+                    thread_profilers.len() - 1
+                }
+            };
+            f(&mut thread_profilers[index].1)
+        })
+    }
+}
+
+thread_local! {
+    /// One [`ThreadProfiler`] per [`Profiler`] used on this thread.
+    static THREAD_PROFILERS: RefCell<Vec<(ProfilerId, ThreadProfiler)>> = Default::default();
+}
+
+/// Do something with the [`ThreadProfiler`] of this thread for the given profiler, if it has one.
+pub(crate) fn call_thread_profiler_by_id(id: ProfilerId, f: impl FnOnce(&mut ThreadProfiler)) {
+    THREAD_PROFILERS.with(|thread_profilers| {
+        if let Some((_, thread_profiler)) = thread_profilers
+            .borrow_mut()
+            .iter_mut()
+            .find(|(profiler_id, _)| *profiler_id == id)
+        {
+            f(thread_profiler);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Profiler, THREAD_PROFILERS};
+
+    fn num_thread_profilers() -> usize {
+        THREAD_PROFILERS.with(|thread_profilers| thread_profilers.borrow().len())
+    }
+
+    #[test]
+    fn forget_dropped_profilers() {
+        let kept = Profiler::new();
+        for _ in 0..3 {
+            let dropped = Profiler::new();
+            dropped.call_thread_profiler(|_| {});
+            kept.call_thread_profiler(|_| {});
+        }
+        // Each dropped profiler is forgotten the next time the thread uses a profiler:
+        assert_eq!(num_thread_profilers(), 2);
+
+        kept.call_thread_profiler(|_| {});
+        assert_eq!(num_thread_profilers(), 1);
+    }
+}
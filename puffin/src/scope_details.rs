@@ -109,6 +109,11 @@ pub struct ScopeDetails {
 
     /// The exact line number at which this scope is located.
     pub line_nr: u32,
+
+    /// What kind of work the scope does, e.g. `"render"`, `"physics"` or `"io"`.
+    ///
+    /// Set with e.g. `profile_scope!("load_mesh", category = "io")` or [`Self::with_category`].
+    pub category: Option<Cow<'static, str>>,
 }
 
 impl ScopeDetails {
@@ -123,9 +128,36 @@ impl ScopeDetails {
             function_name: Default::default(),
             file_path: Default::default(),
             line_nr: Default::default(),
+            category: None,
         }
     }
 
+    /// Creates the details of a function scope, with a unique id allocated by puffin.
+    ///
+    /// Used by [`crate::profile_function`] and [`crate::profile_function_in`].
+    pub fn new_function_scope(
+        function_name: impl Into<Cow<'static, str>>,
+        file_path: impl Into<Cow<'static, str>>,
+        line_nr: u32,
+    ) -> Self {
+        Self::from_scope_id(crate::fetch_add_scope_id())
+            .with_function_name(function_name)
+            .with_file(file_path)
+            .with_line_nr(line_nr)
+    }
+
+    /// Creates the details of a named scope, with a unique id allocated by puffin.
+    ///
+    /// Used by [`crate::profile_scope`] and [`crate::profile_scope_in`].
+    pub fn new_named_scope(
+        scope_name: impl Into<Cow<'static, str>>,
+        function_name: impl Into<Cow<'static, str>>,
+        file_path: impl Into<Cow<'static, str>>,
+        line_nr: u32,
+    ) -> Self {
+        Self::new_function_scope(function_name, file_path, line_nr).with_scope_name(scope_name)
+    }
+
     /// Creates a new user scope with a unique id allocated by puffin.
     /// This function should not be exposed as only puffin should allocate ids for scopes.
     pub(crate) fn from_scope_id(scope_id: ScopeId) -> Self {
@@ -135,6 +167,7 @@ impl ScopeDetails {
             function_name: Default::default(),
             file_path: Default::default(),
             line_nr: Default::default(),
+            category: None,
         }
     }
 
@@ -165,6 +198,16 @@ impl ScopeDetails {
         self
     }
 
+    /// Scope of a category, e.g. `"render"`, `"physics"` or `"io"`.
+    #[inline]
+    pub fn with_category<T>(mut self, category: T) -> Self
+    where
+        T: Into<Cow<'static, str>>,
+    {
+        self.category = Some(category.into());
+        self
+    }
+
     /// Returns the scope name if this is a profile scope or else the function name.
     pub fn name(&self) -> &Cow<'static, str> {
         self.scope_name.as_ref().map_or(&self.function_name, |x| x)
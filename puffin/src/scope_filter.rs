@@ -0,0 +1,122 @@
+use std::{
+    borrow::Cow,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use once_cell::sync::Lazy;
+
+use crate::ScopeDetails;
+
+/// Selects profile scopes to turn on or off at runtime,
+/// with [`crate::GlobalProfiler::set_scopes_enabled`].
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum ScopeFilter {
+    /// Scopes with this [`ScopeDetails::name`].
+    Name(Cow<'static, str>),
+
+    /// Scopes in functions whose path starts with this, e.g. `"my_renderer::"` for a whole crate.
+    FunctionPrefix(Cow<'static, str>),
+
+    /// Scopes in this file, as given by [`ScopeDetails::file_path`].
+    File(Cow<'static, str>),
+}
+
+impl ScopeFilter {
+    /// Does this filter select the given scope?
+    pub fn matches(&self, scope_details: &ScopeDetails) -> bool {
+        match self {
+            Self::Name(name) => scope_details.name() == name,
+            Self::FunctionPrefix(prefix) => scope_details.function_name.starts_with(&**prefix),
+            Self::File(file_path) => scope_details.file_path == *file_path,
+        }
+    }
+}
+
+/// Turns the profile scope of one call site on or off, as decided by the [`ScopeFilter`]s.
+///
+/// The profiler macros keep one of these in a `static` next to the scope id,
+/// so that checking it is only an atomic load.
+pub struct ScopeSwitch(AtomicBool);
+
+impl Default for ScopeSwitch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScopeSwitch {
+    /// A switch that is on until it is registered.
+    pub const fn new() -> Self {
+        Self(AtomicBool::new(true))
+    }
+
+    /// Is the scope turned on?
+    #[inline]
+    pub fn is_on(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Let the [`ScopeFilter`]s turn this switch on or off, now and whenever they change.
+    ///
+    /// Called once by the profiler macros when they register their scope.
+    pub fn register(&'static self, scope_details: &ScopeDetails) {
+        let mut registry = REGISTRY.lock();
+        self.0
+            .store(registry.is_enabled(scope_details), Ordering::Relaxed);
+        registry.switches.push((scope_details.clone(), self));
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    /// Later filters take precedence.
+    filters: Vec<(ScopeFilter, bool)>,
+    switches: Vec<(ScopeDetails, &'static ScopeSwitch)>,
+}
+
+impl Registry {
+    fn is_enabled(&self, scope_details: &ScopeDetails) -> bool {
+        self.filters
+            .iter()
+            .rev()
+            .find(|(filter, _)| filter.matches(scope_details))
+            .map_or(true, |(_, enabled)| *enabled)
+    }
+
+    fn update_switches(&self) {
+        for (scope_details, switch) in &self.switches {
+            switch
+                .0
+                .store(self.is_enabled(scope_details), Ordering::Relaxed);
+        }
+    }
+}
+
+static REGISTRY: Lazy<parking_lot::Mutex<Registry>> = Lazy::new(Default::default);
+
+pub(crate) fn set_scopes_enabled(filter: ScopeFilter, enabled: bool) {
+    let mut registry = REGISTRY.lock();
+    registry.filters.retain(|(f, _)| *f != filter);
+    registry.filters.push((filter, enabled));
+    registry.update_switches();
+}
+
+pub(crate) fn remove_scope_filter(filter: &ScopeFilter) {
+    let mut registry = REGISTRY.lock();
+    registry.filters.retain(|(f, _)| f != filter);
+    registry.update_switches();
+}
+
+pub(crate) fn clear_scope_filters() {
+    let mut registry = REGISTRY.lock();
+    registry.filters.clear();
+    registry.update_switches();
+}
+
+pub(crate) fn scope_filters() -> Vec<(ScopeFilter, bool)> {
+    REGISTRY.lock().filters.clone()
+}
+
+pub(crate) fn is_scope_enabled(scope_details: &ScopeDetails) -> bool {
+    REGISTRY.lock().is_enabled(scope_details)
+}
@@ -0,0 +1,229 @@
+//! [`Mutex`] and [`RwLock`] that show lock contention in the profile.
+//!
+//! These wrap the locks of `parking_lot`, and record a "waiting for lock" scope whenever
+//! taking the lock blocks for at least [`wait_threshold_ns`], with the name of the lock as its data.
+//! Taking a lock nobody holds records nothing, and costs no more than with `parking_lot`.
+//!
+//! ```
+//! static TEXTURES: puffin::sync::Mutex<Vec<String>> = puffin::sync::Mutex::new("textures", Vec::new());
+//!
+//! TEXTURES.lock().push("grass.png".to_owned());
+//! ```
+//!
+//! The wait scopes have the category [`WAIT_CATEGORY`], and are recorded like those of
+//! the profiler macros: only while [`crate::are_scopes_on`], and never with the `disable` feature.
+
+use std::sync::{
+    atomic::{AtomicI64, Ordering},
+    OnceLock,
+};
+
+use crate::{NanoSecond, ScopeDetails, ScopeId, ScopeSwitch, ThreadProfiler};
+
+/// The [`ScopeDetails::category`] of the scopes recorded while waiting for a lock.
+pub const WAIT_CATEGORY: &str = "lock wait";
+
+static WAIT_THRESHOLD_NS: AtomicI64 = AtomicI64::new(10_000);
+
+/// Only record waiting for a lock when it takes at least this long.
+///
+/// Defaults to 10 µs, to not clutter the profile with short waits.
+pub fn set_wait_threshold_ns(threshold_ns: NanoSecond) {
+    WAIT_THRESHOLD_NS.store(threshold_ns, Ordering::Relaxed);
+}
+
+/// See [`set_wait_threshold_ns`].
+pub fn wait_threshold_ns() -> NanoSecond {
+    WAIT_THRESHOLD_NS.load(Ordering::Relaxed)
+}
+
+/// The scope recorded while waiting for one kind of lock, registered like those of the profiler macros.
+struct WaitScope {
+    scope_name: &'static str,
+    function_name: &'static str,
+    line_nr: u32,
+    scope_id: OnceLock<ScopeId>,
+    switch: ScopeSwitch,
+}
+
+impl WaitScope {
+    const fn new(scope_name: &'static str, function_name: &'static str, line_nr: u32) -> Self {
+        Self {
+            scope_name,
+            function_name,
+            line_nr,
+            scope_id: OnceLock::new(),
+            switch: ScopeSwitch::new(),
+        }
+    }
+
+    /// `None` when the scope should not be recorded.
+    fn scope_id(&'static self) -> Option<ScopeId> {
+        if cfg!(feature = "disable") || !crate::are_scopes_on() {
+            return None;
+        }
+        let scope_id = *self.scope_id.get_or_init(|| {
+            let scope_details = ScopeDetails::new_named_scope(
+                self.scope_name,
+                self.function_name,
+                "sync.rs",
+                self.line_nr,
+            )
+            .with_category(WAIT_CATEGORY);
+            self.switch.register(&scope_details);
+            ThreadProfiler::call(|tp| tp.register_scope(scope_details))
+        });
+        self.switch.is_on().then_some(scope_id)
+    }
+
+    /// Take a lock with `acquire`, recording the wait if it takes long enough.
+    fn wait<G>(&'static self, lock_name: &str, acquire: impl FnOnce() -> G) -> G {
+        let Some(scope_id) = self.scope_id() else {
+            return acquire();
+        };
+        let now_ns = ThreadProfiler::call(|tp| tp.now_ns_source());
+        let start_ns = now_ns();
+        let guard = acquire();
+        let stop_ns = now_ns();
+        if stop_ns - start_ns >= wait_threshold_ns() {
+            ThreadProfiler::call(|tp| tp.record_scope(scope_id, lock_name, start_ns, stop_ns));
+        }
+        guard
+    }
+}
+
+static MUTEX_WAIT: WaitScope =
+    WaitScope::new("waiting for lock", "puffin::sync::Mutex::lock", line!());
+static READ_WAIT: WaitScope = WaitScope::new(
+    "waiting for read lock",
+    "puffin::sync::RwLock::read",
+    line!(),
+);
+static WRITE_WAIT: WaitScope = WaitScope::new(
+    "waiting for write lock",
+    "puffin::sync::RwLock::write",
+    line!(),
+);
+
+/// A [`parking_lot::Mutex`] that records a scope when [`Self::lock`] has to wait.
+pub struct Mutex<T: ?Sized> {
+    name: &'static str,
+    inner: parking_lot::Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    /// A mutex called `name` in the profile, e.g. `"textures"`.
+    pub const fn new(name: &'static str, value: T) -> Self {
+        Self {
+            name,
+            inner: parking_lot::const_mutex(value),
+        }
+    }
+
+    /// The value, without the mutex.
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// The name of the mutex, recorded as the data of its wait scopes.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Take the lock, recording a "waiting for lock" scope if that blocks for long enough.
+    pub fn lock(&self) -> parking_lot::MutexGuard<'_, T> {
+        if let Some(guard) = self.inner.try_lock() {
+            return guard;
+        }
+        MUTEX_WAIT.wait(self.name, || self.inner.lock())
+    }
+
+    /// Take the lock if nobody holds it. Never records anything.
+    pub fn try_lock(&self) -> Option<parking_lot::MutexGuard<'_, T>> {
+        self.inner.try_lock()
+    }
+
+    /// The value, without locking since nobody else can hold the lock.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Mutex")
+            .field("name", &self.name)
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// A [`parking_lot::RwLock`] that records a scope when [`Self::read`] or [`Self::write`] has to wait.
+pub struct RwLock<T: ?Sized> {
+    name: &'static str,
+    inner: parking_lot::RwLock<T>,
+}
+
+impl<T> RwLock<T> {
+    /// A lock called `name` in the profile, e.g. `"scene"`.
+    pub const fn new(name: &'static str, value: T) -> Self {
+        Self {
+            name,
+            inner: parking_lot::const_rwlock(value),
+        }
+    }
+
+    /// The value, without the lock.
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// The name of the lock, recorded as the data of its wait scopes.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Take a shared lock, recording a "waiting for read lock" scope if that blocks for long enough.
+    pub fn read(&self) -> parking_lot::RwLockReadGuard<'_, T> {
+        if let Some(guard) = self.inner.try_read() {
+            return guard;
+        }
+        READ_WAIT.wait(self.name, || self.inner.read())
+    }
+
+    /// Take an exclusive lock, recording a "waiting for write lock" scope if that blocks for long enough.
+    pub fn write(&self) -> parking_lot::RwLockWriteGuard<'_, T> {
+        if let Some(guard) = self.inner.try_write() {
+            return guard;
+        }
+        WRITE_WAIT.wait(self.name, || self.inner.write())
+    }
+
+    /// Take a shared lock if nobody holds an exclusive one. Never records anything.
+    pub fn try_read(&self) -> Option<parking_lot::RwLockReadGuard<'_, T>> {
+        self.inner.try_read()
+    }
+
+    /// Take an exclusive lock if nobody holds the lock. Never records anything.
+    pub fn try_write(&self) -> Option<parking_lot::RwLockWriteGuard<'_, T>> {
+        self.inner.try_write()
+    }
+
+    /// The value, without locking since nobody else can hold the lock.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RwLock")
+            .field("name", &self.name)
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
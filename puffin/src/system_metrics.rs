@@ -0,0 +1,264 @@
+use crate::NanoSecond;
+
+/// What the process used during one frame, sampled by [`crate::GlobalProfiler::set_system_metrics`].
+///
+/// Everything but [`Self::rss_bytes`] is counted since the previous call to
+/// [`crate::GlobalProfiler::new_frame`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct SystemMetrics {
+    /// Wall time between the two samples.
+    pub duration_ns: NanoSecond,
+
+    /// CPU time spent by the process in user mode, summed over all its threads.
+    pub user_cpu_ns: NanoSecond,
+
+    /// CPU time spent by the kernel on behalf of the process, summed over all its threads.
+    pub system_cpu_ns: NanoSecond,
+
+    /// Resident set size at the end of the frame, i.e. the memory of the process that is in RAM.
+    pub rss_bytes: u64,
+
+    /// Page faults that were resolved without reading from disk.
+    pub minor_page_faults: u64,
+
+    /// Page faults that had to read from disk.
+    pub major_page_faults: u64,
+
+    /// Times a thread gave up the CPU, e.g. to wait for a lock or for IO.
+    pub voluntary_context_switches: u64,
+
+    /// Times the kernel took the CPU away from a thread, e.g. because its time slice was up.
+    pub involuntary_context_switches: u64,
+}
+
+impl SystemMetrics {
+    /// CPU time spent in both user and kernel mode.
+    pub fn cpu_ns(&self) -> NanoSecond {
+        self.user_cpu_ns + self.system_cpu_ns
+    }
+
+    /// How many CPU cores the process kept busy on average, e.g. `1.5` for one and a half cores.
+    pub fn cpu_usage(&self) -> f32 {
+        if self.duration_ns > 0 {
+            self.cpu_ns() as f32 / self.duration_ns as f32
+        } else {
+            0.0
+        }
+    }
+
+    /// Both voluntary and involuntary context switches.
+    pub fn context_switches(&self) -> u64 {
+        self.voluntary_context_switches + self.involuntary_context_switches
+    }
+
+    /// Both minor and major page faults.
+    pub fn page_faults(&self) -> u64 {
+        self.minor_page_faults + self.major_page_faults
+    }
+}
+
+/// Reads `/proc/self` for each frame of [`crate::GlobalProfiler::new_frame`],
+/// on whichever thread builds the frame.
+///
+/// Never samples anything on other platforms than Linux and Android.
+#[derive(Default)]
+pub(crate) struct SystemMetricsSampler {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    previous: Option<ProcSample>,
+}
+
+impl SystemMetricsSampler {
+    /// What the process used since the previous call.
+    ///
+    /// `None` on the first call, and if `/proc/self` can't be read.
+    pub(crate) fn sample(&mut self) -> Option<SystemMetrics> {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                let sample = ProcSample::read();
+                let previous = std::mem::replace(&mut self.previous, sample.clone());
+                Some(sample?.since(&previous?))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// The counters of `/proc/self/stat` and `/proc/self/status` at one point in time.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ProcSample {
+    time: std::time::Instant,
+    stat: ProcStat,
+    status: ProcStatus,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl ProcSample {
+    fn read() -> Option<Self> {
+        let time = std::time::Instant::now();
+        let stat = ProcStat::parse(&std::fs::read_to_string("/proc/self/stat").ok()?)?;
+        let status = ProcStatus::parse(&std::fs::read_to_string("/proc/self/status").ok()?)?;
+        Some(Self { time, stat, status })
+    }
+
+    fn since(&self, previous: &Self) -> SystemMetrics {
+        let ticks_to_ns = |ticks: u64| {
+            let ticks_per_second = rustix::param::clock_ticks_per_second().max(1);
+            (ticks.saturating_mul(1_000_000_000) / ticks_per_second) as NanoSecond
+        };
+        SystemMetrics {
+            duration_ns: self.time.duration_since(previous.time).as_nanos() as NanoSecond,
+            user_cpu_ns: ticks_to_ns(
+                self.stat
+                    .user_ticks
+                    .saturating_sub(previous.stat.user_ticks),
+            ),
+            system_cpu_ns: ticks_to_ns(
+                self.stat
+                    .system_ticks
+                    .saturating_sub(previous.stat.system_ticks),
+            ),
+            rss_bytes: self.status.rss_bytes,
+            minor_page_faults: self
+                .stat
+                .minor_page_faults
+                .saturating_sub(previous.stat.minor_page_faults),
+            major_page_faults: self
+                .stat
+                .major_page_faults
+                .saturating_sub(previous.stat.major_page_faults),
+            voluntary_context_switches: self
+                .status
+                .voluntary_context_switches
+                .saturating_sub(previous.status.voluntary_context_switches),
+            involuntary_context_switches: self
+                .status
+                .involuntary_context_switches
+                .saturating_sub(previous.status.involuntary_context_switches),
+        }
+    }
+}
+
+/// The fields we want of `/proc/self/stat`, see `man proc`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ProcStat {
+    minor_page_faults: u64,
+    major_page_faults: u64,
+    user_ticks: u64,
+    system_ticks: u64,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl ProcStat {
+    fn parse(stat: &str) -> Option<Self> {
+        // The process name in parentheses may contain spaces and parentheses itself,
+        // so we start counting after the last `)`, which is followed by field 3 (the state).
+        let (_, fields) = stat.rsplit_once(')')?;
+        let fields: Vec<&str> = fields.split_whitespace().collect();
+        let field = |number: usize| fields.get(number - 3)?.parse().ok();
+        Some(Self {
+            minor_page_faults: field(10)?,
+            major_page_faults: field(12)?,
+            user_ticks: field(14)?,
+            system_ticks: field(15)?,
+        })
+    }
+}
+
+/// The fields we want of `/proc/self/status`, see `man proc`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ProcStatus {
+    rss_bytes: u64,
+    voluntary_context_switches: u64,
+    involuntary_context_switches: u64,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl ProcStatus {
+    fn parse(status: &str) -> Option<Self> {
+        let mut rss_bytes = None;
+        let mut voluntary_context_switches = None;
+        let mut involuntary_context_switches = None;
+        for line in status.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let mut value = value.split_whitespace();
+            let number = value.next().and_then(|number| number.parse::<u64>().ok());
+            match key {
+                "VmRSS" => {
+                    // Always in kB:
+                    rss_bytes = number.map(|kb| kb * 1024);
+                }
+                "voluntary_ctxt_switches" => voluntary_context_switches = number,
+                "nonvoluntary_ctxt_switches" => involuntary_context_switches = number,
+                _ => {}
+            }
+        }
+        Some(Self {
+            rss_bytes: rss_bytes?,
+            voluntary_context_switches: voluntary_context_switches?,
+            involuntary_context_switches: involuntary_context_switches?,
+        })
+    }
+}
+
+#[cfg(test)]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_proc_stat() {
+        let stat = "1234 (my (weird) app) S 1 1234 1234 0 -1 4194560 5712 0 3 0 150 42 0 0 20 0 \
+                    8 0 123456 987654321 4321 18446744073709551615 1 1 0 0 0 0 0 4096 17642 0 0 0 \
+                    17 2 0 0 0 0 0";
+        assert_eq!(
+            ProcStat::parse(stat),
+            Some(ProcStat {
+                minor_page_faults: 5712,
+                major_page_faults: 3,
+                user_ticks: 150,
+                system_ticks: 42,
+            })
+        );
+        assert_eq!(ProcStat::parse("1234 (app) S 1 2"), None);
+    }
+
+    #[test]
+    fn parse_proc_status() {
+        let status = "Name:\tmy app\n\
+                      VmPeak:\t  123456 kB\n\
+                      VmRSS:\t    2048 kB\n\
+                      Threads:\t8\n\
+                      voluntary_ctxt_switches:\t120\n\
+                      nonvoluntary_ctxt_switches:\t7\n";
+        assert_eq!(
+            ProcStatus::parse(status),
+            Some(ProcStatus {
+                rss_bytes: 2048 * 1024,
+                voluntary_context_switches: 120,
+                involuntary_context_switches: 7,
+            })
+        );
+        assert_eq!(ProcStatus::parse("Name:\tmy app\n"), None);
+    }
+
+    #[test]
+    fn sample_this_process() {
+        let mut sampler = SystemMetricsSampler::default();
+        assert_eq!(
+            sampler.sample(),
+            None,
+            "Nothing to compare the first sample to"
+        );
+
+        let metrics = sampler.sample().expect("Can't read /proc/self");
+        assert!(metrics.duration_ns > 0);
+        assert!(metrics.rss_bytes > 0);
+    }
+}
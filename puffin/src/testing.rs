@@ -0,0 +1,284 @@
+//! Helpers for deterministic tests of puffin itself, and of tools built on its profile data.
+//!
+//! Build frames from declarative [`ScopeTree`]s with [`FrameBuilder`],
+//! and check what was recorded with [`ScopeTree::read_frame`] and [`assert_frame_shape`].
+//! To get the same time stamps on every run when recording with the profiler macros,
+//! time the profiler with a [`MockClock`] (see [`crate::set_clock`]).
+//!
+//! ```
+//! use puffin::testing::{assert_frame_shape, FrameBuilder, ScopeTree};
+//!
+//! let frame = FrameBuilder::new(0)
+//!     .thread("main", [ScopeTree::new("update", 0, 100)
+//!         .with_children([ScopeTree::new("physics", 10, 40), ScopeTree::new("render", 50, 50)])])
+//!     .build();
+//!
+//! let mut frame_view = puffin::FrameView::default();
+//! frame_view.add_frame(std::sync::Arc::new(frame));
+//!
+//! let frame = frame_view.latest_frame().unwrap();
+//! assert_frame_shape(&frame, frame_view.scope_collection(), "main", "update(physics, render)");
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    sync::Arc,
+};
+
+use once_cell::sync::Lazy;
+
+pub use crate::MockClock;
+use crate::{
+    FrameData, FrameIndex, GlobalProfiler, NanoSecond, Reader, ScopeCollection, ScopeDetails,
+    ScopeId, Stream, StreamInfo, ThreadInfo,
+};
+
+/// A scope with its children, for building and checking frames in tests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScopeTree {
+    /// The [`ScopeDetails::name`] of the scope.
+    pub name: String,
+
+    /// The data of the scope, e.g. a file name.
+    pub data: String,
+
+    /// The start of the scope in nanoseconds.
+    pub start_ns: NanoSecond,
+
+    /// The duration of the scope in nanoseconds.
+    pub duration_ns: NanoSecond,
+
+    /// The child scopes, in order.
+    pub children: Vec<ScopeTree>,
+}
+
+impl ScopeTree {
+    /// A scope without data or children.
+    pub fn new(name: impl Into<String>, start_ns: NanoSecond, duration_ns: NanoSecond) -> Self {
+        Self {
+            name: name.into(),
+            data: String::new(),
+            start_ns,
+            duration_ns,
+            children: vec![],
+        }
+    }
+
+    /// Scope with this data.
+    pub fn with_data(mut self, data: impl Into<String>) -> Self {
+        self.data = data.into();
+        self
+    }
+
+    /// Scope with these children, which should be within it.
+    pub fn with_children(mut self, children: impl IntoIterator<Item = Self>) -> Self {
+        self.children = children.into_iter().collect();
+        self
+    }
+
+    /// The top scopes of each thread of a frame, by thread name.
+    ///
+    /// The names of the scopes are looked up in `scope_collection`,
+    /// e.g. [`crate::FrameView::scope_collection`].
+    ///
+    /// # Panics
+    /// If the frame can't be read, or a scope is missing from the `scope_collection`.
+    pub fn read_frame(
+        frame: &FrameData,
+        scope_collection: &ScopeCollection,
+    ) -> BTreeMap<String, Vec<Self>> {
+        let unpacked = frame
+            .unpacked()
+            .unwrap_or_else(|_| panic!("Failed to unpack frame {}", frame.frame_index()));
+        unpacked
+            .thread_streams
+            .iter()
+            .map(|(thread_info, stream_info)| {
+                let reader = Reader::from_start(&stream_info.stream);
+                let scopes = read_scopes(&stream_info.stream, reader, scope_collection);
+                (thread_info.name.clone(), scopes)
+            })
+            .collect()
+    }
+}
+
+fn read_scopes(
+    stream: &Stream,
+    reader: Reader<'_>,
+    scope_collection: &ScopeCollection,
+) -> Vec<ScopeTree> {
+    let scopes = reader.read_top_scopes().expect("a valid stream");
+    scopes
+        .iter()
+        .map(|scope| {
+            let details = scope_collection
+                .fetch_by_id(&scope.id)
+                .unwrap_or_else(|| panic!("{:?} is missing from the scope collection", scope.id));
+            let children_reader = Reader::with_parent(stream, scope).expect("a valid stream");
+            ScopeTree {
+                name: details.name().to_string(),
+                data: scope.record.data.to_owned(),
+                start_ns: scope.record.start_ns,
+                duration_ns: scope.record.duration_ns,
+                children: read_scopes(stream, children_reader, scope_collection),
+            }
+        })
+        .collect()
+}
+
+/// The names of the scopes and how they nest, e.g. `"update(physics, render(draw, draw)), sleep"`.
+pub fn shape(scopes: &[ScopeTree]) -> String {
+    let mut shape = String::new();
+    for (i, scope) in scopes.iter().enumerate() {
+        if i > 0 {
+            shape.push_str(", ");
+        }
+        shape.push_str(&scope.name);
+        if !scope.children.is_empty() {
+            let _ = write!(&mut shape, "({})", self::shape(&scope.children));
+        }
+    }
+    shape
+}
+
+/// Assert that the scopes of a thread of the frame have the given [`shape`],
+/// e.g. `"update(physics, render(draw, draw)), sleep"`.
+///
+/// # Panics
+/// If the shape is different, or the thread has no scopes in the frame.
+#[track_caller]
+pub fn assert_frame_shape(
+    frame: &FrameData,
+    scope_collection: &ScopeCollection,
+    thread_name: &str,
+    expected_shape: &str,
+) {
+    let threads = ScopeTree::read_frame(frame, scope_collection);
+    let Some(scopes) = threads.get(thread_name) else {
+        let thread_names: Vec<_> = threads.keys().collect();
+        panic!(
+            "No thread {thread_name:?} in frame {}, only {thread_names:?}",
+            frame.frame_index()
+        );
+    };
+    let shape = shape(scopes);
+    assert_eq!(
+        shape,
+        expected_shape,
+        "Unexpected scopes in thread {thread_name:?} of frame {}",
+        frame.frame_index()
+    );
+}
+
+/// The details of the scope with this name, made up the first time it is asked for.
+///
+/// Used by [`FrameBuilder`], so that its frames agree on the scope ids.
+pub fn scope_details(name: &str) -> Arc<ScopeDetails> {
+    static SCOPES: Lazy<parking_lot::Mutex<HashMap<String, Arc<ScopeDetails>>>> =
+        Lazy::new(Default::default);
+    SCOPES
+        .lock()
+        .entry(name.to_owned())
+        .or_insert_with(|| {
+            Arc::new(
+                ScopeDetails::from_scope_id(crate::fetch_add_scope_id())
+                    .with_function_name(name.to_owned()),
+            )
+        })
+        .clone()
+}
+
+/// The id of the scope with this name in the frames of [`FrameBuilder`].
+pub fn scope_id(name: &str) -> ScopeId {
+    scope_details(name).scope_id.expect("the scope has an id")
+}
+
+/// The frames collected by [`collect_frames`], oldest first.
+pub type CollectedFrames = Arc<parking_lot::Mutex<Vec<Arc<FrameData>>>>;
+
+/// Collect every frame the profiler finishes from now on,
+/// e.g. `collect_frames(&mut GlobalProfiler::lock())` or `collect_frames(&mut profiler.lock())`
+/// for a [`crate::Profiler`].
+pub fn collect_frames(profiler: &mut GlobalProfiler) -> CollectedFrames {
+    let frames = CollectedFrames::default();
+    profiler.add_sink(Box::new({
+        let frames = frames.clone();
+        move |frame| frames.lock().push(frame)
+    }));
+    frames
+}
+
+/// Builds a [`FrameData`] from the [`ScopeTree`]s of each thread.
+pub struct FrameBuilder {
+    frame_index: FrameIndex,
+    threads: BTreeMap<ThreadInfo, Vec<ScopeTree>>,
+}
+
+impl FrameBuilder {
+    /// A frame with this index, and no threads yet.
+    pub fn new(frame_index: FrameIndex) -> Self {
+        Self {
+            frame_index,
+            threads: Default::default(),
+        }
+    }
+
+    /// Add a thread with these top scopes.
+    pub fn thread(
+        mut self,
+        thread_name: impl Into<String>,
+        scopes: impl IntoIterator<Item = ScopeTree>,
+    ) -> Self {
+        let thread_info = ThreadInfo {
+            name: thread_name.into(),
+            ..Default::default()
+        };
+        self.threads
+            .insert(thread_info, scopes.into_iter().collect());
+        self
+    }
+
+    /// Build the frame, with the [`scope_details`] of all its scopes in [`FrameData::scope_delta`].
+    ///
+    /// # Panics
+    /// If there are no scopes in the frame.
+    pub fn build(self) -> FrameData {
+        let mut scope_delta = BTreeMap::new();
+        let thread_streams = self
+            .threads
+            .into_iter()
+            .map(|(thread_info, scopes)| {
+                let mut stream = Stream::default();
+                for scope in &scopes {
+                    write_scope(&mut stream, scope, &mut scope_delta);
+                }
+                let stream_info = StreamInfo::parse(stream).expect("a valid stream");
+                (thread_info, stream_info)
+            })
+            .collect();
+        FrameData::new(
+            self.frame_index,
+            thread_streams,
+            scope_delta.into_values().collect(),
+            false,
+        )
+        .expect("a frame with at least one scope")
+    }
+}
+
+fn write_scope(
+    stream: &mut Stream,
+    scope: &ScopeTree,
+    scope_delta: &mut BTreeMap<String, Arc<ScopeDetails>>,
+) {
+    let details = scope_delta
+        .entry(scope.name.clone())
+        .or_insert_with(|| scope_details(&scope.name));
+    let scope_id = details.scope_id.expect("the scope has an id");
+    let (offset, _) = stream.begin_scope(|| scope.start_ns, scope_id, &scope.data);
+    for child in &scope.children {
+        write_scope(stream, child, scope_delta);
+    }
+    stream.end_scope(offset, scope.start_ns + scope.duration_ns);
+}
@@ -1,10 +1,17 @@
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 
 use crate::GlobalProfiler;
 use crate::NanoSecond;
 use crate::NsSource;
 
-use crate::fetch_add_scope_id;
+#[cfg(feature = "alloc-tracking")]
+use crate::allocations::{set_thread_allocations, thread_allocations};
+use crate::data::OpenScope;
+use crate::global_profiler::{NewFrameCounter, ReportSender, GLOBAL_NEW_FRAMES};
+#[cfg(feature = "alloc-tracking")]
+use crate::Allocations;
 use crate::ScopeDetails;
 use crate::ScopeId;
 use crate::StreamInfo;
@@ -12,12 +19,112 @@ use crate::StreamInfoRef;
 
 /// Report a stream of profile data from a thread to the [`GlobalProfiler`] singleton.
 /// This is used for internal purposes only
+///
+/// This doesn't lock the [`GlobalProfiler`], see [`GlobalProfiler::enqueue_report`].
 pub fn internal_profile_reporter(
     info: ThreadInfo,
     scope_details: &[ScopeDetails],
     stream_scope_times: &StreamInfoRef<'_>,
 ) {
-    GlobalProfiler::lock().report(info, scope_details, stream_scope_times);
+    GlobalProfiler::enqueue_report(info, scope_details, stream_scope_times);
+}
+
+/// What a thread does when its profile stream grows beyond [`ThreadMemoryLimit::max_bytes`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThreadMemoryPolicy {
+    /// Report what was recorded so far, with the open scopes marked as [`crate::Scope::in_progress`].
+    ///
+    /// The open scopes then continue in the next report.
+    /// Threads with open scopes also do this once per frame, once they notice that a new frame has started.
+    Flush,
+
+    /// Throw away the oldest finished scopes, and count them in [`crate::FrameMeta::num_dropped_scopes`].
+    ///
+    /// Nothing is reported until the outermost scope ends.
+    DropOldest,
+}
+
+/// Limits how much memory the profile stream of a thread may use while it has open scopes,
+/// e.g. when a worker thread runs a long loop inside of a profile scope.
+///
+/// Set for the current thread with [`set_thread_memory_limit`],
+/// or for all threads that don't set their own with [`set_default_thread_memory_limit`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ThreadMemoryLimit {
+    /// The maximum size of the stream of a thread, in bytes.
+    pub max_bytes: usize,
+
+    /// What to do when the stream grows beyond [`Self::max_bytes`].
+    pub policy: ThreadMemoryPolicy,
+}
+
+/// `usize::MAX` means no limit.
+static DEFAULT_MEMORY_LIMIT_MAX_BYTES: AtomicUsize = AtomicUsize::new(usize::MAX);
+static DEFAULT_MEMORY_LIMIT_DROP_OLDEST: AtomicBool = AtomicBool::new(false);
+
+/// Limit the memory used by the profile stream of every thread that hasn't set its own limit
+/// with [`set_thread_memory_limit`], or `None` for no limit.
+///
+/// This is [`None`] by default, so a thread that never closes its outermost scope
+/// keeps collecting profile data until it does.
+pub fn set_default_thread_memory_limit(limit: Option<ThreadMemoryLimit>) {
+    let (max_bytes, drop_oldest) = match limit {
+        Some(limit) => (
+            limit.max_bytes,
+            limit.policy == ThreadMemoryPolicy::DropOldest,
+        ),
+        None => (usize::MAX, false),
+    };
+    DEFAULT_MEMORY_LIMIT_DROP_OLDEST.store(drop_oldest, Ordering::Relaxed);
+    DEFAULT_MEMORY_LIMIT_MAX_BYTES.store(max_bytes, Ordering::Relaxed);
+}
+
+/// The limit set with [`set_default_thread_memory_limit`].
+pub fn default_thread_memory_limit() -> Option<ThreadMemoryLimit> {
+    let max_bytes = DEFAULT_MEMORY_LIMIT_MAX_BYTES.load(Ordering::Relaxed);
+    (max_bytes != usize::MAX).then(|| ThreadMemoryLimit {
+        max_bytes,
+        policy: if DEFAULT_MEMORY_LIMIT_DROP_OLDEST.load(Ordering::Relaxed) {
+            ThreadMemoryPolicy::DropOldest
+        } else {
+            ThreadMemoryPolicy::Flush
+        },
+    })
+}
+
+/// Limit the memory used by the profile stream of the current thread, or `None` for no limit,
+/// instead of using [`default_thread_memory_limit`].
+///
+/// This only applies to the [`GlobalProfiler`], see [`crate::Profiler::set_thread_memory_limit`]
+/// for a [`crate::Profiler`].
+pub fn set_thread_memory_limit(limit: Option<ThreadMemoryLimit>) {
+    ThreadProfiler::call(|tp| tp.set_memory_limit(limit));
+}
+
+static REPORT_OPEN_SCOPES: AtomicBool = AtomicBool::new(false);
+
+/// Report the scopes that are still open at the start of every frame,
+/// marked as [`crate::Scope::in_progress`], so that long-running scopes (e.g. loading)
+/// show up in every frame they span instead of only in the frame where they end.
+///
+/// [`crate::GlobalProfiler::new_frame`] does this right away for the calling thread.
+/// Other threads do it once they notice the new frame, i.e. when they next begin or end a scope.
+/// Each report continues where the previous one left off, so viewers can stitch the pieces together.
+///
+/// The open scopes of a thread can only be reported from that thread, so a thread that sits idle
+/// inside its open scopes (e.g. blocked waiting for work) is missing from the frames it sleeps through.
+/// What it recorded shows up once it begins or ends its next scope.
+///
+/// This is [`false`] by default.
+pub fn set_report_open_scopes(on: bool) {
+    REPORT_OPEN_SCOPES.store(on, Ordering::Relaxed);
+}
+
+/// Are open scopes reported at the start of every frame?
+///
+/// Turn on with [`set_report_open_scopes`].
+pub fn are_open_scopes_reported() -> bool {
+    REPORT_OPEN_SCOPES.load(Ordering::Relaxed)
 }
 
 /// Collects profiling data for one thread
@@ -26,9 +133,25 @@ pub struct ThreadProfiler {
     scope_details: Vec<ScopeDetails>,
     /// Current depth.
     depth: usize,
+    /// Where the scopes that haven't ended yet are in the stream, outermost first.
+    open_scopes: Vec<OpenScope>,
+    /// What the thread had allocated when each of the open scopes began, outermost first.
+    #[cfg(feature = "alloc-tracking")]
+    open_allocations: Vec<Allocations>,
+    /// The new frames of the profiler we report to.
+    new_frames: NewFrameCounter,
+    /// The value of `new_frames` when we last reported.
+    last_report_frame: u64,
     now_ns: NsSource,
     reporter: ThreadReporter,
+    /// Set for the threads of a [`crate::Profiler`], and then used instead of `reporter`.
+    report_sender: Option<ReportSender>,
+    /// Which scopes we have sent the details of, indexed by [`ScopeId`].
+    /// Only used for a [`crate::Profiler`].
+    registered_scopes: Vec<bool>,
     start_time_ns: Option<NanoSecond>,
+    /// Set with [`Self::set_memory_limit`], or `None` to use [`default_thread_memory_limit`].
+    memory_limit: Option<Option<ThreadMemoryLimit>>,
 }
 
 impl Default for ThreadProfiler {
@@ -37,9 +160,17 @@ impl Default for ThreadProfiler {
             stream_info: Default::default(),
             scope_details: Default::default(),
             depth: 0,
+            open_scopes: Default::default(),
+            #[cfg(feature = "alloc-tracking")]
+            open_allocations: Default::default(),
+            new_frames: GLOBAL_NEW_FRAMES.clone(),
+            last_report_frame: 0,
             now_ns: crate::now_ns,
             reporter: internal_profile_reporter,
+            report_sender: None,
+            registered_scopes: Default::default(),
             start_time_ns: None,
+            memory_limit: None,
         }
     }
 }
@@ -47,11 +178,10 @@ impl Default for ThreadProfiler {
 impl ThreadProfiler {
     /// Explicit initialize with custom callbacks.
     ///
-    /// If not called, each thread will use the default nanosecond source ([`crate::now_ns`])
+    /// If not called, each thread will use the clock of the profiler ([`crate::now_ns`])
     /// and report scopes to the global profiler ([`internal_profile_reporter`]).
     ///
-    /// For instance, when compiling for WASM the default timing function ([`crate::now_ns`]) won't work,
-    /// so you'll want to call `puffin::ThreadProfiler::initialize(my_timing_function, internal_profile_reporter);`.
+    /// To change the clock of all threads, use [`crate::set_clock`] instead.
     pub fn initialize(now_ns: NsSource, reporter: ThreadReporter) {
         ThreadProfiler::call(|tp| {
             tp.now_ns = now_ns;
@@ -59,6 +189,45 @@ impl ThreadProfiler {
         });
     }
 
+    /// Limit the memory used by the profile stream of this thread, or `None` for no limit,
+    /// instead of using [`default_thread_memory_limit`].
+    pub fn set_memory_limit(&mut self, limit: Option<ThreadMemoryLimit>) {
+        self.memory_limit = Some(limit);
+    }
+
+    /// The limit set with [`Self::set_memory_limit`], or else [`default_thread_memory_limit`].
+    pub fn memory_limit(&self) -> Option<ThreadMemoryLimit> {
+        self.memory_limit
+            .unwrap_or_else(default_thread_memory_limit)
+    }
+
+    /// Has the [`crate::Profiler`] this thread profiler reports to been dropped?
+    pub(crate) fn is_disconnected(&self) -> bool {
+        self.report_sender
+            .as_ref()
+            .is_some_and(|report_sender| !report_sender.is_connected())
+    }
+
+    /// The clock of this thread, see [`Self::initialize`].
+    pub(crate) fn now_ns_source(&self) -> NsSource {
+        self.now_ns
+    }
+
+    /// A thread profiler reporting to a [`crate::Profiler`].
+    ///
+    /// Uses the same nanosecond source as the thread's [`ThreadProfiler`].
+    pub(crate) fn with_report_sender(
+        report_sender: ReportSender,
+        new_frames: NewFrameCounter,
+    ) -> Self {
+        Self {
+            now_ns: Self::call(|tp| tp.now_ns),
+            report_sender: Some(report_sender),
+            new_frames,
+            ..Default::default()
+        }
+    }
+
     /// Register a function scope.
     #[must_use]
     pub fn register_function_scope(
@@ -67,14 +236,11 @@ impl ThreadProfiler {
         file_path: impl Into<Cow<'static, str>>,
         line_nr: u32,
     ) -> ScopeId {
-        let new_id = fetch_add_scope_id();
-        self.scope_details.push(
-            ScopeDetails::from_scope_id(new_id)
-                .with_function_name(function_name)
-                .with_file(file_path)
-                .with_line_nr(line_nr),
-        );
-        new_id
+        self.register_scope(ScopeDetails::new_function_scope(
+            function_name,
+            file_path,
+            line_nr,
+        ))
     }
 
     /// Register a named scope.
@@ -86,38 +252,150 @@ impl ThreadProfiler {
         file_path: impl Into<Cow<'static, str>>,
         line_nr: u32,
     ) -> ScopeId {
-        let new_id = fetch_add_scope_id();
-        self.scope_details.push(
-            ScopeDetails::from_scope_id(new_id)
-                .with_scope_name(scope_name)
-                .with_function_name(function_name)
-                .with_file(file_path)
-                .with_line_nr(line_nr),
-        );
-        new_id
+        self.register_scope(ScopeDetails::new_named_scope(
+            scope_name,
+            function_name,
+            file_path,
+            line_nr,
+        ))
+    }
+
+    /// Register a scope created with [`ScopeDetails::new_function_scope`] or [`ScopeDetails::new_named_scope`].
+    ///
+    /// Used by the profiler macros.
+    #[must_use]
+    pub fn register_scope(&mut self, scope_details: ScopeDetails) -> ScopeId {
+        let scope_id = scope_details
+            .scope_id
+            .expect("puffin allocated the scope id");
+        self.scope_details.push(scope_details);
+        scope_id
+    }
+
+    /// Sends the scope details along with the next report, unless they were sent before.
+    ///
+    /// Used for a [`crate::Profiler`], where the same scope can be used with many profilers.
+    pub(crate) fn register_scope_once(&mut self, scope_details: &ScopeDetails) -> ScopeId {
+        let scope_id = scope_details
+            .scope_id
+            .expect("`ScopeDetails` missing `ScopeId`");
+        let index = scope_id.0.get() as usize;
+        if self.registered_scopes.len() <= index {
+            self.registered_scopes.resize(index + 1, false);
+        }
+        if !self.registered_scopes[index] {
+            self.registered_scopes[index] = true;
+            self.scope_details.push(scope_details.clone());
+        }
+        scope_id
     }
 
     /// Marks the beginning of the scope.
     /// Returns position where to write scope size once the scope is closed.
     #[must_use]
     pub fn begin_scope(&mut self, scope_id: ScopeId, data: &str) -> usize {
-        self.depth += 1;
+        #[cfg(feature = "alloc-tracking")]
+        let allocations = thread_allocations();
+        self.before_begin_scope();
 
+        let begin_offset = self.stream_info.stream.len();
         let (offset, start_ns) = self
             .stream_info
             .stream
             .begin_scope(self.now_ns, scope_id, data);
+        self.push_open_scope(begin_offset, offset, start_ns);
+
+        #[cfg(feature = "alloc-tracking")]
+        self.after_begin_scope(allocations);
+
+        offset
+    }
+
+    /// Like [`Self::begin_scope`], but `data` is only stored once per report,
+    /// no matter how many scopes use it.
+    #[must_use]
+    pub fn begin_scope_interned(&mut self, scope_id: ScopeId, data: &str) -> usize {
+        #[cfg(feature = "alloc-tracking")]
+        let allocations = thread_allocations();
+        self.before_begin_scope();
+
+        let begin_offset = self.stream_info.stream.len();
+        let (offset, start_ns) =
+            self.stream_info
+                .stream
+                .begin_scope_interned(self.now_ns, scope_id, data);
+        self.push_open_scope(begin_offset, offset, start_ns);
+
+        #[cfg(feature = "alloc-tracking")]
+        self.after_begin_scope(allocations);
+
+        offset
+    }
+
+    /// Record a scope that has already ended, from `start_ns` to `stop_ns`.
+    ///
+    /// This is for scopes that are only worth recording once it is known how long they took,
+    /// e.g. waiting for a lock in [`crate::sync`]. The scope should not start before the open scopes.
+    pub fn record_scope(
+        &mut self,
+        scope_id: ScopeId,
+        data: &str,
+        start_ns: NanoSecond,
+        stop_ns: NanoSecond,
+    ) {
+        #[cfg(feature = "alloc-tracking")]
+        let allocations = thread_allocations();
+        self.before_begin_scope();
+
+        let begin_offset = self.stream_info.stream.len();
+        let (offset, _) = self
+            .stream_info
+            .stream
+            .begin_scope(|| start_ns, scope_id, data);
+        self.push_open_scope(begin_offset, offset, start_ns);
+
+        #[cfg(feature = "alloc-tracking")]
+        self.after_begin_scope(allocations);
+
+        self.end_scope_at(offset, stop_ns.max(start_ns));
+    }
+
+    fn before_begin_scope(&mut self) {
+        if self.depth == 0 {
+            // Only frames that start while we have open scopes are of interest.
+            self.last_report_frame = self.new_frames.get();
+        } else {
+            self.report_open_scopes_on_new_frame();
+        }
+        self.depth += 1;
+    }
+
+    fn push_open_scope(&mut self, begin_offset: usize, size_offset: usize, start_ns: NanoSecond) {
+        self.open_scopes.push(OpenScope {
+            begin_offset,
+            size_offset,
+        });
 
         self.stream_info.range_ns.0 = self.stream_info.range_ns.0.min(start_ns);
         self.start_time_ns = Some(self.start_time_ns.unwrap_or(start_ns));
+    }
 
-        offset
+    /// Leave out what the profiler allocated while beginning the scope.
+    #[cfg(feature = "alloc-tracking")]
+    fn after_begin_scope(&mut self, allocations: Allocations) {
+        self.open_allocations.push(allocations);
+        set_thread_allocations(allocations);
     }
 
     /// Marks the end of the scope.
     /// Returns the current depth.
     pub fn end_scope(&mut self, start_offset: usize) {
-        let now_ns = (self.now_ns)();
+        self.end_scope_at(start_offset, (self.now_ns)());
+    }
+
+    fn end_scope_at(&mut self, start_offset: usize, now_ns: NanoSecond) {
+        #[cfg(feature = "alloc-tracking")]
+        let allocations = thread_allocations();
         self.stream_info.depth = self.stream_info.depth.max(self.depth);
         self.stream_info.num_scopes += 1;
         self.stream_info.range_ns.1 = self.stream_info.range_ns.1.max(now_ns);
@@ -128,29 +406,150 @@ impl ThreadProfiler {
             eprintln!("puffin ERROR: Mismatched scope begin/end calls");
         }
 
+        // The stream may have been rearranged to limit its size, so trust our own offsets:
+        let start_offset = self
+            .open_scopes
+            .pop()
+            .map_or(start_offset, |open_scope| open_scope.size_offset);
+        #[cfg(feature = "alloc-tracking")]
+        {
+            let begin_allocations = self.open_allocations.pop().unwrap_or(allocations);
+            self.stream_info.stream.end_scope_with_allocations(
+                start_offset,
+                now_ns,
+                allocations.saturating_sub(begin_allocations),
+            );
+        }
+        #[cfg(not(feature = "alloc-tracking"))]
         self.stream_info.stream.end_scope(start_offset, now_ns);
 
         if self.depth == 0 {
             // We have no open scopes.
             // This is a good time to report our profiling stream to the global profiler:
-            let info = ThreadInfo {
-                start_time_ns: self.start_time_ns,
-                name: std::thread::current().name().unwrap_or_default().to_owned(),
-            };
-            (self.reporter)(
-                info,
-                &self.scope_details,
-                &self.stream_info.as_stream_into_ref(),
-            );
-
+            self.report(&self.stream_info.as_stream_into_ref());
             self.scope_details.clear();
             self.stream_info.clear();
+        } else {
+            if let Some(limit) = self.memory_limit() {
+                self.limit_memory(limit, now_ns);
+            }
+            self.report_open_scopes_on_new_frame();
+        }
+
+        #[cfg(feature = "alloc-tracking")]
+        set_thread_allocations(allocations);
+    }
+
+    /// If [`set_report_open_scopes`] is on and a new frame has started since we last reported,
+    /// report everything recorded so far, with the open scopes marked as in progress.
+    pub(crate) fn report_open_scopes_on_new_frame(&mut self) {
+        if are_open_scopes_reported() {
+            self.flush_on_new_frame();
         }
     }
 
+    /// If a new frame has started since we last reported, report everything recorded so far,
+    /// with the open scopes marked as in progress, like [`set_report_open_scopes`] does for every thread.
+    ///
+    /// For threads that run short tasks inside long scopes, e.g. the workers of a thread pool,
+    /// so that each task shows up in the frame it ran in.
+    pub fn flush_on_new_frame(&mut self) {
+        if self.depth > 0 && self.last_report_frame != self.new_frames.get() {
+            self.flush((self.now_ns)());
+        }
+    }
+
+    /// The id of the innermost scope that has not ended yet, if any.
+    pub fn current_scope_id(&self) -> Option<ScopeId> {
+        let open_scope = self.open_scopes.last()?;
+        self.stream_info.stream.open_scope_id(open_scope)
+    }
+
+    fn report(&self, stream_info: &StreamInfoRef<'_>) {
+        let info = THREAD_IDENTITY.with_borrow(|identity| ThreadInfo {
+            start_time_ns: self.start_time_ns,
+            name: std::thread::current().name().unwrap_or_default().to_owned(),
+            index: Some(identity.index),
+            os_id: identity.os_id,
+            display_name: identity.display_name.clone(),
+            group: identity.group.clone(),
+            sort_key: identity.sort_key,
+        });
+        match &self.report_sender {
+            Some(report_sender) => report_sender.send(info, &self.scope_details, stream_info),
+            None => (self.reporter)(info, &self.scope_details, stream_info),
+        }
+    }
+
+    fn limit_memory(&mut self, limit: ThreadMemoryLimit, now_ns: NanoSecond) {
+        let too_big = limit.max_bytes < self.stream_info.stream.len();
+        match limit.policy {
+            ThreadMemoryPolicy::Flush => {
+                let new_frame = self.last_report_frame != self.new_frames.get();
+                if too_big || new_frame {
+                    self.flush(now_ns);
+                }
+            }
+            ThreadMemoryPolicy::DropOldest => {
+                if too_big {
+                    match self
+                        .stream_info
+                        .stream
+                        .drop_oldest_scopes(&mut self.open_scopes, limit.max_bytes)
+                    {
+                        Ok(num_dropped) => {
+                            self.stream_info.num_scopes -= num_dropped;
+                            self.stream_info.num_dropped_scopes += num_dropped;
+                        }
+                        Err(err) => {
+                            eprintln!("puffin ERROR: Failed to drop old scopes: {err:?}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Report everything recorded so far, keeping the open scopes open.
+    fn flush(&mut self, now_ns: NanoSecond) {
+        // What the open scopes allocated so far, after which they start counting anew:
+        #[cfg(feature = "alloc-tracking")]
+        let allocations: Vec<Allocations> = {
+            let allocations = thread_allocations();
+            self.open_allocations
+                .iter_mut()
+                .map(|begin_allocations| {
+                    allocations.saturating_sub(std::mem::replace(begin_allocations, allocations))
+                })
+                .collect()
+        };
+        #[cfg(not(feature = "alloc-tracking"))]
+        let allocations = [];
+        let stream = self.stream_info.stream.split_off_in_progress(
+            &mut self.open_scopes,
+            &allocations,
+            now_ns,
+        );
+        let finished = StreamInfo {
+            stream,
+            num_scopes: self.stream_info.num_scopes + self.open_scopes.len(),
+            depth: self.stream_info.depth.max(self.open_scopes.len()),
+            range_ns: (self.stream_info.range_ns.0, now_ns),
+            num_dropped_scopes: self.stream_info.num_dropped_scopes,
+        };
+        self.report(&finished.as_stream_into_ref());
+        self.last_report_frame = self.new_frames.get();
+
+        self.scope_details.clear();
+        self.stream_info.num_scopes = 0;
+        self.stream_info.depth = 0;
+        self.stream_info.range_ns = (now_ns, NanoSecond::MIN);
+        self.stream_info.num_dropped_scopes = 0;
+    }
+
     /// Do something with the thread local [`ThreadProfiler`]
     #[inline]
-    pub fn call<R>(f: impl Fn(&mut Self) -> R) -> R {
+    pub fn call<R>(f: impl FnOnce(&mut Self) -> R) -> R {
         thread_local! {
             pub static THREAD_PROFILER: std::cell::RefCell<ThreadProfiler> = Default::default();
         }
@@ -159,13 +558,101 @@ impl ThreadProfiler {
 }
 
 /// Used to identify one source of profiling data.
-#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+///
+/// Frames tell threads apart by all of these, so they should not change while a thread is profiled.
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq, Ord, PartialOrd)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct ThreadInfo {
     /// Useful for ordering threads.
     pub start_time_ns: Option<NanoSecond>,
     /// Name of the thread
     pub name: String,
+    /// Given by puffin to each thread, counting from 0 in the order they are first seen,
+    /// so that threads with the same name (or none) can be told apart.
+    ///
+    /// `None` for sources of profiling data that are not threads, like [`crate::gpu::GpuTimeline`],
+    /// and in frames recorded before this was added.
+    pub index: Option<u32>,
+    /// The id the operating system gave the thread, on Linux and Android.
+    pub os_id: Option<u64>,
+    /// The name to show instead of [`Self::name`], see [`set_thread_name`].
+    pub display_name: Option<String>,
+    /// Threads of the same group are shown together, see [`set_thread_group`].
+    pub group: Option<String>,
+    /// Threads with lower keys are shown first, see [`set_thread_sort_key`].
+    pub sort_key: i64,
+}
+
+impl ThreadInfo {
+    /// What to call the thread: its [`Self::display_name`] or [`Self::name`],
+    /// or its [`Self::index`] if it has neither.
+    pub fn label(&self) -> Cow<'_, str> {
+        if let Some(display_name) = &self.display_name {
+            display_name.into()
+        } else if !self.name.is_empty() {
+            self.name.as_str().into()
+        } else if let Some(index) = self.index {
+            format!("thread {index}").into()
+        } else {
+            "".into()
+        }
+    }
+}
+
+static NEXT_THREAD_INDEX: AtomicU32 = AtomicU32::new(0);
+
+/// What is reported about the current thread besides its name.
+struct ThreadIdentity {
+    index: u32,
+    os_id: Option<u64>,
+    display_name: Option<String>,
+    group: Option<String>,
+    sort_key: i64,
+}
+
+impl ThreadIdentity {
+    fn new() -> Self {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let os_id = Some(rustix::thread::gettid().as_raw_nonzero().get() as u64);
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let os_id = None;
+        Self {
+            index: NEXT_THREAD_INDEX.fetch_add(1, Ordering::Relaxed),
+            os_id,
+            display_name: None,
+            group: None,
+            sort_key: 0,
+        }
+    }
+}
+
+thread_local! {
+    static THREAD_IDENTITY: RefCell<ThreadIdentity> = RefCell::new(ThreadIdentity::new());
+}
+
+/// Show the current thread as `name` in the profiler, instead of the name it was spawned with.
+///
+/// Useful for threads that were spawned without a name, or by code you don't control.
+/// Call this before the thread is profiled, since changing the [`ThreadInfo`] mid-frame
+/// shows the thread twice in that frame.
+pub fn set_thread_name(name: impl Into<String>) {
+    let name = name.into();
+    THREAD_IDENTITY.with_borrow_mut(|identity| identity.display_name = Some(name));
+}
+
+/// Show the current thread together with the other threads of `group`, e.g. the workers of a thread pool.
+///
+/// Like [`set_thread_name`], call this before the thread is profiled.
+pub fn set_thread_group(group: impl Into<String>) {
+    let group = group.into();
+    THREAD_IDENTITY.with_borrow_mut(|identity| identity.group = Some(group));
+}
+
+/// Show the current thread before the threads with a higher key, and after those with a lower one.
+///
+/// Threads have the key `0` by default. Like [`set_thread_name`], call this before the thread is profiled.
+pub fn set_thread_sort_key(sort_key: i64) {
+    THREAD_IDENTITY.with_borrow_mut(|identity| identity.sort_key = sort_key);
 }
 
 // Function interface for reporting thread local scope details.
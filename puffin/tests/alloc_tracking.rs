@@ -0,0 +1,59 @@
+//! In its own process, since it needs its own global allocator.
+#![cfg(all(feature = "alloc-tracking", not(feature = "disable")))]
+
+use puffin::{testing::collect_frames, GlobalProfiler, Reader, TrackingAllocator};
+
+#[global_allocator]
+static ALLOCATOR: TrackingAllocator = TrackingAllocator::new(std::alloc::System);
+
+fn allocate(num_bytes: usize) -> Vec<u8> {
+    puffin::profile_function!();
+    vec![0; num_bytes]
+}
+
+#[test]
+fn scope_allocations() {
+    puffin::set_scopes_on(true);
+    let frames = collect_frames(&mut GlobalProfiler::lock());
+
+    // The first frame also counts registering the scopes, so look at the second one:
+    for _ in 0..2 {
+        {
+            puffin::profile_scope!("outer");
+            let first = allocate(1000);
+            let second = allocate(24);
+            drop(first);
+            drop(second);
+        }
+        GlobalProfiler::lock().new_frame();
+    }
+
+    let frames = frames.lock();
+    assert_eq!(frames.len(), 2);
+    let unpacked = frames[1].unpacked().ok().unwrap();
+    let (_, stream_info) = unpacked.thread_streams.iter().next().unwrap();
+    let stream = &stream_info.stream;
+
+    let top = Reader::from_start(stream).read_top_scopes().unwrap();
+    assert_eq!(top.len(), 1);
+    let outer = top[0].allocations;
+    assert_eq!((outer.alloc_count, outer.alloc_bytes), (2, 1024));
+    assert_eq!((outer.dealloc_count, outer.dealloc_bytes), (2, 1024));
+
+    let children = Reader::with_parent(stream, &top[0])
+        .unwrap()
+        .read_top_scopes()
+        .unwrap();
+    let allocated: Vec<_> = children
+        .iter()
+        .map(|child| {
+            let allocations = child.allocations;
+            (allocations.alloc_count, allocations.alloc_bytes)
+        })
+        .collect();
+    assert_eq!(allocated, [(1, 1000), (1, 24)]);
+    // The vectors were dropped in the outer scope:
+    assert!(children
+        .iter()
+        .all(|child| child.allocations.dealloc_count == 0));
+}
@@ -0,0 +1,44 @@
+//! In its own process, since the clock is global.
+#![cfg(not(feature = "disable"))]
+
+use puffin::{
+    testing::collect_frames, ClockAlreadySet, ClockKind, GlobalProfiler, MockClock, Reader,
+};
+
+fn work() {
+    puffin::profile_function!();
+    puffin::profile_scope!("inner");
+}
+
+#[test]
+fn mock_clock() {
+    let clock = MockClock::new(1_000);
+    clock.set_step(10);
+    puffin::set_clock(clock.clone()).unwrap();
+    assert_eq!(puffin::set_clock(MockClock::new(0)), Err(ClockAlreadySet));
+    assert_eq!(puffin::clock_kind(), ClockKind::Mock);
+
+    puffin::set_scopes_on(true);
+    let frames = collect_frames(&mut GlobalProfiler::lock());
+
+    work();
+    clock.advance(1_000);
+    work();
+    GlobalProfiler::lock().new_frame();
+
+    let frames = frames.lock();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].meta().clock, ClockKind::Mock);
+
+    let unpacked = frames[0].unpacked().ok().unwrap();
+    let (_, stream_info) = unpacked.thread_streams.iter().next().unwrap();
+    let top_scopes = Reader::from_start(&stream_info.stream)
+        .read_top_scopes()
+        .unwrap();
+    let ranges: Vec<_> = top_scopes
+        .iter()
+        .map(|scope| (scope.record.start_ns, scope.record.stop_ns()))
+        .collect();
+    // Each reading of the clock moves it 10 ns forward:
+    assert_eq!(ranges, [(1_000, 1_030), (2_040, 2_070)]);
+}
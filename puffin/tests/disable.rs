@@ -0,0 +1,75 @@
+//! In its own process, since only this test runs with the `disable` feature:
+//! `cargo test -p puffin --features disable --test disable`
+#![cfg(feature = "disable")]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use puffin::{testing::collect_frames, GlobalProfiler, Profiler};
+
+static NUM_EVALUATIONS: AtomicUsize = AtomicUsize::new(0);
+
+fn evaluated<T>(value: T) -> T {
+    NUM_EVALUATIONS.fetch_add(1, Ordering::Relaxed);
+    value
+}
+
+fn profiled(profiler: &Profiler) {
+    puffin::profile_function!();
+    puffin::profile_function!(evaluated("data"));
+    puffin::profile_function!(category = evaluated("io"), "data");
+    puffin::profile_function_if!(evaluated(true));
+    puffin::profile_scope!("scope");
+    puffin::profile_scope!("scope", evaluated(String::from("data")));
+    puffin::profile_scope!("scope", category = "io");
+    puffin::profile_scope_if!(evaluated(true), "scope");
+    puffin::profile_function_in!(profiler);
+    puffin::profile_scope_in!(profiler, "scope", evaluated("data"));
+
+    let scope = puffin::profile_scope_custom!(evaluated("scope"));
+    assert!(scope.is_none());
+    let scope = puffin::profile_scope_custom_if!(evaluated(true), "scope", "data");
+    assert!(scope.is_none());
+}
+
+#[cfg(feature = "macros")]
+#[puffin::profile(name = "scope", category = evaluated("io"), data = evaluated("data"), if = evaluated(true))]
+fn attributed() {}
+
+#[cfg(feature = "macros")]
+#[puffin::profile(data = evaluated("data"))]
+async fn attributed_async() -> usize {
+    1
+}
+
+#[cfg(feature = "macros")]
+struct NoopWaker;
+
+#[cfg(feature = "macros")]
+impl std::task::Wake for NoopWaker {
+    fn wake(self: std::sync::Arc<Self>) {}
+}
+
+#[test]
+fn macros_compile_away() {
+    puffin::set_scopes_on(true);
+
+    let frames = collect_frames(&mut GlobalProfiler::lock());
+    let profiler = Profiler::new();
+    let profiler_frames = collect_frames(&mut profiler.lock());
+
+    profiled(&profiler);
+    #[cfg(feature = "macros")]
+    {
+        attributed();
+        let future = std::pin::pin!(attributed_async());
+        let waker = std::task::Waker::from(std::sync::Arc::new(NoopWaker));
+        let poll = std::future::Future::poll(future, &mut std::task::Context::from_waker(&waker));
+        assert_eq!(poll, std::task::Poll::Ready(1));
+    }
+    GlobalProfiler::lock().new_frame();
+    profiler.new_frame();
+
+    assert!(frames.lock().is_empty());
+    assert!(profiler_frames.lock().is_empty());
+    assert_eq!(NUM_EVALUATIONS.load(Ordering::Relaxed), 0);
+}
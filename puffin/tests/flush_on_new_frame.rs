@@ -0,0 +1,42 @@
+#![cfg(not(feature = "disable"))]
+
+use puffin::{testing::assert_frame_shape, GlobalFrameView, GlobalProfiler, ThreadProfiler};
+
+fn task() -> Option<puffin::ScopeId> {
+    puffin::profile_scope!("task");
+    ThreadProfiler::call(|tp| tp.current_scope_id())
+}
+
+#[test]
+fn flush_on_new_frame() {
+    puffin::set_scopes_on(true);
+    let frame_view = GlobalFrameView::default();
+    assert_eq!(ThreadProfiler::call(|tp| tp.current_scope_id()), None);
+
+    let task_id = {
+        puffin::profile_scope!("job");
+        let task_id = task();
+        // Nothing to report before the first frame:
+        ThreadProfiler::call(|tp| tp.flush_on_new_frame());
+        GlobalProfiler::lock().new_frame();
+        assert!(frame_view.lock().latest_frame().is_none());
+
+        task();
+        ThreadProfiler::call(|tp| tp.flush_on_new_frame());
+        GlobalProfiler::lock().new_frame();
+        task_id
+    };
+
+    let frame_view = frame_view.lock();
+    let frame = frame_view.latest_frame().unwrap();
+    assert_frame_shape(
+        &frame,
+        frame_view.scope_collection(),
+        "flush_on_new_frame",
+        "job(task, task)",
+    );
+    assert_eq!(
+        task_id.as_ref(),
+        frame_view.scope_collection().fetch_by_name("task")
+    );
+}
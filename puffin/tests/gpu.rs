@@ -0,0 +1,61 @@
+use puffin::{
+    gpu::{GpuCalibration, GpuTimeline, MockGpu},
+    testing::{assert_frame_shape, ScopeTree},
+    GlobalFrameView, GlobalProfiler, ScopeDetails,
+};
+
+#[test]
+fn report_gpu_scopes() {
+    let frame_view = GlobalFrameView::default();
+    let scope_ids = GlobalProfiler::lock().register_user_scopes(&[
+        ScopeDetails::from_scope_name("render"),
+        ScopeDetails::from_scope_name("shadows"),
+        ScopeDetails::from_scope_name("lighting"),
+    ]);
+
+    let mut gpu = MockGpu::new(1_000_000);
+    let mut timeline = GpuTimeline::new("GPU");
+
+    let render = timeline.begin_scope(scope_ids[0], "main view");
+    gpu.write_timestamp(render.begin_query);
+    for (scope_id, duration_ns) in [(scope_ids[1], 300), (scope_ids[2], 500)] {
+        let pass = timeline.begin_scope(scope_id, "");
+        gpu.write_timestamp(pass.begin_query);
+        gpu.execute(duration_ns);
+        gpu.write_timestamp(pass.end_query);
+        timeline.end_scope(pass);
+    }
+    gpu.write_timestamp(render.end_query);
+    timeline.end_scope(render);
+
+    let queries = timeline.finish();
+    let timestamps = gpu.read_timestamps(queries.num_queries()).unwrap();
+    let calibration = GpuCalibration {
+        gpu_ns: 1_000_000,
+        cpu_ns: 5_000,
+    };
+    queries
+        .report(&mut GlobalProfiler::lock(), &timestamps, calibration)
+        .unwrap();
+    GlobalProfiler::lock().new_frame();
+
+    let frame_view = frame_view.lock();
+    let frame = frame_view.latest_frame().unwrap();
+    assert_frame_shape(
+        &frame,
+        frame_view.scope_collection(),
+        "GPU",
+        "render(shadows, lighting)",
+    );
+
+    let threads = ScopeTree::read_frame(&frame, frame_view.scope_collection());
+    assert_eq!(
+        threads["GPU"],
+        [ScopeTree::new("render", 5_000, 800)
+            .with_data("main view")
+            .with_children([
+                ScopeTree::new("shadows", 5_000, 300),
+                ScopeTree::new("lighting", 5_300, 500),
+            ])]
+    );
+}
@@ -0,0 +1,80 @@
+#![cfg(not(feature = "disable"))]
+
+use puffin::{testing::collect_frames, Profiler, Reader, ThreadMemoryLimit, ThreadMemoryPolicy};
+
+/// A long-running loop inside of a scope, with a new frame every 100 iterations.
+fn run_loop(profiler: &Profiler) {
+    puffin::profile_scope_in!(profiler, "loop");
+    for i in 0..1000 {
+        puffin::profile_scope_in!(profiler, "iteration");
+        if i % 100 == 99 {
+            profiler.new_frame();
+        }
+    }
+}
+
+#[test]
+fn memory_limit() {
+    puffin::set_scopes_on(true);
+
+    // Flush, for all threads:
+    puffin::set_default_thread_memory_limit(Some(ThreadMemoryLimit {
+        max_bytes: 1_000,
+        policy: ThreadMemoryPolicy::Flush,
+    }));
+    let profiler = Profiler::new();
+    let frames = collect_frames(&mut profiler.lock());
+    run_loop(&profiler);
+    profiler.new_frame();
+
+    let frames = frames.lock();
+    assert_eq!(frames.len(), 11);
+    let num_scopes: usize = frames.iter().map(|frame| frame.meta().num_scopes).sum();
+    assert!(1001 < num_scopes, "The loop scope is split up");
+    for frame in frames.iter() {
+        assert_eq!(frame.meta().num_dropped_scopes, 0);
+        let unpacked = frame.unpacked().ok().unwrap();
+        let stream_info = unpacked.thread_streams.values().next().unwrap();
+        let top_scopes = Reader::from_start(&stream_info.stream)
+            .read_top_scopes()
+            .unwrap();
+        // All but the last piece of the loop scope continue in the next report:
+        let (last, rest) = top_scopes.split_last().unwrap();
+        assert!(rest.iter().all(|scope| scope.in_progress));
+        assert_eq!(last.in_progress, frame.frame_index() != 10);
+    }
+
+    // Drop oldest, for this thread only:
+    let profiler = Profiler::new();
+    profiler.set_thread_memory_limit(Some(ThreadMemoryLimit {
+        max_bytes: 1_000,
+        policy: ThreadMemoryPolicy::DropOldest,
+    }));
+    let frames = collect_frames(&mut profiler.lock());
+    run_loop(&profiler);
+    profiler.new_frame();
+
+    let meta = {
+        let frames = frames.lock();
+        assert_eq!(frames.len(), 1, "Nothing is reported until the loop ends");
+        *frames[0].meta()
+    };
+    assert!(0 < meta.num_dropped_scopes);
+    assert_eq!(meta.num_scopes + meta.num_dropped_scopes, 1001);
+
+    // Other threads keep using the default:
+    puffin::set_default_thread_memory_limit(None);
+    let frames = collect_frames(&mut profiler.lock());
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .spawn_scoped(scope, || run_loop(&profiler))
+            .unwrap();
+    });
+    profiler.new_frame();
+
+    let frames = frames.lock();
+    assert_eq!(frames.len(), 1, "Nothing is reported until the loop ends");
+    let meta = frames[0].meta();
+    assert_eq!(meta.num_dropped_scopes, 0);
+    assert_eq!(meta.num_scopes, 1001);
+}
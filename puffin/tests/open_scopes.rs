@@ -0,0 +1,128 @@
+#![cfg(not(feature = "disable"))]
+
+use std::sync::mpsc;
+
+use puffin::{testing::collect_frames, FrameData, NanoSecond, Profiler, Reader, ScopeId};
+
+/// The id, `in_progress` and time range of a top scope.
+type TopScope = (ScopeId, bool, NanoSecond, NanoSecond);
+
+/// The top scopes of each thread, by thread name.
+fn top_scopes(frame: &FrameData) -> Vec<(String, Vec<TopScope>)> {
+    let unpacked = frame.unpacked().ok().unwrap();
+    unpacked
+        .thread_streams
+        .iter()
+        .map(|(info, stream_info)| {
+            let scopes = Reader::from_start(&stream_info.stream)
+                .read_top_scopes()
+                .unwrap()
+                .into_iter()
+                .map(|scope| {
+                    (
+                        scope.id,
+                        scope.in_progress,
+                        scope.record.start_ns,
+                        scope.record.stop_ns(),
+                    )
+                })
+                .collect();
+            (info.name.clone(), scopes)
+        })
+        .collect()
+}
+
+#[test]
+fn open_scopes() {
+    puffin::set_scopes_on(true);
+    puffin::set_report_open_scopes(true);
+
+    let profiler = Profiler::new();
+    let frames = collect_frames(&mut profiler.lock());
+
+    let (started_tx, started_rx) = mpsc::channel();
+    let (step_tx, step_rx) = mpsc::channel::<()>();
+    let (stepped_tx, stepped_rx) = mpsc::channel();
+    let worker = std::thread::Builder::new()
+        .name("worker".to_owned())
+        .spawn({
+            let profiler = profiler.clone();
+            move || {
+                puffin::profile_scope_in!(profiler, "loading");
+                started_tx.send(()).unwrap();
+                for () in step_rx {
+                    {
+                        puffin::profile_scope_in!(profiler, "step");
+                    }
+                    stepped_tx.send(()).unwrap();
+                }
+            }
+        })
+        .unwrap();
+    started_rx.recv().unwrap();
+
+    {
+        puffin::profile_scope_in!(profiler, "main");
+        for _ in 0..3 {
+            profiler.new_frame();
+            step_tx.send(()).unwrap();
+            stepped_rx.recv().unwrap();
+        }
+        // The worker sits idle:
+        profiler.new_frame();
+        profiler.new_frame();
+    }
+    drop(step_tx);
+    worker.join().unwrap();
+    profiler.new_frame();
+
+    let frames = frames.lock();
+    assert_eq!(frames.len(), 6);
+
+    // The calling thread reports its open scopes right away:
+    for (index, frame) in frames.iter().enumerate() {
+        let threads = top_scopes(frame);
+        let (_, main) = threads.iter().find(|(name, _)| name != "worker").unwrap();
+        assert_eq!(main.len(), 1);
+        assert_eq!(main[0].1, index < 5);
+    }
+
+    // The worker reports when it begins its next scope after the new frame,
+    // which ends up in the next frame, and not at all while it is idle:
+    let worker_in_progress: Vec<Vec<bool>> = frames
+        .iter()
+        .map(|frame| {
+            top_scopes(frame)
+                .into_iter()
+                .filter(|(name, _)| name == "worker")
+                .flat_map(|(_, scopes)| scopes)
+                .map(|(_, in_progress, _, _)| in_progress)
+                .collect()
+        })
+        .collect();
+    assert_eq!(
+        worker_in_progress,
+        [
+            vec![],
+            vec![true],
+            vec![true],
+            vec![true],
+            vec![],
+            vec![false]
+        ]
+    );
+
+    // Each piece continues where the previous one ended:
+    let worker_pieces: Vec<_> = frames
+        .iter()
+        .flat_map(|frame| top_scopes(frame))
+        .filter(|(name, _)| name == "worker")
+        .flat_map(|(_, scopes)| scopes)
+        .collect();
+    for pair in worker_pieces.windows(2) {
+        assert_eq!(pair[0].0, pair[1].0);
+        assert_eq!(pair[0].3, pair[1].2);
+    }
+
+    puffin::set_report_open_scopes(false);
+}
@@ -0,0 +1,160 @@
+#![cfg(all(feature = "macros", not(feature = "disable")))]
+
+use std::{
+    future::Future,
+    pin::pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake, Waker},
+};
+
+use puffin::{testing::collect_frames, GlobalFrameView, GlobalProfiler, Reader};
+
+#[puffin::profile]
+fn update() {}
+
+#[puffin::profile(name = "load", category = "io", data = path.to_owned())]
+fn load(path: &str) -> usize {
+    path.len()
+}
+
+#[puffin::profile(if = profiled)]
+fn sometimes(profiled: bool) {}
+
+trait Shape {
+    fn area(&self) -> f32;
+}
+
+struct Square(f32);
+
+impl Shape for Square {
+    #[puffin::profile]
+    fn area(&self) -> f32 {
+        self.0 * self.0
+    }
+}
+
+/// Returns `Pending` the first time it is polled.
+struct YieldOnce(bool);
+
+impl Future for YieldOnce {
+    type Output = ();
+
+    fn poll(mut self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[puffin::profile(data = format!("{bytes} bytes"))]
+async fn download(bytes: usize) -> Result<usize, std::num::ParseIntError> {
+    YieldOnce(false).await;
+    let parsed: usize = "2".parse()?;
+    YieldOnce(false).await;
+    Ok(bytes * parsed)
+}
+
+static NUM_EVALUATIONS: AtomicUsize = AtomicUsize::new(0);
+
+fn evaluated<T>(value: T) -> T {
+    NUM_EVALUATIONS.fetch_add(1, Ordering::Relaxed);
+    value
+}
+
+#[puffin::profile(if = evaluated(true), data = evaluated("data"))]
+async fn wait() {
+    YieldOnce(false).await;
+}
+
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+#[test]
+fn profile_attribute() {
+    puffin::set_scopes_on(true);
+
+    let frame_view = GlobalFrameView::default();
+    let frames = collect_frames(&mut GlobalProfiler::lock());
+
+    update();
+    assert_eq!(load("tree.obj"), 8);
+    sometimes(true);
+    sometimes(false);
+    assert_eq!(Square(2.0).area(), 4.0);
+    assert_eq!(block_on(download(100)), Ok(200));
+    GlobalProfiler::lock().new_frame();
+
+    let frames = frames.lock();
+    assert_eq!(frames.len(), 1);
+    let unpacked = frames[0].unpacked().ok().unwrap();
+    assert_eq!(unpacked.thread_streams.len(), 1);
+    let (_, stream_info) = unpacked.thread_streams.iter().next().unwrap();
+
+    let frame_view = frame_view.lock();
+    let scopes: Vec<_> = Reader::from_start(&stream_info.stream)
+        .read_top_scopes()
+        .unwrap()
+        .into_iter()
+        .map(|scope| {
+            let details = frame_view
+                .scope_collection()
+                .fetch_by_id(&scope.id)
+                .unwrap();
+            (
+                details.name().to_string(),
+                details.category.as_deref().map(str::to_owned),
+                scope.record.data.to_owned(),
+            )
+        })
+        .collect();
+
+    let scope = |name: &str, category: Option<&str>, data: &str| {
+        (
+            name.to_owned(),
+            category.map(str::to_owned),
+            data.to_owned(),
+        )
+    };
+    assert_eq!(
+        scopes,
+        [
+            scope("profile_attribute::update", None, ""),
+            scope("load", Some("io"), "tree.obj"),
+            scope("profile_attribute::sometimes", None, ""),
+            scope("<Square as Shape>::area", None, ""),
+            // One scope for each poll of the `async fn`:
+            scope("profile_attribute::download", None, "100 bytes"),
+            scope("profile_attribute::download", None, "100 bytes"),
+            scope("profile_attribute::download", None, "100 bytes"),
+        ]
+    );
+
+    // Not even the condition and data are evaluated when profiling is off:
+    puffin::set_scopes_on(false);
+    block_on(wait());
+    assert_eq!(NUM_EVALUATIONS.load(Ordering::Relaxed), 0);
+    puffin::set_scopes_on(true);
+    block_on(wait());
+    assert_eq!(NUM_EVALUATIONS.load(Ordering::Relaxed), 2);
+}
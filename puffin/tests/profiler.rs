@@ -0,0 +1,137 @@
+#![cfg(not(feature = "disable"))]
+
+use puffin::{
+    testing::collect_frames, FrameData, Profiler, Reader, ThreadMemoryLimit, ThreadMemoryPolicy,
+};
+
+fn work(profiler: &Profiler) {
+    puffin::profile_function_in!(profiler);
+    puffin::profile_scope_in!(profiler, "inner", "data");
+}
+
+#[test]
+fn separate_profilers() {
+    puffin::set_scopes_on(true);
+
+    let render = Profiler::new();
+    let simulation = Profiler::new();
+    let render_frames = collect_frames(&mut render.lock());
+    let simulation_frames = collect_frames(&mut simulation.lock());
+
+    work(&render);
+    work(&render);
+    work(&simulation);
+    std::thread::Builder::new()
+        .spawn({
+            let simulation = simulation.clone();
+            move || work(&simulation)
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+
+    render.new_frame();
+    simulation.new_frame();
+    puffin::GlobalProfiler::lock().new_frame();
+
+    let render_frames = render_frames.lock();
+    assert_eq!(render_frames.len(), 1);
+    assert_eq!(render_frames[0].meta().num_scopes, 4);
+    assert_eq!(render_frames[0].scope_delta.len(), 2);
+
+    // Both profilers need the details of the same scopes:
+    let simulation_frames = simulation_frames.lock();
+    assert_eq!(simulation_frames.len(), 1);
+    assert_eq!(simulation_frames[0].meta().num_scopes, 4);
+    let mut scope_names: Vec<_> = simulation_frames[0]
+        .scope_delta
+        .iter()
+        .map(|scope| scope.name().to_string())
+        .collect();
+    scope_names.sort();
+    scope_names.dedup();
+    assert_eq!(scope_names, ["inner", "profiler::work"]);
+
+    // One stream per thread:
+    let unpacked = simulation_frames[0].unpacked().ok().unwrap();
+    assert_eq!(unpacked.thread_streams.len(), 2);
+}
+
+#[test]
+fn separate_frame_boundaries() {
+    puffin::set_scopes_on(true);
+
+    let render = Profiler::new();
+    let simulation = Profiler::new();
+    let render_frames = collect_frames(&mut render.lock());
+
+    // Flushes the open scopes when a new render frame starts, but not a new simulation frame:
+    render.set_thread_memory_limit(Some(ThreadMemoryLimit {
+        max_bytes: usize::MAX,
+        policy: ThreadMemoryPolicy::Flush,
+    }));
+    {
+        puffin::profile_scope_in!(render, "outer");
+        for _ in 0..3 {
+            work(&render);
+            simulation.new_frame();
+        }
+    }
+    render.new_frame();
+
+    let render_frames = render_frames.lock();
+    assert_eq!(render_frames.len(), 1);
+    let unpacked = render_frames[0].unpacked().ok().unwrap();
+    let stream_info = unpacked.thread_streams.values().next().unwrap();
+    let top_scopes = Reader::from_start(&stream_info.stream)
+        .read_top_scopes()
+        .unwrap();
+    assert_eq!(top_scopes.len(), 1, "The outer scope is in one piece");
+    assert!(!top_scopes[0].in_progress);
+}
+
+fn load_mesh(profiler: &Profiler) {
+    puffin::profile_function_in!(profiler, category = "io");
+    puffin::profile_scope_in!(profiler, "parse", category = "cpu", "tree.obj");
+    puffin::profile_scope_in!(profiler, "upload");
+}
+
+#[test]
+fn categories() {
+    puffin::set_scopes_on(true);
+
+    let profiler = Profiler::new();
+    let frames = collect_frames(&mut profiler.lock());
+    load_mesh(&profiler);
+    profiler.new_frame();
+
+    let frames = frames.lock();
+    let categories = |frame: &FrameData| {
+        let mut categories: Vec<_> = frame
+            .scope_delta
+            .iter()
+            .map(|scope| (scope.name().to_string(), scope.category.clone()))
+            .collect();
+        categories.sort();
+        categories
+    };
+    let expected = [
+        ("parse".to_owned(), Some("cpu".into())),
+        ("profiler::load_mesh".to_owned(), Some("io".into())),
+        ("upload".to_owned(), None),
+    ];
+    assert_eq!(categories(&frames[0]), expected);
+
+    #[cfg(feature = "serialization")]
+    {
+        let mut bytes = vec![];
+        frames[0]
+            .write_into(&Default::default(), false, &mut bytes)
+            .unwrap();
+        let frame = FrameData::read_next(&mut bytes.as_slice())
+            .unwrap()
+            .unwrap();
+        assert_eq!(categories(&frame), expected);
+        assert_eq!(frame.meta().clock, puffin::ClockKind::Instant);
+    }
+}
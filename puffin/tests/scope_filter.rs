@@ -0,0 +1,51 @@
+#![cfg(not(feature = "disable"))]
+
+use puffin::{testing::collect_frames, GlobalProfiler, Profiler, ScopeFilter};
+
+fn render(profiler: &Profiler) {
+    puffin::profile_function_in!(profiler);
+    puffin::profile_scope_in!(profiler, "draw_meshes");
+}
+
+fn simulate(profiler: &Profiler) {
+    puffin::profile_function_in!(profiler);
+}
+
+/// The number of scopes in a new frame.
+fn count_scopes(profiler: &Profiler) -> usize {
+    let frames = collect_frames(&mut profiler.lock());
+    render(profiler);
+    simulate(profiler);
+    profiler.new_frame();
+    let num_scopes = frames.lock().iter().map(|f| f.meta().num_scopes).sum();
+    num_scopes
+}
+
+#[test]
+fn scope_filter() {
+    puffin::set_scopes_on(true);
+
+    assert_eq!(count_scopes(&Profiler::new()), 3);
+
+    let render_scopes = ScopeFilter::FunctionPrefix("scope_filter::render".into());
+    GlobalProfiler::set_scopes_enabled(render_scopes.clone(), false);
+    assert_eq!(count_scopes(&Profiler::new()), 1);
+
+    // Later filters take precedence:
+    GlobalProfiler::set_scopes_enabled(ScopeFilter::Name("draw_meshes".into()), true);
+    assert_eq!(count_scopes(&Profiler::new()), 2);
+    assert_eq!(GlobalProfiler::scope_filters().len(), 2);
+
+    // Setting the same filter again moves it to the end:
+    GlobalProfiler::set_scopes_enabled(render_scopes.clone(), false);
+    assert_eq!(count_scopes(&Profiler::new()), 1);
+
+    GlobalProfiler::remove_scope_filter(&render_scopes);
+    assert_eq!(count_scopes(&Profiler::new()), 3);
+
+    GlobalProfiler::set_scopes_enabled(ScopeFilter::File("tests/scope_filter.rs".into()), false);
+    assert_eq!(count_scopes(&Profiler::new()), 0);
+
+    GlobalProfiler::clear_scope_filters();
+    assert_eq!(count_scopes(&Profiler::new()), 3);
+}
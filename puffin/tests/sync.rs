@@ -0,0 +1,72 @@
+#![cfg(not(feature = "disable"))]
+
+use std::{sync::mpsc, time::Duration};
+
+use puffin::{
+    sync::{Mutex, RwLock},
+    testing::{assert_frame_shape, ScopeTree},
+    GlobalFrameView, GlobalProfiler,
+};
+
+static TEXTURES: Mutex<Vec<&str>> = Mutex::new("textures", Vec::new());
+static SCENE: RwLock<u32> = RwLock::new("scene", 0);
+
+#[test]
+fn record_lock_waits() {
+    puffin::set_scopes_on(true);
+    let frame_view = GlobalFrameView::default();
+
+    let textures = TEXTURES.lock();
+    let scene = SCENE.write();
+    let (sender, receiver) = mpsc::channel();
+    let waiter = std::thread::Builder::new()
+        .name("waiter".to_owned())
+        .spawn(move || {
+            puffin::profile_scope!("work");
+            sender.send(()).unwrap();
+            TEXTURES.lock().push("grass.png");
+            let _ = *SCENE.read();
+            // Nobody holds the locks any more, so this records nothing:
+            TEXTURES.lock().push("sky.png");
+            *SCENE.write() += 1;
+        })
+        .unwrap();
+
+    receiver.recv().unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    drop(textures);
+    std::thread::sleep(Duration::from_millis(20));
+    drop(scene);
+    waiter.join().unwrap();
+    GlobalProfiler::lock().new_frame();
+
+    assert_eq!(*TEXTURES.lock(), ["grass.png", "sky.png"]);
+    assert_eq!(*SCENE.read(), 1);
+
+    let frame_view = frame_view.lock();
+    let frame = frame_view.latest_frame().unwrap();
+    assert_frame_shape(
+        &frame,
+        frame_view.scope_collection(),
+        "waiter",
+        "work(waiting for lock, waiting for read lock)",
+    );
+
+    let threads = ScopeTree::read_frame(&frame, frame_view.scope_collection());
+    let waits = &threads["waiter"][0].children;
+    assert_eq!(waits[0].data, "textures");
+    assert_eq!(waits[1].data, "scene");
+    for wait in waits {
+        assert!(wait.duration_ns >= puffin::sync::wait_threshold_ns());
+    }
+
+    let details = frame_view
+        .scope_collection()
+        .fetch_by_name("waiting for lock")
+        .and_then(|scope_id| frame_view.scope_collection().fetch_by_id(scope_id))
+        .unwrap();
+    assert_eq!(
+        details.category.as_deref(),
+        Some(puffin::sync::WAIT_CATEGORY)
+    );
+}
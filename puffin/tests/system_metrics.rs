@@ -0,0 +1,70 @@
+#![cfg(not(feature = "disable"))]
+
+use puffin::{GlobalFrameView, GlobalProfiler};
+
+fn profile_frame(work: impl FnOnce()) {
+    {
+        puffin::profile_scope!("work");
+        work();
+    }
+    GlobalProfiler::lock().new_frame();
+}
+
+#[test]
+fn system_metrics() {
+    puffin::set_scopes_on(true);
+    let frame_view = GlobalFrameView::default();
+
+    profile_frame(|| {});
+    let frame = frame_view.lock().latest_frame().unwrap();
+    assert_eq!(frame.meta().system_metrics, None, "Off by default");
+
+    GlobalProfiler::lock().set_system_metrics(true);
+    assert!(GlobalProfiler::lock().system_metrics());
+    profile_frame(|| {});
+    let frame = frame_view.lock().latest_frame().unwrap();
+    assert_eq!(
+        frame.meta().system_metrics,
+        None,
+        "Nothing to compare the first sample to"
+    );
+
+    let memory = vec![1_u8; 16 << 20];
+    profile_frame(|| std::thread::sleep(std::time::Duration::from_millis(30)));
+    let frame = frame_view.lock().latest_frame().unwrap();
+    let metrics = frame.meta().system_metrics;
+    if cfg!(any(target_os = "linux", target_os = "android")) {
+        let metrics = metrics.expect("Can't read /proc/self");
+        assert!(metrics.duration_ns >= 30_000_000);
+        assert!(metrics.rss_bytes >= memory.len() as u64);
+    } else {
+        assert_eq!(metrics, None);
+    }
+
+    #[cfg(feature = "serialization")]
+    {
+        let mut bytes = vec![];
+        frame
+            .write_into(&Default::default(), false, &mut bytes)
+            .unwrap();
+        let read_frame = puffin::FrameData::read_next(&mut bytes.as_slice())
+            .unwrap()
+            .unwrap();
+        assert_eq!(read_frame.meta().system_metrics, metrics);
+    }
+
+    // Sampled by the background thread instead:
+    GlobalProfiler::lock().set_background_dispatch(true);
+    profile_frame(|| std::thread::sleep(std::time::Duration::from_millis(30)));
+    GlobalProfiler::lock().set_background_dispatch(false); // Waits for the frame.
+    let frame = frame_view.lock().latest_frame().unwrap();
+    if cfg!(any(target_os = "linux", target_os = "android")) {
+        let metrics = frame.meta().system_metrics.expect("Can't read /proc/self");
+        assert!(metrics.duration_ns >= 30_000_000);
+    }
+
+    GlobalProfiler::lock().set_system_metrics(false);
+    profile_frame(|| {});
+    let frame = frame_view.lock().latest_frame().unwrap();
+    assert_eq!(frame.meta().system_metrics, None);
+}
@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use puffin::{
+    merge_scopes_for_thread,
+    testing::{assert_frame_shape, scope_id, FrameBuilder, ScopeTree},
+    FrameView, NanoSecond, ScopeCollection,
+};
+
+fn update(start_ns: NanoSecond) -> ScopeTree {
+    ScopeTree::new("update", start_ns, 100).with_children([
+        ScopeTree::new("physics", start_ns + 10, 40).with_data("10 bodies"),
+        ScopeTree::new("render", start_ns + 50, 50).with_children([ScopeTree::new(
+            "draw",
+            start_ns + 60,
+            30,
+        )]),
+    ])
+}
+
+#[test]
+fn build_and_read_frame() {
+    let frame = FrameBuilder::new(7)
+        .thread("main", [update(0), ScopeTree::new("sleep", 100, 20)])
+        .thread(
+            "worker",
+            [ScopeTree::new("load", 0, 500).with_data("tree.png")],
+        )
+        .build();
+    assert_eq!(frame.frame_index(), 7);
+    assert_eq!(frame.range_ns(), (0, 500));
+    assert_eq!(frame.meta().num_scopes, 6);
+
+    let mut frame_view = FrameView::default();
+    frame_view.add_frame(Arc::new(frame));
+    let frame = frame_view.latest_frame().unwrap();
+    let threads = ScopeTree::read_frame(&frame, frame_view.scope_collection());
+    assert_eq!(
+        threads["main"],
+        [update(0), ScopeTree::new("sleep", 100, 20)]
+    );
+    assert_eq!(
+        threads["worker"],
+        [ScopeTree::new("load", 0, 500).with_data("tree.png")]
+    );
+
+    assert_frame_shape(
+        &frame,
+        frame_view.scope_collection(),
+        "main",
+        "update(physics, render(draw)), sleep",
+    );
+}
+
+#[test]
+#[should_panic(expected = "Unexpected scopes in thread \"main\"")]
+fn assert_different_shape() {
+    let frame = FrameBuilder::new(0).thread("main", [update(0)]).build();
+    let mut scope_collection = ScopeCollection::default();
+    for scope_details in &frame.scope_delta {
+        scope_collection.insert(scope_details.clone());
+    }
+    assert_frame_shape(&frame, &scope_collection, "main", "update(render)");
+}
+
+#[test]
+fn merge_built_frames() {
+    let mut frame_view = FrameView::default();
+    for frame_index in 0..2 {
+        let start_ns = 1_000 * frame_index as NanoSecond;
+        let frame = FrameBuilder::new(frame_index)
+            .thread("main", [update(start_ns)])
+            .build();
+        frame_view.add_frame(Arc::new(frame));
+    }
+
+    let frames: Vec<_> = frame_view
+        .recent_frames()
+        .map(|frame| frame.unpacked().ok().unwrap())
+        .collect();
+    let thread_info = frames[0].thread_streams.keys().next().unwrap().clone();
+    let merged =
+        merge_scopes_for_thread(frame_view.scope_collection(), &frames, &thread_info).unwrap();
+
+    assert_eq!(merged.len(), 1);
+    assert_eq!(merged[0].id, scope_id("update"));
+    assert_eq!(merged[0].num_pieces, 2);
+    assert_eq!(merged[0].total_duration_ns, 200);
+    let children: Vec<_> = merged[0].children.iter().map(|child| child.id).collect();
+    assert_eq!(children, [scope_id("physics"), scope_id("render")]);
+}
+
+/// Recording with the profiler macros, timed by a `MockClock`.
+#[cfg(not(feature = "disable"))] // The profiler macros record nothing with `disable`.
+mod recording {
+    use puffin::testing::{assert_frame_shape, MockClock, ScopeTree};
+
+    fn update() {
+        puffin::profile_scope!("update");
+        physics();
+        puffin::profile_scope!("render", "2 meshes");
+    }
+
+    fn physics() {
+        puffin::profile_scope!("physics");
+    }
+
+    #[test]
+    fn deterministic_recording() {
+        let clock = MockClock::new(0);
+        clock.set_step(10);
+        puffin::set_clock(clock).unwrap();
+        puffin::set_scopes_on(true);
+
+        let frame_view = puffin::GlobalFrameView::default();
+        update();
+        puffin::GlobalProfiler::lock().new_frame();
+
+        let frame_view = frame_view.lock();
+        let frame = frame_view.latest_frame().unwrap();
+        let thread_name = std::thread::current().name().unwrap().to_owned();
+        assert_frame_shape(
+            &frame,
+            frame_view.scope_collection(),
+            &thread_name,
+            "update(physics, render)",
+        );
+
+        // Each reading of the clock moves it 10 ns forward:
+        let threads = ScopeTree::read_frame(&frame, frame_view.scope_collection());
+        assert_eq!(
+            threads[&thread_name],
+            [ScopeTree::new("update", 0, 50).with_children([
+                ScopeTree::new("physics", 10, 10),
+                ScopeTree::new("render", 30, 10).with_data("2 meshes"),
+            ])]
+        );
+    }
+}
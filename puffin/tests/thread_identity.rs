@@ -0,0 +1,78 @@
+#![cfg(not(feature = "disable"))]
+
+use puffin::{GlobalFrameView, GlobalProfiler, ThreadInfo};
+
+fn profile_thread(name: Option<&str>, setup: impl FnOnce() + Send + 'static) {
+    let mut builder = std::thread::Builder::new();
+    if let Some(name) = name {
+        builder = builder.name(name.to_owned());
+    }
+    builder
+        .spawn(move || {
+            setup();
+            puffin::profile_scope!("work");
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn thread_identity() {
+    puffin::set_scopes_on(true);
+    let frame_view = GlobalFrameView::default();
+
+    profile_thread(Some("worker"), || {});
+    profile_thread(Some("worker"), || {
+        puffin::set_thread_name("physics worker");
+        puffin::set_thread_group("physics");
+        puffin::set_thread_sort_key(-1);
+    });
+    profile_thread(None, || {});
+    GlobalProfiler::lock().new_frame();
+
+    let frame = frame_view.lock().latest_frame().unwrap();
+    let mut threads: Vec<ThreadInfo> = frame
+        .unpacked()
+        .ok()
+        .unwrap()
+        .thread_streams
+        .keys()
+        .cloned()
+        .collect();
+    threads.sort_by_key(|info| info.index);
+
+    // Threads with the same name, or none, are told apart:
+    assert_eq!(threads.len(), 3);
+    let labels: Vec<_> = threads.iter().map(|info| info.label()).collect();
+    let unnamed = format!("thread {}", threads[2].index.unwrap());
+    assert_eq!(labels, ["worker", "physics worker", unnamed.as_str()]);
+    assert!(threads[0].index < threads[1].index);
+    assert_eq!(threads[1].name, "worker");
+    assert_eq!(threads[1].group.as_deref(), Some("physics"));
+    assert_eq!(threads[1].sort_key, -1);
+    assert_eq!(threads[0].group, None);
+    if cfg!(any(target_os = "linux", target_os = "android")) {
+        assert!(threads.iter().all(|info| info.os_id.is_some()));
+    }
+
+    #[cfg(feature = "serialization")]
+    {
+        let mut bytes = vec![];
+        frame
+            .write_into(&Default::default(), false, &mut bytes)
+            .unwrap();
+        let frame = puffin::FrameData::read_next(&mut bytes.as_slice())
+            .unwrap()
+            .unwrap();
+        let mut read_threads: Vec<ThreadInfo> = frame
+            .unpacked()
+            .unwrap()
+            .thread_streams
+            .keys()
+            .cloned()
+            .collect();
+        read_threads.sort_by_key(|info| info.index);
+        assert_eq!(read_threads, threads);
+    }
+}
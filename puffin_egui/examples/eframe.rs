@@ -51,7 +51,7 @@ fn main() -> eframe::Result<()> {
             std::thread::sleep(std::time::Duration::from_millis(20))
         }
         if frame_counter % 343 == 0 {
-            puffin::profile_scope!("Big spike");
+            puffin::profile_scope!("Big spike", category = "spike");
             std::thread::sleep(std::time::Duration::from_millis(50))
         }
         if frame_counter % 55 == 0 {
@@ -76,7 +76,7 @@ fn main() -> eframe::Result<()> {
 }
 
 fn sleep_ms(ms: usize) {
-    puffin::profile_function_if!(ms > 1);
+    puffin::profile_function_if!(ms > 1, category = "sleep");
     match ms {
         0 => {}
         1 => std::thread::sleep(std::time::Duration::from_millis(1)),
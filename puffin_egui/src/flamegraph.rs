@@ -1,4 +1,4 @@
-use std::vec;
+use std::{cell::RefCell, collections::BTreeMap, vec};
 
 use egui::*;
 use emath::GuiRounding;
@@ -6,20 +6,26 @@ use indexmap::IndexMap;
 
 use puffin::*;
 
-use crate::{filter::Filter, SelectedFrames, ERROR_COLOR, HOVER_COLOR};
+use crate::{filter::Filter, SelectedFrames, ERROR_COLOR, HOVER_COLOR, LOCK_WAIT_COLOR};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum SortBy {
     Time,
     Name,
+    /// [`ThreadInfo::index`], i.e. the order the threads were first seen in.
+    Index,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct Sorting {
     pub sort_by: SortBy,
     pub reversed: bool,
+
+    /// Show the threads of each [`ThreadInfo::group`] together.
+    pub group_threads: bool,
 }
 
 impl Default for Sorting {
@@ -27,6 +33,7 @@ impl Default for Sorting {
         Self {
             sort_by: SortBy::Time,
             reversed: false,
+            group_threads: true,
         }
     }
 }
@@ -35,16 +42,44 @@ impl Sorting {
     fn sort(self, mut threads: Vec<ThreadInfo>) -> Vec<ThreadInfo> {
         match self.sort_by {
             SortBy::Time => {
-                threads.sort_by_key(|info| info.start_time_ns);
+                threads.sort_by_key(|info| (info.start_time_ns, info.index));
             }
             SortBy::Name => {
-                threads.sort_by(|a, b| natord::compare_ignore_case(&a.name, &b.name));
+                threads.sort_by(|a, b| {
+                    natord::compare_ignore_case(&a.label(), &b.label()).then(a.index.cmp(&b.index))
+                });
+            }
+            SortBy::Index => {
+                threads.sort_by_key(|info| info.index);
             }
         }
         if self.reversed {
             threads.reverse();
         }
-        threads
+
+        // The threads decide their own order before anything else:
+        threads.sort_by_key(|info| info.sort_key);
+
+        if !self.group_threads {
+            return threads;
+        }
+
+        // Each group goes where its first thread is:
+        let mut grouped: Vec<Vec<ThreadInfo>> = vec![];
+        let mut group_positions: BTreeMap<String, usize> = BTreeMap::new();
+        for thread in threads {
+            match &thread.group {
+                Some(group) => match group_positions.get(group) {
+                    Some(&position) => grouped[position].push(thread),
+                    None => {
+                        group_positions.insert(group.clone(), grouped.len());
+                        grouped.push(vec![thread]);
+                    }
+                },
+                None => grouped.push(vec![thread]),
+            }
+        }
+        grouped.into_iter().flatten().collect()
     }
 
     fn ui(&mut self, ui: &mut egui::Ui) {
@@ -53,7 +88,7 @@ impl Sorting {
 
             let dir = if self.reversed { '⬆' } else { '⬇' };
 
-            for &sort_by in &[SortBy::Time, SortBy::Name] {
+            for &sort_by in &[SortBy::Time, SortBy::Name, SortBy::Index] {
                 let selected = self.sort_by == sort_by;
 
                 let label = if selected {
@@ -71,10 +106,53 @@ impl Sorting {
                     }
                 }
             }
+
+            ui.checkbox(&mut self.group_threads, "Group")
+                .on_hover_text("Show the threads of each group together");
         });
     }
 }
 
+/// What decides the color of the scopes in the flamegraph.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ColorBy {
+    /// Brighter for longer scopes.
+    #[default]
+    Duration,
+    /// [`ScopeDetails::category`].
+    Category,
+    /// The thread of the scope.
+    Thread,
+    /// The crate of the function of the scope, i.e. the first part of its path.
+    Crate,
+    /// [`ScopeDetails::file_path`].
+    File,
+    /// Brighter for scopes that allocate more bytes, see [`Scope::allocations`].
+    Allocations,
+}
+
+impl ColorBy {
+    const ALL: [Self; 6] = [
+        Self::Duration,
+        Self::Category,
+        Self::Thread,
+        Self::Crate,
+        Self::File,
+        Self::Allocations,
+    ];
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_id_salt("flamegraph_color_by")
+            .selected_text(format!("Color by {self:?}"))
+            .show_ui(ui, |ui| {
+                for color_by in Self::ALL {
+                    ui.selectable_value(self, color_by, format!("{color_by:?}"));
+                }
+            });
+    }
+}
+
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct ThreadVisualizationSettings {
@@ -114,6 +192,9 @@ pub struct Options {
     pub spacing: f32,
     pub rounding: f32,
 
+    /// What decides the color of the scopes.
+    pub color_by: ColorBy,
+
     pub frame_list_height: f32,
     /// Distance between subsequent frames in the frame view.
     pub frame_width: f32,
@@ -152,6 +233,8 @@ impl Default for Options {
             spacing: 4.0,
             rounding: 4.0,
 
+            color_by: Default::default(),
+
             frame_list_height: 48.0,
             frame_width: 10.,
 
@@ -189,6 +272,9 @@ struct Info<'a> {
     font_id: FontId,
 
     scope_collection: &'a ScopeCollection,
+
+    /// The colors used so far, unless coloring by duration.
+    legend: RefCell<BTreeMap<String, Rgba>>,
 }
 
 #[derive(Clone, Copy, Eq, PartialEq)]
@@ -232,6 +318,7 @@ pub fn ui(
 
     ui.horizontal(|ui| {
         options.scope_name_filter.ui(ui);
+        options.color_by.ui(ui);
 
         ui.menu_button("🔧 Settings", |ui| {
             ui.set_max_height(500.0);
@@ -264,11 +351,9 @@ pub fn ui(
                 ui.strong("Visible Threads");
                 egui::ScrollArea::vertical().id_salt("f").show(ui, |ui| {
                     for f in frames.threads.keys() {
-                        let entry = options
-                            .flamegraph_threads
-                            .entry(f.name.clone())
-                            .or_default();
-                        ui.checkbox(&mut entry.flamegraph_show, f.name.clone());
+                        let label = f.label().into_owned();
+                        let entry = options.flamegraph_threads.entry(label.clone()).or_default();
+                        ui.checkbox(&mut entry.flamegraph_show, label);
                     }
                 });
             });
@@ -312,6 +397,7 @@ pub fn ui(
                 layer_id: ui.layer_id(),
                 font_id: TextStyle::Body.resolve(ui.style()),
                 scope_collection,
+                legend: Default::default(),
             };
 
             if reset_view {
@@ -337,6 +423,8 @@ pub fn ui(
             info.painter
                 .set(where_to_put_timeline, Shape::Vec(timeline));
 
+            paint_legend(&info, options, ui.clip_rect().intersect(canvas));
+
             ui.allocate_rect(used_rect, Sense::hover());
         });
     });
@@ -365,7 +453,7 @@ fn ui_canvas(
     for thread_info in threads {
         let thread_visualization = options
             .flamegraph_threads
-            .entry(thread_info.name.clone())
+            .entry(thread_info.label().into_owned())
             .or_default();
 
         if !thread_visualization.flamegraph_show {
@@ -401,14 +489,24 @@ fn ui_canvas(
             let mut paint_streams = || -> Result<()> {
                 if options.merge_scopes {
                     for merge in &frames.threads[&thread_info].merged_scopes {
-                        paint_merge_scope(info, options, 0, merge, 0, cursor_y);
+                        paint_merge_scope(info, options, &thread_info, 0, merge, 0, cursor_y);
                     }
                 } else {
+                    let mut stitches = vec![];
                     for stream_info in &frames.threads[&thread_info].streams {
                         let top_scopes =
                             Reader::from_start(&stream_info.stream).read_top_scopes()?;
                         for scope in top_scopes {
-                            paint_scope(info, options, &stream_info.stream, &scope, 0, cursor_y)?;
+                            paint_scope(
+                                info,
+                                options,
+                                &thread_info,
+                                &stream_info.stream,
+                                &scope,
+                                0,
+                                cursor_y,
+                                &mut stitches,
+                            )?;
                         }
                     }
                 }
@@ -620,10 +718,13 @@ fn grid_text(grid_ns: NanoSecond) -> String {
 fn paint_record(
     info: &Info<'_>,
     options: &mut Options,
+    thread_info: &ThreadInfo,
     prefix: &str,
     suffix: &str,
     scope_id: ScopeId,
     scope_data: &ScopeRecord<'_>,
+    allocations: Allocations,
+    in_progress: bool,
     top_y: f32,
 ) -> PaintResult {
     let start_x = info.point_from_ns(options, scope_data.start_ns);
@@ -667,10 +768,22 @@ fn paint_record(
         ));
     }
 
+    // Waiting for a lock stands out, whatever the scopes are colored by:
+    let is_lock_wait = scope_details.category.as_deref() == Some(puffin::sync::WAIT_CATEGORY);
+
     let mut rect_color = if is_hovered {
         HOVER_COLOR
+    } else if is_lock_wait {
+        LOCK_WAIT_COLOR
     } else {
-        color_from_duration(scope_data.duration_ns)
+        scope_color(
+            info,
+            options,
+            thread_info,
+            scope_details,
+            scope_data.duration_ns,
+            allocations,
+        )
     };
 
     let mut min_width = options.min_width;
@@ -692,7 +805,21 @@ fn paint_record(
             egui::Stroke::new(min_width, rect_color),
         );
     } else {
-        info.painter.rect_filled(rect, options.rounding, rect_color);
+        let mut corner_radius = CornerRadius::from(options.rounding);
+        if in_progress {
+            // Open-ended, since the scope continues after this:
+            corner_radius.ne = 0;
+            corner_radius.se = 0;
+        }
+        info.painter.rect_filled(rect, corner_radius, rect_color);
+        if is_lock_wait {
+            info.painter.rect_stroke(
+                rect,
+                corner_radius,
+                egui::Stroke::new(1.5, ERROR_COLOR),
+                egui::StrokeKind::Inside,
+            );
+        }
     }
 
     let wide_enough_for_text = stop_x - start_x > 32.0;
@@ -754,6 +881,133 @@ fn color_from_duration(ns: NanoSecond) -> Rgba {
     Rgba::from_rgb(r, g, b) * a
 }
 
+fn color_from_alloc_bytes(bytes: u64) -> Rgba {
+    // Brighter = more bytes, on a log scale from nothing to 10 MB.
+    let t = remap_clamp((bytes as f32 + 1.0).log10(), 0.0..=7.0, 0.0..=1.0);
+    let r = lerp(0.2..=1.0, t);
+    let g = lerp(0.2..=0.8, t * t);
+    let b = lerp(0.4..=0.1, t);
+    let a = 0.9;
+    Rgba::from_rgb(r, g, b) * a
+}
+
+fn scope_color(
+    info: &Info<'_>,
+    options: &Options,
+    thread_info: &ThreadInfo,
+    scope_details: &ScopeDetails,
+    duration_ns: NanoSecond,
+    allocations: Allocations,
+) -> Rgba {
+    let thread_label = thread_info.label();
+    let key = match options.color_by {
+        ColorBy::Duration => return color_from_duration(duration_ns),
+        ColorBy::Allocations => return color_from_alloc_bytes(allocations.alloc_bytes),
+        ColorBy::Category => scope_details.category.as_deref().unwrap_or("uncategorized"),
+        ColorBy::Thread => &thread_label,
+        ColorBy::Crate => scope_details
+            .function_name
+            .split_once("::")
+            .map_or(&*scope_details.function_name, |(krate, _)| krate),
+        ColorBy::File => &scope_details.file_path,
+    };
+
+    let mut legend = info.legend.borrow_mut();
+    if let Some(color) = legend.get(key) {
+        *color
+    } else {
+        let color = color_from_key(key);
+        legend.insert(key.to_owned(), color);
+        color
+    }
+}
+
+/// A color that is the same for the same key, every time.
+fn color_from_key(key: &str) -> Rgba {
+    let hash = Id::new(key).value();
+    let hue = (hash % 360) as f32 / 360.0;
+    ecolor::Hsva::new(hue, 0.5, 0.8, 0.9).into()
+}
+
+/// Shows what the colors mean, in the top right corner of `rect`.
+fn paint_legend(info: &Info<'_>, options: &Options, rect: Rect) {
+    const MAX_ENTRIES: usize = 20;
+
+    let entries: Vec<(String, Rgba)> = if options.color_by == ColorBy::Duration {
+        [0, 1, 5, 10, 20, 33]
+            .into_iter()
+            .map(|ms| (format!("{ms} ms"), color_from_duration(ms * 1_000_000)))
+            .collect()
+    } else if options.color_by == ColorBy::Allocations {
+        ["0 B", "1 kB", "10 kB", "100 kB", "1 MB", "10 MB"]
+            .into_iter()
+            .zip([0, 1_000, 10_000, 100_000, 1_000_000, 10_000_000])
+            .map(|(text, bytes)| (text.to_owned(), color_from_alloc_bytes(bytes)))
+            .collect()
+    } else {
+        let legend = info.legend.borrow();
+        let mut entries: Vec<_> = legend
+            .iter()
+            .take(MAX_ENTRIES)
+            .map(|(key, color)| (key.clone(), *color))
+            .collect();
+        if MAX_ENTRIES < legend.len() {
+            entries.push((
+                format!("…and {} more", legend.len() - MAX_ENTRIES),
+                Rgba::TRANSPARENT,
+            ));
+        }
+        entries
+    };
+    if entries.is_empty() {
+        return;
+    }
+
+    let galleys: Vec<_> = entries
+        .into_iter()
+        .map(|(text, color)| {
+            let galley = info
+                .painter
+                .layout_no_wrap(text, info.font_id.clone(), Color32::WHITE);
+            (galley, color)
+        })
+        .collect();
+
+    let margin = 6.0;
+    let swatch_size = info.text_height * 0.75;
+    let text_width = galleys
+        .iter()
+        .map(|(galley, _)| galley.size().x)
+        .fold(0.0, f32::max);
+    let size = vec2(
+        swatch_size + text_width + 3.0 * margin,
+        galleys.len() as f32 * info.text_height + 2.0 * margin,
+    );
+    // Leave room for the time labels:
+    let top_right = rect.right_top() + vec2(-margin, info.text_height + margin);
+    let legend_rect = Rect::from_min_size(top_right - vec2(size.x, 0.0), size);
+    info.painter
+        .rect_filled(legend_rect, 4.0, Color32::from_black_alpha(200));
+
+    let mut cursor = legend_rect.min + vec2(margin, margin);
+    for (galley, color) in galleys {
+        let swatch_rect = Rect::from_center_size(
+            pos2(
+                cursor.x + 0.5 * swatch_size,
+                cursor.y + 0.5 * info.text_height,
+            ),
+            Vec2::splat(swatch_size),
+        );
+        info.painter.rect_filled(swatch_rect, 2.0, color);
+        info.painter.galley(
+            pos2(cursor.x + swatch_size + margin, cursor.y),
+            galley,
+            Color32::WHITE,
+        );
+        cursor.y += info.text_height;
+    }
+}
+
 fn to_ms(ns: NanoSecond) -> f64 {
     ns as f64 * 1e-6
 }
@@ -771,22 +1025,78 @@ fn duration_fmt(ns: NanoSecond, large: bool) -> String {
     format!("{:6.3} ms", ms)
 }
 
+/// A scope that was still in progress at the end of a stream,
+/// and may be continued by a scope at the same depth in the next stream.
+struct Stitch {
+    id: ScopeId,
+    start_ns: NanoSecond,
+    stop_ns: NanoSecond,
+}
+
+/// Paints the scope and its children.
+///
+/// `stitches` has the scopes in progress at each depth, so that the pieces of a scope
+/// that spans several streams are painted as one bar.
+#[allow(clippy::too_many_arguments)]
 fn paint_scope(
     info: &Info<'_>,
     options: &mut Options,
+    thread_info: &ThreadInfo,
     stream: &Stream,
     scope: &Scope<'_>,
     depth: usize,
     min_y: f32,
+    stitches: &mut Vec<Option<Stitch>>,
 ) -> Result<PaintResult> {
     let top_y = min_y + (depth as f32) * (options.rect_height + options.spacing);
 
-    let result = paint_record(info, options, "", "", scope.id, &scope.record, top_y);
+    if stitches.len() <= depth {
+        stitches.resize_with(depth + 1, || None);
+    }
+    let start_ns = match &stitches[depth] {
+        Some(stitch) if stitch.id == scope.id && stitch.stop_ns == scope.record.start_ns => {
+            stitch.start_ns
+        }
+        _ => scope.record.start_ns,
+    };
+    let record = ScopeRecord {
+        start_ns,
+        duration_ns: scope.record.stop_ns() - start_ns,
+        data: scope.record.data,
+    };
+    stitches[depth] = scope.in_progress.then(|| Stitch {
+        id: scope.id,
+        start_ns,
+        stop_ns: record.stop_ns(),
+    });
+
+    let suffix = if scope.in_progress { "in progress" } else { "" };
+    let result = paint_record(
+        info,
+        options,
+        thread_info,
+        "",
+        suffix,
+        scope.id,
+        &record,
+        scope.allocations,
+        scope.in_progress,
+        top_y,
+    );
 
     if result != PaintResult::Culled {
         let mut num_children = 0;
-        for child_scope in Reader::with_offset(stream, scope.child_begin_position)? {
-            paint_scope(info, options, stream, &child_scope?, depth + 1, min_y)?;
+        for child_scope in Reader::with_parent(stream, scope)? {
+            paint_scope(
+                info,
+                options,
+                thread_info,
+                stream,
+                &child_scope?,
+                depth + 1,
+                min_y,
+                stitches,
+            )?;
             num_children += 1;
         }
 
@@ -803,9 +1113,13 @@ fn paint_scope(
 
                     ui.monospace(format!(
                         "duration: {}",
-                        duration_fmt(scope.record.duration_ns, true)
+                        duration_fmt(record.duration_ns, true)
                     ));
                     ui.monospace(format!("children: {num_children:3}"));
+                    allocations_tooltip(ui, scope.allocations);
+                    if scope.in_progress {
+                        ui.monospace("in progress");
+                    }
                 },
             );
         }
@@ -817,6 +1131,7 @@ fn paint_scope(
 fn paint_merge_scope(
     info: &Info<'_>,
     options: &mut Options,
+    thread_info: &ThreadInfo,
     ns_offset: NanoSecond,
     merge: &MergeScope<'_>,
     depth: usize,
@@ -851,11 +1166,30 @@ fn paint_merge_scope(
         data: &merge.data,
     };
 
-    let result = paint_record(info, options, &prefix, suffix, merge.id, &record, top_y);
+    let result = paint_record(
+        info,
+        options,
+        thread_info,
+        &prefix,
+        suffix,
+        merge.id,
+        &record,
+        allocations_per_frame(merge.allocations, info.num_frames),
+        false,
+        top_y,
+    );
 
     if result != PaintResult::Culled {
         for child in &merge.children {
-            paint_merge_scope(info, options, record.start_ns, child, depth + 1, min_y);
+            paint_merge_scope(
+                info,
+                options,
+                thread_info,
+                record.start_ns,
+                child,
+                depth + 1,
+                min_y,
+            );
         }
 
         if result == PaintResult::Hovered {
@@ -909,6 +1243,32 @@ fn paint_scope_details(ui: &mut Ui, scope_id: ScopeId, data: &str, scope_details
         });
 }
 
+fn allocations_per_frame(allocations: Allocations, num_frames: usize) -> Allocations {
+    let num_frames = num_frames.max(1) as u64;
+    Allocations {
+        alloc_count: allocations.alloc_count / num_frames,
+        alloc_bytes: allocations.alloc_bytes / num_frames,
+        dealloc_count: allocations.dealloc_count / num_frames,
+        dealloc_bytes: allocations.dealloc_bytes / num_frames,
+    }
+}
+
+/// What a scope allocated and freed, if anything.
+fn allocations_tooltip(ui: &mut Ui, allocations: Allocations) {
+    if !allocations.is_empty() {
+        ui.monospace(format!(
+            "allocated: {:.1} kB in {} allocations",
+            allocations.alloc_bytes as f64 * 1e-3,
+            allocations.alloc_count
+        ));
+        ui.monospace(format!(
+            "freed:     {:.1} kB in {} deallocations",
+            allocations.dealloc_bytes as f64 * 1e-3,
+            allocations.dealloc_count
+        ));
+    }
+}
+
 fn merge_scope_tooltip(
     ui: &mut egui::Ui,
     scope_collection: &ScopeCollection,
@@ -942,7 +1302,10 @@ fn merge_scope_tooltip(
                     true
                 )
             ));
-            ui.monospace(format!("max:   {}", duration_fmt(merge.max_duration_ns,true)));
+            ui.monospace(format!(
+                "max:   {}",
+                duration_fmt(merge.max_duration_ns, true)
+            ));
         }
     } else {
         ui.monospace(format!(
@@ -967,13 +1330,18 @@ fn merge_scope_tooltip(
         ));
         ui.monospace(format!(
             "{} / call",
-            duration_fmt(merge.total_duration_ns / merge.num_pieces as NanoSecond, true),
+            duration_fmt(
+                merge.total_duration_ns / merge.num_pieces as NanoSecond,
+                true
+            ),
         ));
         ui.monospace(format!(
             "{} for slowest call",
             duration_fmt(merge.max_duration_ns, true)
         ));
     }
+
+    allocations_tooltip(ui, allocations_per_frame(merge.allocations, num_frames));
 }
 
 fn paint_thread_info(info: &Info<'_>, thread: &ThreadInfo, pos: Pos2, collapsed: &mut bool) {
@@ -981,7 +1349,7 @@ fn paint_thread_info(info: &Info<'_>, thread: &ThreadInfo, pos: Pos2, collapsed:
 
     let galley = info.ctx.fonts(|f| {
         f.layout_no_wrap(
-            format!("{} {}", collapsed_symbol, thread.name.clone()),
+            format!("{} {}", collapsed_symbol, thread.label()),
             info.font_id.clone(),
             egui::Color32::PLACEHOLDER,
         )
@@ -1012,4 +1380,45 @@ fn paint_thread_info(info: &Info<'_>, thread: &ThreadInfo, pos: Pos2, collapsed:
     if is_hovered && info.response.clicked() {
         *collapsed = !(*collapsed);
     }
+
+    if is_hovered {
+        egui::show_tooltip_at_pointer(
+            &info.ctx,
+            info.layer_id,
+            Id::new("puffin_thread_tooltip"),
+            |ui| thread_tooltip(ui, thread),
+        );
+    }
+}
+
+fn thread_tooltip(ui: &mut Ui, thread: &ThreadInfo) {
+    egui::Grid::new("thread_tooltip")
+        .num_columns(2)
+        .show(ui, |ui| {
+            if thread.display_name.is_some() {
+                ui.monospace("thread name");
+                ui.monospace(&thread.name);
+                ui.end_row();
+            }
+            if let Some(index) = thread.index {
+                ui.monospace("index");
+                ui.monospace(index.to_string());
+                ui.end_row();
+            }
+            if let Some(os_id) = thread.os_id {
+                ui.monospace("OS thread id");
+                ui.monospace(os_id.to_string());
+                ui.end_row();
+            }
+            if let Some(group) = &thread.group {
+                ui.monospace("group");
+                ui.monospace(group);
+                ui.end_row();
+            }
+            if thread.sort_key != 0 {
+                ui.monospace("sort key");
+                ui.monospace(thread.sort_key.to_string());
+                ui.end_row();
+            }
+        });
 }
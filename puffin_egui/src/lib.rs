@@ -14,6 +14,7 @@
 mod filter;
 mod flamegraph;
 mod maybe_mut_ref;
+mod scope_switches;
 mod stats;
 
 pub use {egui, maybe_mut_ref::MaybeMutRef, puffin};
@@ -30,6 +31,8 @@ use time::OffsetDateTime;
 
 const ERROR_COLOR: Color32 = Color32::RED;
 const HOVER_COLOR: Rgba = Rgba::from_rgb(0.8, 0.8, 0.8);
+/// Scopes of [`puffin::sync::WAIT_CATEGORY`] in the flamegraph.
+const LOCK_WAIT_COLOR: Rgba = Rgba::from_rgb(0.9, 0.45, 0.1);
 
 // ----------------------------------------------------------------------------
 
@@ -116,6 +119,9 @@ impl GlobalProfilerUi {
     ///
     /// Returns `false` if the user closed the profile window.
     pub fn window(&mut self, ctx: &egui::Context) -> bool {
+        self.profiler_ui
+            .scope_switches
+            .get_or_insert_with(Default::default);
         let mut frame_view = self.global_frame_view.lock();
         self.profiler_ui
             .window(ctx, &mut MaybeMutRef::MutRef(&mut frame_view))
@@ -125,6 +131,9 @@ impl GlobalProfilerUi {
     ///
     /// Call this from within an [`egui::Window`], or use [`Self::window`] instead.
     pub fn ui(&mut self, ui: &mut egui::Ui) {
+        self.profiler_ui
+            .scope_switches
+            .get_or_insert_with(Default::default);
         let mut frame_view = self.global_frame_view.lock();
         self.profiler_ui
             .ui(ui, &mut MaybeMutRef::MutRef(&mut frame_view));
@@ -339,6 +348,10 @@ pub struct ProfilerUi {
     /// When did we last run a pass to pack all the frames?
     #[cfg_attr(feature = "serde", serde(skip))]
     last_pack_pass: Option<web_time::Instant>,
+
+    /// Set when we are profiling the app we are running in, see [`GlobalProfilerUi`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    scope_switches: Option<scope_switches::ScopeSwitches>,
 }
 
 impl Default for ProfilerUi {
@@ -351,6 +364,7 @@ impl Default for ProfilerUi {
             max_num_latest: 1,
             slowest_frame: 0.16,
             last_pack_pass: None,
+            scope_switches: None,
         }
     }
 }
@@ -501,6 +515,14 @@ impl ProfilerUi {
                 hovered_frame = self.show_frames(ui, frame_view);
             });
 
+        if let Some(scope_switches) = &mut self.scope_switches {
+            egui::CollapsingHeader::new("Enabled scopes")
+                .default_open(false)
+                .show(ui, |ui| {
+                    scope_switches.ui(ui, frame_view.scope_collection());
+                });
+        }
+
         let frames = if let Some(frame) = hovered_frame {
             match frame.unpacked() {
                 Ok(frame) => {
@@ -661,6 +683,12 @@ impl ProfilerUi {
                         stats.unpacked_frames(),
                         stats.bytes_of_ram_used() as f64 * 1e-6
                     ));
+                    if stats.bytes_saved_by_compaction() > 0 {
+                        ui.label(format!(
+                            "The compact stream layout saved {:.1} MB of unpacked data.",
+                            stats.bytes_saved_by_compaction() as f64 * 1e-6
+                        ));
+                    }
 
                     max_recent = max_frames_ui(ui, recent, stats.bytes_of_ram_used(), max_recent);
 
@@ -678,19 +706,34 @@ impl ProfilerUi {
                     .stick_to_right(true)
                     .drag_to_scroll(false)
                     .show(ui, |ui| {
-                        let (slowest_visible, recent_new_selection) = self.show_frame_list(
-                            ui,
-                            paused.as_ref(),
-                            latest_frame.as_deref(),
-                            &frames.recent,
-                            false,
-                            &mut hovered_frame,
-                            self.slowest_frame,
-                        );
-                        new_selection = recent_new_selection;
-
-                        // quickly, but smoothly, normalize frame height:
-                        self.slowest_frame = lerp(self.slowest_frame..=slowest_visible as f32, 0.2);
+                        ui.vertical(|ui| {
+                            let (slowest_visible, recent_new_selection) = self.show_frame_list(
+                                ui,
+                                paused.as_ref(),
+                                latest_frame.as_deref(),
+                                &frames.recent,
+                                false,
+                                &mut hovered_frame,
+                                self.slowest_frame,
+                            );
+                            new_selection = recent_new_selection;
+
+                            // quickly, but smoothly, normalize frame height:
+                            self.slowest_frame =
+                                lerp(self.slowest_frame..=slowest_visible as f32, 0.2);
+
+                            if frames
+                                .recent
+                                .iter()
+                                .any(|frame| frame.meta().system_metrics.is_some())
+                            {
+                                system_metrics_tracks(
+                                    ui,
+                                    &frames.recent,
+                                    self.flamegraph_options.frame_width,
+                                );
+                            }
+                        });
                     });
             });
 
@@ -751,12 +794,13 @@ impl ProfilerUi {
     }
 
     /// Returns the `(slowest_visible_frame, new_selection)`
-    fn show_frame_list<'a>(
+    #[allow(clippy::too_many_arguments)]
+    fn show_frame_list(
         &mut self,
         ui: &mut egui::Ui,
         paused: Option<&Paused>,
         latest_frame: Option<&FrameData>,
-        frames: &[&'a FrameData],
+        frames: &[&FrameData],
         tight: bool,
         hovered_frame: &mut Option<FrameData>,
         slowest_frame: f32,
@@ -892,6 +936,161 @@ impl ProfilerUi {
     }
 }
 
+/// A row of bars below the frame list, one for each frame.
+struct MetricTrack {
+    name: &'static str,
+    value: fn(&SystemMetrics) -> f64,
+    /// The bars fill the track at this value or the largest one, whichever is bigger.
+    min_range: f64,
+}
+
+const METRIC_TRACKS: [MetricTrack; 4] = [
+    MetricTrack {
+        name: "CPU",
+        value: |metrics| metrics.cpu_usage() as f64,
+        min_range: 1.0, // one busy core
+    },
+    MetricTrack {
+        name: "RSS",
+        value: |metrics| metrics.rss_bytes as f64,
+        min_range: 0.0,
+    },
+    MetricTrack {
+        name: "Page faults",
+        value: |metrics| metrics.page_faults() as f64,
+        min_range: 0.0,
+    },
+    MetricTrack {
+        name: "Context switches",
+        value: |metrics| metrics.context_switches() as f64,
+        min_range: 0.0,
+    },
+];
+
+/// One track for each of the [`puffin::SystemMetrics`] of the frames, lined up with
+/// the non-tight frame list above it.
+fn system_metrics_tracks(
+    ui: &mut egui::Ui,
+    frames: &[&FrameData],
+    frame_width_including_spacing: f32,
+) {
+    puffin::profile_function!();
+
+    let track_height = 20.0;
+    let frame_spacing = 2.0;
+    let frame_width = frame_width_including_spacing - frame_spacing;
+
+    let first_frame_index = frames[0].frame_index();
+    let latest_frame_index = frames[frames.len() - 1].frame_index();
+    let num_frames = latest_frame_index + 1 - first_frame_index;
+    let desired_size = Vec2::new(
+        num_frames as f32 * frame_width_including_spacing,
+        track_height,
+    );
+
+    for track in &METRIC_TRACKS {
+        let (response, painter) = ui.allocate_painter(desired_size, Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, Rgba::from_black_alpha(0.2));
+
+        let max_value = frames
+            .iter()
+            .filter_map(|frame| frame.meta().system_metrics.as_ref())
+            .map(track.value)
+            .fold(track.min_range, f64::max);
+
+        for frame in frames {
+            let Some(metrics) = &frame.meta().system_metrics else {
+                continue;
+            };
+            let x = rect.right()
+                - (latest_frame_index + 1 - frame.frame_index()) as f32
+                    * frame_width_including_spacing;
+            let frame_rect = Rect::from_min_max(
+                Pos2::new(x, rect.top()),
+                Pos2::new(x + frame_width, rect.bottom()),
+            );
+            if !ui.clip_rect().intersects(frame_rect) {
+                continue;
+            }
+
+            let is_hovered = response
+                .hover_pos()
+                .is_some_and(|mouse_pos| frame_rect.contains(mouse_pos));
+            let color = if is_hovered {
+                HOVER_COLOR
+            } else {
+                Rgba::from_rgb(0.4, 0.6, 0.4)
+            };
+
+            let mut value_rect = frame_rect;
+            if max_value > 0.0 {
+                value_rect.min.y = lerp(
+                    frame_rect.bottom_up_range(),
+                    ((track.value)(metrics) / max_value) as f32,
+                );
+            } else {
+                value_rect.min.y = frame_rect.bottom();
+            }
+            painter.rect_filled(value_rect, 0.0, color);
+
+            if is_hovered {
+                egui::show_tooltip_at_pointer(
+                    ui.ctx(),
+                    ui.layer_id(),
+                    Id::new("puffin_system_metrics_tooltip"),
+                    |ui| system_metrics_ui(ui, frame.frame_index(), metrics),
+                );
+            }
+        }
+
+        painter.text(
+            pos2(
+                ui.clip_rect().left().max(rect.left()) + 4.0,
+                rect.center().y,
+            ),
+            Align2::LEFT_CENTER,
+            track.name,
+            FontId::proportional(11.0),
+            ui.visuals().text_color(),
+        );
+    }
+}
+
+fn system_metrics_ui(ui: &mut egui::Ui, frame_index: FrameIndex, metrics: &SystemMetrics) {
+    ui.label(format!("Frame #{frame_index}"));
+    egui::Grid::new("system_metrics")
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("CPU");
+            ui.label(format!(
+                "{:.2} cores ({:.1} ms user, {:.1} ms system)",
+                metrics.cpu_usage(),
+                metrics.user_cpu_ns as f64 * 1e-6,
+                metrics.system_cpu_ns as f64 * 1e-6,
+            ));
+            ui.end_row();
+
+            ui.label("RSS");
+            ui.label(format!("{:.1} MB", metrics.rss_bytes as f64 * 1e-6));
+            ui.end_row();
+
+            ui.label("Page faults");
+            ui.label(format!(
+                "{} minor, {} major",
+                metrics.minor_page_faults, metrics.major_page_faults
+            ));
+            ui.end_row();
+
+            ui.label("Context switches");
+            ui.label(format!(
+                "{} voluntary, {} involuntary",
+                metrics.voluntary_context_switches, metrics.involuntary_context_switches
+            ));
+            ui.end_row();
+        });
+}
+
 fn frames_info_ui(ui: &mut egui::Ui, selection: &SelectedFrames) {
     let mut sum_ns = 0;
     let mut sum_scopes = 0;
@@ -926,7 +1125,9 @@ fn frames_info_ui(ui: &mut egui::Ui, selection: &SelectedFrames) {
         let _ = write!(&mut info, " Recorded {time}.");
     }
 
-    ui.label(info);
+    let clock = selection.frames.first().meta.clock;
+    ui.label(info)
+        .on_hover_text(format!("Timed with the {clock} clock."));
 }
 
 fn format_time(nanos: NanoSecond) -> Option<String> {
@@ -0,0 +1,121 @@
+use std::collections::BTreeSet;
+
+use puffin::{GlobalProfiler, ScopeCollection, ScopeFilter};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum FilterKind {
+    #[default]
+    Name,
+    FunctionPrefix,
+    File,
+}
+
+impl FilterKind {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Name => "Scope name",
+            Self::FunctionPrefix => "Function prefix",
+            Self::File => "File",
+        }
+    }
+
+    fn filter(self, pattern: String) -> ScopeFilter {
+        match self {
+            Self::Name => ScopeFilter::Name(pattern.into()),
+            Self::FunctionPrefix => ScopeFilter::FunctionPrefix(pattern.into()),
+            Self::File => ScopeFilter::File(pattern.into()),
+        }
+    }
+}
+
+/// Turn the scopes of the app we are running in on and off,
+/// with [`GlobalProfiler::set_scopes_enabled`].
+#[derive(Clone, Debug, Default)]
+pub struct ScopeSwitches {
+    new_filter_kind: FilterKind,
+    new_filter_pattern: String,
+}
+
+impl ScopeSwitches {
+    pub fn ui(&mut self, ui: &mut egui::Ui, scope_collection: &ScopeCollection) {
+        ui.label("Turned off scopes are not recorded, and cost next to nothing.");
+
+        let filters = GlobalProfiler::scope_filters();
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Crates:");
+            for krate in crates(scope_collection) {
+                let filter = ScopeFilter::FunctionPrefix(format!("{krate}::").into());
+                let mut enabled = filters
+                    .iter()
+                    .find(|(f, _)| *f == filter)
+                    .map_or(true, |(_, enabled)| *enabled);
+                if ui.checkbox(&mut enabled, krate).changed() {
+                    GlobalProfiler::set_scopes_enabled(filter, enabled);
+                }
+            }
+        });
+
+        if !filters.is_empty() {
+            ui.label("Filters, where later ones take precedence:");
+            egui::Grid::new("scope_switches")
+                .num_columns(3)
+                .show(ui, |ui| {
+                    for (filter, mut enabled) in filters {
+                        let text = match &filter {
+                            ScopeFilter::Name(name) => format!("Scope name {name:?}"),
+                            ScopeFilter::FunctionPrefix(prefix) => {
+                                format!("Function prefix {prefix:?}")
+                            }
+                            ScopeFilter::File(file_path) => format!("File {file_path:?}"),
+                        };
+                        if ui.checkbox(&mut enabled, "on").changed() {
+                            GlobalProfiler::set_scopes_enabled(filter.clone(), enabled);
+                        }
+                        ui.monospace(text);
+                        if ui.button("🗑").on_hover_text("Remove filter").clicked() {
+                            GlobalProfiler::remove_scope_filter(&filter);
+                        }
+                        ui.end_row();
+                    }
+                });
+        }
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("scope_switch_kind")
+                .selected_text(self.new_filter_kind.label())
+                .show_ui(ui, |ui| {
+                    for kind in [
+                        FilterKind::Name,
+                        FilterKind::FunctionPrefix,
+                        FilterKind::File,
+                    ] {
+                        ui.selectable_value(&mut self.new_filter_kind, kind, kind.label());
+                    }
+                });
+            ui.text_edit_singleline(&mut self.new_filter_pattern);
+
+            let pattern = self.new_filter_pattern.trim();
+            if !pattern.is_empty() {
+                if ui.button("Turn off").clicked() {
+                    let filter = self.new_filter_kind.filter(pattern.to_owned());
+                    GlobalProfiler::set_scopes_enabled(filter, false);
+                    self.new_filter_pattern.clear();
+                } else if ui.button("Turn on").clicked() {
+                    let filter = self.new_filter_kind.filter(pattern.to_owned());
+                    GlobalProfiler::set_scopes_enabled(filter, true);
+                    self.new_filter_pattern.clear();
+                }
+            }
+        });
+    }
+}
+
+/// The crates with known scopes, i.e. the first part of the function paths.
+fn crates(scope_collection: &ScopeCollection) -> BTreeSet<&str> {
+    scope_collection
+        .scopes_by_id()
+        .values()
+        .filter_map(|scope_details| scope_details.function_name.split_once("::"))
+        .map(|(krate, _)| krate)
+        .collect()
+}
@@ -26,6 +26,8 @@ pub enum StatsColumnId {
     MaxSelfTime,
     TotalTime,
     MeanTime,
+    AllocCount,
+    AllocBytes,
 }
 
 impl StatsColumnId {
@@ -42,6 +44,8 @@ impl StatsColumnId {
             StatsColumnId::MaxSelfTime => "Max self time",
             StatsColumnId::TotalTime => "Total time",
             StatsColumnId::MeanTime => "Mean time",
+            StatsColumnId::AllocCount => "Alloc count",
+            StatsColumnId::AllocBytes => "Alloc bytes",
         }
     }
     pub fn grouped_stats_ordering(
@@ -51,7 +55,7 @@ impl StatsColumnId {
         b: &GroupedStats,
     ) -> Ordering {
         match self {
-            StatsColumnId::Thread => a.key.thread_name.cmp(&b.key.thread_name),
+            StatsColumnId::Thread => a.key.thread_name.cmp(b.key.thread_name),
             StatsColumnId::Location => {
                 if let (Some(ai), Some(bi)) = (
                     scope_infos.fetch_by_id(&a.key.id),
@@ -88,6 +92,8 @@ impl StatsColumnId {
             StatsColumnId::MeanTime => (a.scope_stats.total_ns as f32 / a.scope_stats.count as f32)
                 .partial_cmp(&(b.scope_stats.total_ns as f32 / b.scope_stats.count as f32))
                 .unwrap_or(Ordering::Equal),
+            StatsColumnId::AllocCount => a.scope_stats.alloc_count.cmp(&b.scope_stats.alloc_count),
+            StatsColumnId::AllocBytes => a.scope_stats.alloc_bytes.cmp(&b.scope_stats.alloc_bytes),
         }
     }
 }
@@ -147,6 +153,10 @@ pub fn draw_column_data(
             "{:>8.1} µs",
             stats.total_ns as f32 * 1e-3 / (stats.count as f32)
         )),
+        StatsColumnId::AllocCount => ui.monospace(format!("{:>7}", stats.alloc_count)),
+        StatsColumnId::AllocBytes => {
+            ui.monospace(format!("{:>8.1} kB", stats.alloc_bytes as f32 * 1e-3))
+        }
     };
 }
 
@@ -182,7 +192,6 @@ pub struct Options {
 
     tree_view: bool,
     tree_view_state: HashMap<u64, bool>,
-
     // table_type: StatsTableType,
 }
 
@@ -207,6 +216,8 @@ impl Default for Options {
                 StatsColumn::new(StatsColumnId::TotalTime, true),
                 StatsColumn::new(StatsColumnId::MeanTime, true),
                 StatsColumn::new(StatsColumnId::Size, true),
+                StatsColumn::new(StatsColumnId::AllocCount, false),
+                StatsColumn::new(StatsColumnId::AllocBytes, false),
             ],
         }
     }
@@ -248,15 +259,13 @@ pub fn ui(
                         // NOTE: these swaps will cause one menu option to be drawn twice, and other
                         // zero times.
                         // But just for one frame so it's fine?
-                        if i != 0 {
-                            if ui.button(RichText::new("▲").monospace()).clicked() {
-                                options.columns.swap(i, i - 1);
-                            }
+                        if i != 0 && ui.button(RichText::new("▲").monospace()).clicked() {
+                            options.columns.swap(i, i - 1);
                         }
-                        if i != options.columns.len() - 1 {
-                            if ui.button(RichText::new("▼").monospace()).clicked() {
-                                options.columns.swap(i, i + 1);
-                            }
+                        if i != options.columns.len() - 1
+                            && ui.button(RichText::new("▼").monospace()).clicked()
+                        {
+                            options.columns.swap(i, i + 1);
                         }
                     });
                 });
@@ -293,25 +302,17 @@ pub fn ui(
             .collect::<Vec<_>>();
 
         // if !options.tree_view || options.table_type == StatsTableType::Grid {
-            egui::Grid::new("table")
-                .striped(true)
-                .spacing([32.0, ui.spacing().item_spacing.y])
-                .show(ui, |ui| {
-                    for &column in &columns {
-                        draw_column_header(ui, options, column);
-                    }
-                    ui.end_row();
-
-                    draw_grid_rows(
-                        scope_infos,
-                        0,
-                        String::new(),
-                        &scopes,
-                        options,
-                        &columns,
-                        ui,
-                    );
-                });
+        egui::Grid::new("table")
+            .striped(true)
+            .spacing([32.0, ui.spacing().item_spacing.y])
+            .show(ui, |ui| {
+                for &column in &columns {
+                    draw_column_header(ui, options, column);
+                }
+                ui.end_row();
+
+                draw_grid_rows(scope_infos, 0, String::new(), &scopes, options, ui);
+            });
         // } else {
         //     ui.columns(columns.len(), |ui| {
         //         for (i, column) in columns.iter().enumerate() {
@@ -342,7 +343,6 @@ fn draw_grid_rows(
     tree_string: String,
     scopes: &[GroupedStats],
     options: &mut Options,
-    columns: &[StatsColumnId],
     ui: &mut Ui,
 ) {
     for (i, stat) in scopes.iter().enumerate() {
@@ -353,9 +353,9 @@ fn draw_grid_rows(
         };
 
         let mut draw_children = false;
-        if options.filter.include(&stat.key.thread_name)
+        if options.filter.include(stat.key.thread_name)
             || options.filter.include(&scope_details.location())
-            || options.filter.include(&scope_details.name())
+            || options.filter.include(scope_details.name())
         {
             ui.horizontal(|ui| {
                 let tree_glyph = if level == 0 {
@@ -366,7 +366,7 @@ fn draw_grid_rows(
                     " ├╴"
                 };
 
-                ui.label(RichText::new(&format!("{}{}", tree_string, tree_glyph)).monospace());
+                ui.label(RichText::new(format!("{tree_string}{tree_glyph}")).monospace());
                 if !stat.children.is_empty() {
                     let expanded = options
                         .tree_view_state
@@ -384,7 +384,7 @@ fn draw_grid_rows(
                 }
             });
             for col in options.columns.iter().skip(1).filter(|col| col.enabled) {
-                draw_column_data(ui, col.id, &stat.key, &scope_details, &stat.scope_stats);
+                draw_column_data(ui, col.id, &stat.key, scope_details, &stat.scope_stats);
             }
             ui.end_row();
         } else {
@@ -406,7 +406,6 @@ fn draw_grid_rows(
                 format!("{}{}", tree_string, tree_glyph),
                 &stat.children,
                 options,
-                columns,
                 ui,
             );
         }
@@ -1,7 +1,9 @@
 use core::cmp::Ordering;
 use std::sync::Arc;
 
-use puffin::{NanoSecond, Reader, ScopeCollection, ScopeId, ThreadInfo, UnpackedFrameData};
+use puffin::{
+    Allocations, NanoSecond, Reader, ScopeCollection, ScopeId, ThreadInfo, UnpackedFrameData,
+};
 
 pub struct GroupedStats<'a> {
     pub key: Key<'a>,
@@ -26,6 +28,10 @@ pub struct ScopeStats {
     /// Time covered by the slowest scope, minus those covered by child scopes.
     /// A lot of time == useful scope.
     pub max_ns: NanoSecond,
+    /// Number of allocations made by all scopes, minus those made by child scopes.
+    pub alloc_count: u64,
+    /// Bytes allocated by all scopes, minus those allocated by child scopes.
+    pub alloc_bytes: u64,
 }
 
 pub struct StatsTotals {
@@ -48,7 +54,10 @@ pub fn process_scopes<'a>(
         for (thread_info, stream) in &frame.thread_streams {
             collect_stream(
                 &mut scopes,
-                &thread_info.name,
+                thread_info
+                    .display_name
+                    .as_deref()
+                    .unwrap_or(&thread_info.name),
                 &stream.stream,
                 options.tree_view,
             )
@@ -127,6 +136,7 @@ fn collect_scope<'s>(
     tree_view: bool,
 ) -> puffin::Result<()> {
     let mut ns_used_by_children = 0;
+    let mut allocations_by_children = Allocations::default();
 
     let key = Key {
         id: scope.id,
@@ -143,7 +153,7 @@ fn collect_scope<'s>(
         });
         index
     };
-    for child_scope in Reader::with_offset(stream, scope.child_begin_position)? {
+    for child_scope in Reader::with_parent(stream, scope)? {
         let child_scope = &child_scope?;
         collect_scope(
             if tree_view {
@@ -157,15 +167,19 @@ fn collect_scope<'s>(
             tree_view,
         )?;
         ns_used_by_children += child_scope.record.duration_ns;
+        allocations_by_children += child_scope.allocations;
     }
 
     let self_time = scope.record.duration_ns.saturating_sub(ns_used_by_children);
+    let self_allocations = scope.allocations.saturating_sub(allocations_by_children);
     let entry = &mut stats[entry_index];
     entry.scope_stats.count += 1;
     entry.scope_stats.bytes += scope_byte_size(scope);
     entry.scope_stats.total_self_ns += self_time;
     entry.scope_stats.total_ns += scope.record.duration_ns;
     entry.scope_stats.max_ns = entry.scope_stats.max_ns.max(self_time);
+    entry.scope_stats.alloc_count += self_allocations.alloc_count;
+    entry.scope_stats.alloc_bytes += self_allocations.alloc_bytes;
 
     Ok(())
 }
@@ -6,7 +6,8 @@ fn main() {
         .ok();
 
     let server_addr = format!("127.0.0.1:{}", puffin_http::DEFAULT_PORT);
-    let _puffin_server = puffin_http::Server::new(&server_addr).unwrap();
+    let mut puffin_server = puffin_http::Server::new(&server_addr).unwrap();
+    puffin_server.announce("puffin_http example").unwrap();
     eprintln!("Serving demo profile data on {server_addr}. Run `puffin_viewer` to view it.");
     puffin::set_scopes_on(true);
 
@@ -1,10 +1,19 @@
-use std::sync::{
-    atomic::{AtomicBool, Ordering::SeqCst},
-    Arc,
+use std::{
+    io::{Read, Write as _},
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Arc,
+    },
+    time::Duration,
 };
 
 use puffin::{FrameData, FrameView};
 
+use crate::{DroppedFrames, SendPolicy};
+
+/// How often the connection thread checks for a new [`SendPolicy`] to send while waiting for frames.
+const POLICY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 /// Connect to a [`crate::Server`], reading profile data
 /// and feeding it to a [`puffin::FrameView`].
 ///
@@ -14,6 +23,16 @@ pub struct Client {
     connected: Arc<AtomicBool>,
     alive: Arc<AtomicBool>,
     frame_view: Arc<parking_lot::Mutex<FrameView>>,
+    send_policy: Arc<parking_lot::Mutex<PolicyQueue>>,
+    dropped_frames: Arc<parking_lot::Mutex<DroppedFrames>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    recording: crate::recording::SharedRecording,
+}
+
+/// The policy we want, and whether the connection thread still has to send it to the server.
+struct PolicyQueue {
+    policy: SendPolicy,
+    pending: bool,
 }
 
 impl Drop for Client {
@@ -36,12 +55,23 @@ impl Client {
         let alive = Arc::new(AtomicBool::new(true));
         let connected = Arc::new(AtomicBool::new(false));
         let frame_view = Arc::new(parking_lot::Mutex::new(FrameView::default()));
+        let send_policy = Arc::new(parking_lot::Mutex::new(PolicyQueue {
+            policy: SendPolicy::default(),
+            pending: false,
+        }));
+        let dropped_frames = Arc::new(parking_lot::Mutex::new(DroppedFrames::default()));
+        #[cfg(not(target_arch = "wasm32"))]
+        let recording = crate::recording::SharedRecording::default();
 
         let client = Self {
             addr: addr.clone(),
             connected: connected.clone(),
             alive: alive.clone(),
             frame_view: frame_view.clone(),
+            send_policy: send_policy.clone(),
+            dropped_frames: dropped_frames.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            recording: recording.clone(),
         };
 
         let _ = std::thread::Builder::new()
@@ -52,14 +82,32 @@ impl Client {
                     match std::net::TcpStream::connect(&addr) {
                         Ok(mut stream) => {
                             *frame_view.lock() = FrameView::default();
+                            *dropped_frames.lock() = DroppedFrames::default();
+                            #[cfg(not(target_arch = "wasm32"))]
+                            if let Some(recording) = recording.lock().as_mut() {
+                                recording.reconnected();
+                            }
                             log::info!("Connected to {}", addr);
                             connected.store(true, SeqCst);
+                            send_policy.lock().pending = true;
+                            if let Err(err) = stream.set_read_timeout(Some(POLICY_POLL_INTERVAL)) {
+                                log::warn!("Failed to set read timeout: {err}");
+                            }
+                            let mut connection = Connection {
+                                stream: &mut stream,
+                                send_policy: &send_policy,
+                                alive: &alive,
+                            };
                             while alive.load(SeqCst) {
-                                match consume_message(&mut stream) {
-                                    Ok(frame_data) => {
-                                        frame_view
-                                            .lock()
-                                            .add_frame(std::sync::Arc::new(frame_data));
+                                match read_message(&mut connection) {
+                                    Ok(message) => {
+                                        let frame_data = Arc::new(message.frame_data);
+                                        frame_view.lock().add_frame(frame_data.clone());
+                                        #[cfg(not(target_arch = "wasm32"))]
+                                        if let Some(recording) = recording.lock().as_mut() {
+                                            recording.record(&frame_data);
+                                        }
+                                        *dropped_frames.lock() += message.dropped_frames;
                                     }
                                     Err(err) => {
                                         log::warn!(
@@ -97,37 +145,157 @@ impl Client {
     pub fn frame_view(&self) -> parking_lot::MutexGuard<'_, FrameView> {
         self.frame_view.lock()
     }
+
+    /// Which frames we ask the server to send us.
+    pub fn send_policy(&self) -> SendPolicy {
+        self.send_policy.lock().policy
+    }
+
+    /// Ask the server to only send us some of the frames, e.g. to save bandwidth.
+    ///
+    /// The policy is kept across reconnects.
+    ///
+    /// It is sent from the connection thread, so this never blocks on the network.
+    pub fn set_send_policy(&self, policy: SendPolicy) {
+        *self.send_policy.lock() = PolicyQueue {
+            policy,
+            pending: true,
+        };
+    }
+
+    /// How many frames the server has not sent us since we connected.
+    pub fn dropped_frames(&self) -> DroppedFrames {
+        *self.dropped_frames.lock()
+    }
+
+    /// Start writing every frame we receive to a `.puffin` file,
+    /// without the limits of [`Self::frame_view`].
+    ///
+    /// Any previous recording is finished first.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn start_recording(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        let scope_collection = self.frame_view.lock().scope_collection().clone();
+        crate::recording::start_recording(&self.recording, path.as_ref(), scope_collection)
+    }
+
+    /// Finish the recording started with [`Self::start_recording`], if any.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn stop_recording(&self) -> anyhow::Result<Option<crate::RecordingInfo>> {
+        crate::recording::stop_recording(&self.recording)
+    }
+
+    /// The state of the current recording, if any.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recording(&self) -> Option<crate::RecordingInfo> {
+        crate::recording::recording_info(&self.recording)
+    }
+}
+
+/// Reads from the server, sending it any new [`SendPolicy`] while waiting for data.
+struct Connection<'a> {
+    stream: &'a mut std::net::TcpStream,
+    send_policy: &'a parking_lot::Mutex<PolicyQueue>,
+    alive: &'a AtomicBool,
+}
+
+impl Connection<'_> {
+    fn send_pending_policy(&mut self) -> std::io::Result<()> {
+        let policy = {
+            let mut send_policy = self.send_policy.lock();
+            if !send_policy.pending {
+                return Ok(());
+            }
+            send_policy.pending = false;
+            send_policy.policy
+        };
+        policy.write_to(self.stream)?;
+        self.stream.flush()
+    }
+}
+
+impl Read for Connection<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            self.send_pending_policy()?;
+            match self.stream.read(buf) {
+                // The read timed out, so check again for a new policy:
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    if !self.alive.load(SeqCst) {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::ConnectionAborted,
+                            "puffin client was dropped",
+                        ));
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// A message from a [`crate::Server`].
+pub struct Message {
+    /// The profiled frame.
+    pub frame_data: FrameData,
+
+    /// Frames the server did not send us since its previous message.
+    pub dropped_frames: DroppedFrames,
 }
 
 /// Read a `puffin_http` message from a stream.
+///
+/// This ignores the dropped frame counts, see [`read_message`].
 pub fn consume_message(stream: &mut impl std::io::Read) -> anyhow::Result<puffin::FrameData> {
+    read_message(stream).map(|message| message.frame_data)
+}
+
+/// Read a `puffin_http` message from a stream.
+///
+/// Messages from servers using protocol version 2 are also accepted,
+/// but never report any dropped frames.
+pub fn read_message(stream: &mut impl std::io::Read) -> anyhow::Result<Message> {
+    /// The oldest protocol version we can still read.
+    const MIN_PROTOCOL_VERSION: u16 = 2;
+
     let mut server_version = [0_u8; 2];
     stream.read_exact(&mut server_version)?;
     let server_version = u16::from_le_bytes(server_version);
 
-    match server_version.cmp(&crate::PROTOCOL_VERSION) {
-        std::cmp::Ordering::Less => {
-            anyhow::bail!(
-                "puffin server is using an older protocol version ({}) than the client ({}).",
-                server_version,
-                crate::PROTOCOL_VERSION
-            );
-        }
-        std::cmp::Ordering::Equal => {}
-        std::cmp::Ordering::Greater => {
-            anyhow::bail!(
-                "puffin server is using a newer protocol version ({}) than the client ({}). Update puffin_viewer with 'cargo install puffin_viewer --locked'.",
-                server_version,
-                crate::PROTOCOL_VERSION
-            );
-        }
+    if server_version < MIN_PROTOCOL_VERSION {
+        anyhow::bail!(
+            "puffin server is using an older protocol version ({}) than the client ({}).",
+            server_version,
+            crate::PROTOCOL_VERSION
+        );
+    } else if crate::PROTOCOL_VERSION < server_version {
+        anyhow::bail!(
+            "puffin server is using a newer protocol version ({}) than the client ({}). Update puffin_viewer with 'cargo install puffin_viewer --locked'.",
+            server_version,
+            crate::PROTOCOL_VERSION
+        );
     }
 
     use anyhow::Context as _;
 
-    FrameData::read_next(stream)
+    let dropped_frames = if server_version == MIN_PROTOCOL_VERSION {
+        DroppedFrames::default()
+    } else {
+        DroppedFrames::read(stream).context("Failed to read dropped frames")?
+    };
+
+    let frame_data = FrameData::read_next(stream)
         .context("Failed to parse FrameData")?
-        .ok_or_else(|| anyhow::format_err!("End of stream"))
+        .ok_or_else(|| anyhow::format_err!("End of stream"))?;
+
+    Ok(Message {
+        frame_data,
+        dropped_frames,
+    })
 }
 
 /// Show full cause chain in a single line
@@ -139,3 +307,60 @@ fn error_display_chain(error: &dyn std::error::Error) -> String {
     }
     s
 }
+
+#[cfg(test)]
+#[cfg(not(target_arch = "wasm32"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send_policy_while_waiting_for_frames() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(10)))
+            .unwrap();
+        let (mut server_stream, _) = listener.accept().unwrap();
+
+        let send_policy = Arc::new(parking_lot::Mutex::new(PolicyQueue {
+            policy: SendPolicy::default(),
+            pending: false,
+        }));
+        let policy = SendPolicy {
+            every_nth_frame: 4,
+            ..Default::default()
+        };
+        let server = std::thread::Builder::new()
+            .spawn({
+                let send_policy = send_policy.clone();
+                move || {
+                    // Set the policy while the client is waiting for data:
+                    std::thread::sleep(Duration::from_millis(50));
+                    *send_policy.lock() = PolicyQueue {
+                        policy,
+                        pending: true,
+                    };
+                    let mut bytes = [0_u8; SendPolicy::NUM_BYTES];
+                    server_stream.read_exact(&mut bytes).unwrap();
+                    server_stream.write_all(&[42]).unwrap();
+                    SendPolicy::from_bytes(&bytes).unwrap()
+                }
+            })
+            .unwrap();
+
+        let alive = AtomicBool::new(true);
+        let mut connection = Connection {
+            stream: &mut stream,
+            send_policy: &send_policy,
+            alive: &alive,
+        };
+        let mut byte = [0_u8; 1];
+        connection.read_exact(&mut byte).unwrap();
+        assert_eq!(byte, [42]);
+        assert_eq!(server.join().unwrap(), policy);
+        assert!(!send_policy.lock().pending);
+
+        alive.store(false, SeqCst);
+        assert!(connection.read_exact(&mut byte).is_err());
+    }
+}
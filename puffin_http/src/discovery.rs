@@ -0,0 +1,385 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+
+/// Every announcement starts with this.
+const MAGIC: &[u8] = b"puffin";
+
+/// How often a server announces itself.
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A server that hasn't announced itself for this long is considered gone.
+const SERVER_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Discovered servers by host, pid and port, and when they were last heard from.
+///
+/// The servers on this machine are keyed by the loopback address, whichever address they were heard from.
+type Servers = HashMap<(IpAddr, u32, u16), (DiscoveredServer, Instant)>;
+
+/// A [`crate::Server`] found with [`Discovery`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredServer {
+    /// The name passed to [`crate::Server::announce`].
+    pub app_name: String,
+
+    /// Process id of the profiled application.
+    pub pid: u32,
+
+    /// Where to connect to.
+    pub addr: SocketAddr,
+
+    /// Was the server started with `Server::new_websocket`?
+    pub websocket: bool,
+
+    /// The [`crate::PROTOCOL_VERSION`] of the server.
+    pub protocol_version: u16,
+}
+
+impl DiscoveredServer {
+    /// Url to connect to, e.g. `127.0.0.1:8585` or `ws://127.0.0.1:8585`.
+    pub fn url(&self) -> String {
+        if self.websocket {
+            format!("ws://{}", self.addr)
+        } else {
+            self.addr.to_string()
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&self.protocol_version.to_le_bytes());
+        bytes.extend_from_slice(&self.addr.port().to_le_bytes());
+        bytes.extend_from_slice(&self.pid.to_le_bytes());
+        bytes.push(self.websocket.into());
+        bytes.extend_from_slice(self.app_name.as_bytes());
+        bytes
+    }
+
+    /// Parse an announcement sent from `sender`.
+    fn from_bytes(bytes: &[u8], sender: SocketAddr) -> Option<Self> {
+        let bytes = bytes.strip_prefix(MAGIC)?;
+        if bytes.len() < 9 {
+            return None;
+        }
+        let protocol_version = u16::from_le_bytes([bytes[0], bytes[1]]);
+        let port = u16::from_le_bytes([bytes[2], bytes[3]]);
+        let pid = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let websocket = bytes[8] != 0;
+        let app_name = String::from_utf8_lossy(&bytes[9..]).into_owned();
+        Some(Self {
+            app_name,
+            pid,
+            addr: SocketAddr::new(sender.ip(), port),
+            websocket,
+            protocol_version,
+        })
+    }
+}
+
+/// Listens for [`crate::Server`]s announcing themselves on the local network.
+///
+/// Only servers that have called [`crate::Server::announce`] can be discovered.
+///
+/// ``` no_run
+/// let discovery = puffin_http::Discovery::new().unwrap();
+/// std::thread::sleep(std::time::Duration::from_secs(2));
+/// for server in discovery.servers() {
+///     println!("{} (pid {}) at {}", server.app_name, server.pid, server.url());
+/// }
+/// ```
+pub struct Discovery {
+    servers: Arc<parking_lot::Mutex<Servers>>,
+    alive: Arc<AtomicBool>,
+}
+
+impl Drop for Discovery {
+    fn drop(&mut self) {
+        self.alive.store(false, SeqCst);
+    }
+}
+
+impl Discovery {
+    /// Start listening for announcements in a background thread.
+    pub fn new() -> anyhow::Result<Self> {
+        let socket = bind_shared(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            crate::DISCOVERY_PORT,
+        ))
+        .context("binding discovery UDP socket")?;
+        socket
+            .set_read_timeout(Some(Duration::from_millis(250)))
+            .context("UDP set_read_timeout")?;
+
+        let servers: Arc<parking_lot::Mutex<Servers>> = Default::default();
+        let alive = Arc::new(AtomicBool::new(true));
+
+        std::thread::Builder::new()
+            .name("puffin-discovery".to_owned())
+            .spawn({
+                let servers = servers.clone();
+                let alive = alive.clone();
+                move || {
+                    let mut buffer = [0_u8; 1024];
+                    let mut local_ips = HashMap::new();
+                    while alive.load(SeqCst) {
+                        match socket.recv_from(&mut buffer) {
+                            Ok((len, sender)) => {
+                                if let Some(server) =
+                                    DiscoveredServer::from_bytes(&buffer[..len], sender)
+                                {
+                                    let on_this_machine = *local_ips
+                                        .entry(sender.ip())
+                                        .or_insert_with(|| is_local_ip(sender.ip()));
+                                    insert_server(&mut servers.lock(), server, on_this_machine);
+                                }
+                            }
+                            Err(err)
+                                if matches!(
+                                    err.kind(),
+                                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                                ) => {}
+                            Err(err) => {
+                                log::warn!("puffin discovery failed: {err}");
+                                break;
+                            }
+                        }
+                    }
+                }
+            })
+            .context("Couldn't spawn thread")?;
+
+        Ok(Self { servers, alive })
+    }
+
+    /// The servers that have recently announced themselves, sorted by name.
+    pub fn servers(&self) -> Vec<DiscoveredServer> {
+        let mut servers = self.servers.lock();
+        servers.retain(|_, (_, last_seen)| last_seen.elapsed() < SERVER_TIMEOUT);
+        let mut servers: Vec<_> = servers.values().map(|(server, _)| server.clone()).collect();
+        servers.sort_by(|a, b| (&a.app_name, a.addr).cmp(&(&b.app_name, b.addr)));
+        servers
+    }
+}
+
+/// Bind a UDP socket that other processes can bind to as well, e.g. another `puffin_viewer`.
+fn bind_shared(addr: SocketAddr) -> std::io::Result<UdpSocket> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(addr),
+        socket2::Type::DGRAM,
+        Some(socket2::Protocol::UDP),
+    )?;
+    socket.set_reuse_address(true)?;
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    socket.set_reuse_port(true)?;
+    socket.bind(&addr.into())?;
+    Ok(socket.into())
+}
+
+/// Does `ip` belong to this machine? Only then can we bind to it.
+fn is_local_ip(ip: IpAddr) -> bool {
+    ip.is_loopback() || UdpSocket::bind(SocketAddr::new(ip, 0)).is_ok()
+}
+
+fn insert_server(servers: &mut Servers, server: DiscoveredServer, on_this_machine: bool) {
+    let host = if on_this_machine {
+        IpAddr::V4(Ipv4Addr::LOCALHOST)
+    } else {
+        server.addr.ip()
+    };
+    let key = (host, server.pid, server.addr.port());
+    let now = Instant::now();
+    match servers.get_mut(&key) {
+        // A server on this machine is heard both over loopback and the network; prefer loopback.
+        Some((existing, last_seen))
+            if existing.addr.ip().is_loopback() && !server.addr.ip().is_loopback() =>
+        {
+            *last_seen = now;
+        }
+        _ => {
+            servers.insert(key, (server, now));
+        }
+    }
+}
+
+/// Listen for announcing servers for a while, and return all that were found.
+///
+/// See [`Discovery`] for listening continuously.
+pub fn discover(duration: Duration) -> anyhow::Result<Vec<DiscoveredServer>> {
+    let discovery = Discovery::new()?;
+    std::thread::sleep(duration);
+    Ok(discovery.servers())
+}
+
+/// Periodically announces a [`crate::Server`] until dropped.
+pub(crate) struct Announcer {
+    stop_tx: Option<crossbeam_channel::Sender<()>>,
+    join_handle: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Drop for Announcer {
+    fn drop(&mut self) {
+        self.stop_tx = None;
+        if let Some(join_handle) = self.join_handle.take() {
+            join_handle.join().ok();
+        }
+    }
+}
+
+impl Announcer {
+    /// Announce a server listening on `server_addr`.
+    ///
+    /// A server only listening on loopback is only announced to this machine.
+    pub(crate) fn start(
+        app_name: String,
+        server_addr: SocketAddr,
+        websocket: bool,
+    ) -> anyhow::Result<Self> {
+        let loopback_only = server_addr.ip().is_loopback();
+        let socket = if loopback_only {
+            UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+        } else {
+            UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        }
+        .context("binding announcement UDP socket")?;
+        socket.set_broadcast(true).context("UDP set_broadcast")?;
+
+        // Every `Discovery` on this machine hears the loopback broadcast (where supported),
+        // but only one of them the unicast:
+        let mut targets = vec![
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), crate::DISCOVERY_PORT),
+            SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::new(127, 255, 255, 255)),
+                crate::DISCOVERY_PORT,
+            ),
+        ];
+        if !loopback_only {
+            targets.push(SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::BROADCAST),
+                crate::DISCOVERY_PORT,
+            ));
+        }
+
+        let announcement = DiscoveredServer {
+            app_name,
+            pid: std::process::id(),
+            addr: server_addr,
+            websocket,
+            protocol_version: crate::PROTOCOL_VERSION,
+        }
+        .to_bytes();
+
+        let (stop_tx, stop_rx) = crossbeam_channel::bounded::<()>(0);
+        let join_handle = std::thread::Builder::new()
+            .name("puffin-server-announce".to_owned())
+            .spawn(move || loop {
+                for target in &targets {
+                    if let Err(err) = socket.send_to(&announcement, target) {
+                        log::trace!("puffin server failed to announce itself to {target}: {err}");
+                    }
+                }
+                if stop_rx.recv_timeout(ANNOUNCE_INTERVAL)
+                    != Err(crossbeam_channel::RecvTimeoutError::Timeout)
+                {
+                    break;
+                }
+            })
+            .context("Couldn't spawn thread")?;
+
+        Ok(Self {
+            stop_tx: Some(stop_tx),
+            join_handle: Some(join_handle),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(ip: IpAddr) -> DiscoveredServer {
+        DiscoveredServer {
+            app_name: "my game".to_owned(),
+            pid: 1234,
+            addr: SocketAddr::new(ip, 8585),
+            websocket: true,
+            protocol_version: crate::PROTOCOL_VERSION,
+        }
+    }
+
+    #[test]
+    fn announcement_roundtrip() {
+        let server = server(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)));
+        let bytes = server.to_bytes();
+
+        // The ip is the sender's, and the port the announced one:
+        let sender = SocketAddr::new(server.addr.ip(), 54321);
+        assert_eq!(DiscoveredServer::from_bytes(&bytes, sender), Some(server));
+    }
+
+    #[test]
+    fn ignore_bad_announcements() {
+        let server = server(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let bytes = server.to_bytes();
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'P';
+        assert_eq!(
+            DiscoveredServer::from_bytes(&wrong_magic, server.addr),
+            None
+        );
+
+        let truncated = &bytes[..MAGIC.len() + 8];
+        assert_eq!(DiscoveredServer::from_bytes(truncated, server.addr), None);
+    }
+
+    #[test]
+    fn prefer_loopback() {
+        let mut servers = Servers::default();
+        let loopback = server(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let network = server(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)));
+        insert_server(&mut servers, loopback.clone(), true);
+        insert_server(&mut servers, network, true);
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers.values().next().unwrap().0, loopback);
+    }
+
+    #[test]
+    fn separate_hosts() {
+        // E.g. two containers, each with its server in process 1:
+        let mut servers = Servers::default();
+        let first = server(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)));
+        let second = server(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 3)));
+        insert_server(&mut servers, first, false);
+        insert_server(&mut servers, second, false);
+        assert_eq!(servers.len(), 2);
+
+        assert!(is_local_ip(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        assert!(!is_local_ip(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7))));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")] // Where every listener hears the loopback broadcast.
+    fn share_discovery_port() {
+        let first = Discovery::new().unwrap();
+        let second = Discovery::new().unwrap();
+        let _announcer = Announcer::start(
+            "shared".to_owned(),
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 8585),
+            false,
+        )
+        .unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+        for discovery in [first, second] {
+            let servers = discovery.servers();
+            assert!(servers.iter().any(|server| server.app_name == "shared"));
+        }
+    }
+}
@@ -10,19 +10,64 @@
 //! eprintln!("Serving demo profile data on {server_addr}. Run `puffin_viewer` to view it.");
 //! puffin::set_scopes_on(true);
 //! ```
+//!
+//! # Discovery
+//! Call [`Server::announce`] to let `puffin_viewer` list your application under "Connect to…",
+//! so you don't need to know which device or port it is serving on.
+//!
+//! # Feature flags
+//! * `websocket`: adds `Server::new_websocket` and `WebSocketClient`,
+//!   so that a browser-hosted `puffin_viewer` can connect to a live application.
 
 /// Bumped on protocol breakage.
-pub const PROTOCOL_VERSION: u16 = 2;
+///
+/// Version 3 added the [`DroppedFrames`] counts to each message,
+/// and lets clients send their [`SendPolicy`] to the server.
+pub const PROTOCOL_VERSION: u16 = 3;
 
 /// The default TCP port used.
 pub const DEFAULT_PORT: u16 = 8585;
 
+/// The UDP port servers announce themselves on, see [`Server::announce`] and [`Discovery`].
+pub const DISCOVERY_PORT: u16 = 8584;
+
 mod client;
+mod send_policy;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod discovery;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod recording;
 
 #[cfg(not(target_arch = "wasm32"))]
 mod server;
 
-pub use client::Client;
+#[cfg(feature = "websocket")]
+mod websocket_client;
+
+pub use client::{consume_message, read_message, Client, Message};
+pub use send_policy::{DroppedFrames, LagPolicy, SendPolicy};
+
+#[cfg(feature = "websocket")]
+pub use websocket_client::WebSocketClient;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use discovery::{discover, DiscoveredServer, Discovery};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use recording::RecordingInfo;
 
 #[cfg(not(target_arch = "wasm32"))]
 pub use server::Server;
+
+/// A frame with a single scope, starting at `frame_index` microseconds.
+#[cfg(test)]
+fn test_frame(frame_index: u64) -> std::sync::Arc<puffin::FrameData> {
+    use puffin::testing::{FrameBuilder, ScopeTree};
+    let start_ns = frame_index as puffin::NanoSecond * 1_000;
+    let frame = FrameBuilder::new(frame_index)
+        .thread("main", [ScopeTree::new("work", start_ns, 500)])
+        .build();
+    std::sync::Arc::new(frame)
+}
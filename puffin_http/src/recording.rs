@@ -0,0 +1,287 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+use puffin::{FrameData, FrameWriter, ScopeCollection};
+
+/// The state of a recording started with e.g. [`crate::Client::start_recording`].
+#[derive(Clone, Debug)]
+pub struct RecordingInfo {
+    /// The `.puffin` file we are writing to.
+    ///
+    /// After reconnecting to the server the recording continues in a new file,
+    /// e.g. `recording-2.puffin`, since the scopes of different connections may clash.
+    pub path: PathBuf,
+
+    /// Number of files written to, see [`Self::path`].
+    pub num_files: usize,
+
+    /// How long we have been recording.
+    pub elapsed: Duration,
+
+    /// Number of frames written, to all files.
+    pub num_frames: usize,
+
+    /// Size of all files so far.
+    pub num_bytes: u64,
+
+    /// Set if writing failed, in which case no more frames are written.
+    pub error: Option<String>,
+}
+
+/// Streams every received frame to a `.puffin` file.
+pub(crate) struct Recording {
+    /// The path the recording was started with.
+    first_path: PathBuf,
+    /// The file we are currently writing to.
+    path: PathBuf,
+    num_files: usize,
+    start_time: Instant,
+    writer: FrameWriter<CountingWriter<BufWriter<File>>>,
+    /// The scopes of the current connection, kept up to date from the frames we record,
+    /// so that we don't need to lock the [`puffin::FrameView`] while writing.
+    scope_collection: ScopeCollection,
+    /// Frames written to earlier files.
+    finished_frames: usize,
+    /// Bytes written to earlier files.
+    finished_bytes: u64,
+    error: Option<String>,
+}
+
+/// A recording shared between a client and its connection thread.
+pub(crate) type SharedRecording = Arc<parking_lot::Mutex<Option<Recording>>>;
+
+impl Recording {
+    /// `scope_collection` holds the scopes received before the recording started.
+    pub(crate) fn start(path: &Path, scope_collection: ScopeCollection) -> anyhow::Result<Self> {
+        let writer = create_writer(path)?;
+        log::info!("Recording to {}", path.display());
+        Ok(Self {
+            first_path: path.to_owned(),
+            path: path.to_owned(),
+            num_files: 1,
+            start_time: Instant::now(),
+            writer,
+            scope_collection,
+            finished_frames: 0,
+            finished_bytes: 0,
+            error: None,
+        })
+    }
+
+    /// Continue in a new file after reconnecting to the server,
+    /// since the new connection may reuse the scope ids and frame indices already written.
+    ///
+    /// Does nothing if nothing has been written to the current file yet.
+    pub(crate) fn reconnected(&mut self) {
+        self.scope_collection = ScopeCollection::default();
+        if self.writer.num_frames() == 0 || self.error.is_some() {
+            return;
+        }
+
+        let path = numbered_path(&self.first_path, self.num_files + 1);
+        let result = create_writer(&path).and_then(|writer| {
+            let previous = std::mem::replace(&mut self.writer, writer);
+            self.finished_frames += previous.num_frames();
+            self.finished_bytes += previous.get_ref().num_bytes;
+            previous
+                .into_inner()
+                .flush()
+                .with_context(|| format!("Failed to write to {}", self.path.display()))
+        });
+        match result {
+            Ok(()) => {
+                log::info!("Reconnected; recording to {}", path.display());
+                self.path = path;
+                self.num_files += 1;
+            }
+            Err(err) => {
+                log::error!("{err:#}");
+                self.error = Some(format!("{err:#}"));
+            }
+        }
+    }
+
+    /// Write a frame, unless an earlier write failed.
+    pub(crate) fn record(&mut self, frame: &FrameData) {
+        for scope in &frame.scope_delta {
+            self.scope_collection.insert(scope.clone());
+        }
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(err) = self.writer.write_frame(frame, &self.scope_collection) {
+            log::error!("Failed to write to {}: {err:#}", self.path.display());
+            self.error = Some(format!("{err:#}"));
+        }
+    }
+
+    pub(crate) fn info(&self) -> RecordingInfo {
+        RecordingInfo {
+            path: self.path.clone(),
+            num_files: self.num_files,
+            elapsed: self.start_time.elapsed(),
+            num_frames: self.finished_frames + self.writer.num_frames(),
+            num_bytes: self.finished_bytes + self.writer.get_ref().num_bytes,
+            error: self.error.clone(),
+        }
+    }
+
+    /// Flush everything to disk.
+    pub(crate) fn finish(mut self) -> anyhow::Result<RecordingInfo> {
+        self.writer
+            .get_mut()
+            .flush()
+            .with_context(|| format!("Failed to write to {}", self.path.display()))?;
+        let info = self.info();
+        log::info!(
+            "Recorded {} frames to {}",
+            info.num_frames,
+            info.path.display()
+        );
+        Ok(info)
+    }
+}
+
+fn create_writer(path: &Path) -> anyhow::Result<FrameWriter<CountingWriter<BufWriter<File>>>> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    FrameWriter::new(CountingWriter {
+        write: BufWriter::new(file),
+        num_bytes: 0,
+    })
+}
+
+/// E.g. `recording-2.puffin` for the second file of `recording.puffin`.
+fn numbered_path(path: &Path, number: usize) -> PathBuf {
+    let mut file_name = path.file_stem().unwrap_or_default().to_owned();
+    file_name.push(format!("-{number}"));
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    path.with_file_name(file_name)
+}
+
+/// Keeps track of how many bytes have been written.
+struct CountingWriter<W> {
+    write: W,
+    num_bytes: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.write.write(buf)?;
+        self.num_bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write.flush()
+    }
+}
+
+/// Start a new recording, finishing any previous one.
+pub(crate) fn start_recording(
+    recording: &SharedRecording,
+    path: &Path,
+    scope_collection: ScopeCollection,
+) -> anyhow::Result<()> {
+    let new_recording = Recording::start(path, scope_collection)?;
+    if let Some(old_recording) = recording.lock().replace(new_recording) {
+        if let Err(err) = old_recording.finish() {
+            log::error!("{err:#}");
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn stop_recording(recording: &SharedRecording) -> anyhow::Result<Option<RecordingInfo>> {
+    recording.lock().take().map(Recording::finish).transpose()
+}
+
+pub(crate) fn recording_info(recording: &SharedRecording) -> Option<RecordingInfo> {
+    recording.lock().as_ref().map(Recording::info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_frames(path: &Path) -> Vec<FrameData> {
+        let bytes = std::fs::read(path).unwrap();
+        let mut bytes = bytes.strip_prefix(b"PUF0").unwrap();
+        let mut frames = vec![];
+        while let Some(frame) = FrameData::read_next(&mut bytes).unwrap() {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    fn frame_indices(frames: &[FrameData]) -> Vec<u64> {
+        frames.iter().map(FrameData::frame_index).collect()
+    }
+
+    fn scope_names(frame: &FrameData) -> Vec<String> {
+        frame
+            .scope_delta
+            .iter()
+            .map(|scope| scope.name().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn record_to_file() {
+        let dir =
+            std::env::temp_dir().join(format!("puffin_http_recording_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("recording.puffin");
+
+        // The scopes were received before the recording started:
+        let mut scope_collection = ScopeCollection::default();
+        scope_collection.insert(puffin::testing::scope_details("work"));
+
+        let recording = SharedRecording::default();
+        start_recording(&recording, &path, scope_collection).unwrap();
+        for frame_index in 0..3 {
+            // Without the scopes, which the server sent earlier:
+            let unpacked = crate::test_frame(frame_index).unpacked().unwrap();
+            let thread_streams = unpacked
+                .thread_streams
+                .iter()
+                .map(|(info, stream_info)| (info.clone(), (**stream_info).clone()))
+                .collect();
+            let frame = FrameData::new(frame_index, thread_streams, vec![], false).unwrap();
+            recording.lock().as_mut().unwrap().record(&frame);
+        }
+        recording.lock().as_mut().unwrap().reconnected();
+        recording.lock().as_mut().unwrap().reconnected(); // Nothing written yet
+        let frame = crate::test_frame(0);
+        recording.lock().as_mut().unwrap().record(&frame);
+
+        let info = stop_recording(&recording).unwrap().unwrap();
+        assert_eq!(info.num_files, 2);
+        assert_eq!(info.num_frames, 4);
+        assert_eq!(info.path, dir.join("recording-2.puffin"));
+        assert_eq!(
+            info.num_bytes,
+            std::fs::metadata(&path).unwrap().len() + std::fs::metadata(&info.path).unwrap().len()
+        );
+        assert!(info.error.is_none());
+        assert!(stop_recording(&recording).unwrap().is_none());
+
+        let frames = read_frames(&path);
+        assert_eq!(frame_indices(&frames), [0, 1, 2]);
+        assert_eq!(scope_names(&frames[0]), ["work"]);
+        let frames = read_frames(&info.path);
+        assert_eq!(frame_indices(&frames), [0]);
+        assert_eq!(scope_names(&frames[0]), ["work"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
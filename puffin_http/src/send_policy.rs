@@ -0,0 +1,214 @@
+use puffin::NanoSecond;
+
+/// What the server should do when a client is not reading frames fast enough.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// Drop the frames that don't fit in the backlog of the client.
+    #[default]
+    Drop,
+
+    /// While the client is lagging behind, merge the frames that don't fit into one summary frame,
+    /// and send that as soon as there is room again.
+    ///
+    /// This way spikes are not lost, even on a slow connection.
+    /// The summary is capped in size, so frames are still dropped if the client lags for long.
+    Merge,
+}
+
+impl LagPolicy {
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::Drop => 0,
+            Self::Merge => 1,
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))] // only used by the server
+    fn from_u8(value: u8) -> anyhow::Result<Self> {
+        match value {
+            0 => Ok(Self::Drop),
+            1 => Ok(Self::Merge),
+            _ => Err(anyhow::anyhow!("Unknown lag policy: {value}")),
+        }
+    }
+}
+
+/// Decides which frames a [`crate::Server`] sends to a specific client.
+///
+/// The client picks its own policy, see e.g. [`crate::Client::set_send_policy`],
+/// so a viewer on a slow connection can ask for less data without affecting other viewers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SendPolicy {
+    /// Only send frames that took at least this long.
+    ///
+    /// `0` means all frames are sent.
+    pub min_frame_duration_ns: NanoSecond,
+
+    /// Only send every Nth frame.
+    ///
+    /// `1` (or `0`) means every frame is sent.
+    pub every_nth_frame: u32,
+
+    /// What to do with frames when the client is lagging behind.
+    pub lag_policy: LagPolicy,
+}
+
+impl Default for SendPolicy {
+    fn default() -> Self {
+        Self {
+            min_frame_duration_ns: 0,
+            every_nth_frame: 1,
+            lag_policy: LagPolicy::Drop,
+        }
+    }
+}
+
+impl SendPolicy {
+    /// Number of bytes of an encoded policy, including the protocol version.
+    pub(crate) const NUM_BYTES: usize = 2 + 8 + 4 + 1;
+
+    /// Should a frame with this duration be sent, given that it is the `frame_count`:th frame
+    /// (counting from 1) seen since the client connected?
+    #[cfg(not(target_arch = "wasm32"))] // only used by the server
+    pub(crate) fn wants(&self, frame_count: u64, duration_ns: NanoSecond) -> bool {
+        let every_nth_frame = u64::from(self.every_nth_frame.max(1));
+        self.min_frame_duration_ns <= duration_ns && frame_count % every_nth_frame == 0
+    }
+
+    /// Ask a server to use this policy, over a raw connection read with [`crate::read_message`].
+    ///
+    /// This is what [`crate::Client::set_send_policy`] does.
+    pub fn write_to(&self, write: &mut impl std::io::Write) -> std::io::Result<()> {
+        write.write_all(&self.to_bytes())
+    }
+
+    /// Encode the policy as a message from the client to the server.
+    pub(crate) fn to_bytes(self) -> [u8; Self::NUM_BYTES] {
+        let mut bytes = [0_u8; Self::NUM_BYTES];
+        bytes[0..2].copy_from_slice(&crate::PROTOCOL_VERSION.to_le_bytes());
+        bytes[2..10].copy_from_slice(&self.min_frame_duration_ns.to_le_bytes());
+        bytes[10..14].copy_from_slice(&self.every_nth_frame.to_le_bytes());
+        bytes[14] = self.lag_policy.to_u8();
+        bytes
+    }
+
+    /// Decode a message written by [`Self::to_bytes`].
+    #[cfg(not(target_arch = "wasm32"))] // only used by the server
+    pub(crate) fn from_bytes(bytes: &[u8; Self::NUM_BYTES]) -> anyhow::Result<Self> {
+        let client_version = u16::from_le_bytes([bytes[0], bytes[1]]);
+        if client_version != crate::PROTOCOL_VERSION {
+            anyhow::bail!(
+                "puffin client is using protocol version {client_version}, but the server is using {}",
+                crate::PROTOCOL_VERSION
+            );
+        }
+
+        let mut min_frame_duration_ns = [0_u8; 8];
+        min_frame_duration_ns.copy_from_slice(&bytes[2..10]);
+        let mut every_nth_frame = [0_u8; 4];
+        every_nth_frame.copy_from_slice(&bytes[10..14]);
+
+        Ok(Self {
+            min_frame_duration_ns: NanoSecond::from_le_bytes(min_frame_duration_ns),
+            every_nth_frame: u32::from_le_bytes(every_nth_frame),
+            lag_policy: LagPolicy::from_u8(bytes[14])?,
+        })
+    }
+}
+
+/// How many frames the server did not send to a client.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DroppedFrames {
+    /// Frames that were not sent because of the client's [`SendPolicy`].
+    pub skipped: u64,
+
+    /// Frames that were dropped because the client was not reading fast enough.
+    pub dropped: u64,
+
+    /// Frames that were not sent one by one because the client was not reading fast enough,
+    /// but merged into the frame of this message, see [`LagPolicy::Merge`].
+    ///
+    /// Not counted in [`Self::total`], since their scopes are not lost.
+    pub merged: u64,
+}
+
+impl DroppedFrames {
+    /// Number of bytes of the encoded counts in a server message.
+    #[cfg(not(target_arch = "wasm32"))] // only used by the server
+    pub(crate) const NUM_BYTES: usize = 8 + 8 + 8;
+
+    /// Total number of frames that were not sent.
+    pub fn total(&self) -> u64 {
+        self.skipped + self.dropped
+    }
+
+    #[cfg(not(target_arch = "wasm32"))] // only used by the server
+    pub(crate) fn to_bytes(self) -> [u8; Self::NUM_BYTES] {
+        let mut bytes = [0_u8; Self::NUM_BYTES];
+        bytes[0..8].copy_from_slice(&self.skipped.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.dropped.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.merged.to_le_bytes());
+        bytes
+    }
+
+    pub(crate) fn read(stream: &mut impl std::io::Read) -> std::io::Result<Self> {
+        let mut skipped = [0_u8; 8];
+        stream.read_exact(&mut skipped)?;
+        let mut dropped = [0_u8; 8];
+        stream.read_exact(&mut dropped)?;
+        let mut merged = [0_u8; 8];
+        stream.read_exact(&mut merged)?;
+        Ok(Self {
+            skipped: u64::from_le_bytes(skipped),
+            dropped: u64::from_le_bytes(dropped),
+            merged: u64::from_le_bytes(merged),
+        })
+    }
+}
+
+impl std::ops::AddAssign for DroppedFrames {
+    fn add_assign(&mut self, rhs: Self) {
+        self.skipped += rhs.skipped;
+        self.dropped += rhs.dropped;
+        self.merged += rhs.merged;
+    }
+}
+
+#[cfg(test)]
+#[cfg(not(target_arch = "wasm32"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send_policy_roundtrip() {
+        let policy = SendPolicy {
+            min_frame_duration_ns: 16_000_000,
+            every_nth_frame: 3,
+            lag_policy: LagPolicy::Merge,
+        };
+        assert_eq!(SendPolicy::from_bytes(&policy.to_bytes()).unwrap(), policy);
+
+        let mut bytes = policy.to_bytes();
+        bytes[0..2].copy_from_slice(&(crate::PROTOCOL_VERSION + 1).to_le_bytes());
+        assert!(SendPolicy::from_bytes(&bytes).is_err());
+
+        let mut bytes = policy.to_bytes();
+        bytes[14] = 42;
+        assert!(SendPolicy::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn dropped_frames_roundtrip() {
+        let dropped_frames = DroppedFrames {
+            skipped: 1,
+            dropped: 2,
+            merged: 3,
+        };
+        let bytes = dropped_frames.to_bytes();
+        assert_eq!(
+            DroppedFrames::read(&mut bytes.as_slice()).unwrap(),
+            dropped_frames
+        );
+        assert!(DroppedFrames::read(&mut &bytes[..10]).is_err());
+    }
+}
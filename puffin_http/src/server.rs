@@ -1,18 +1,32 @@
 use anyhow::Context as _;
 use core::sync::atomic::AtomicBool;
-use puffin::{FrameSinkId, FrameView, GlobalProfiler};
+use puffin::{FrameData, FrameSinkId, FrameView, GlobalProfiler, StreamInfo, ThreadInfo};
 use std::{
-    io::Write,
+    collections::{BTreeMap, HashMap},
+    io::{Read as _, Write},
     net::{SocketAddr, TcpListener, TcpStream},
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
     },
+    time::{Duration, Instant},
 };
 
+use crate::{DroppedFrames, LagPolicy, SendPolicy};
+
 /// Maximum size of the backlog of packets to send to a client if they aren't reading fast enough.
 const MAX_FRAMES_IN_QUEUE: usize = 30;
 
+/// Maximum number of frames merged into one summary frame for a lagging client,
+/// see [`LagPolicy::Merge`]. Any more are dropped.
+const MAX_MERGED_FRAMES: u64 = 100;
+
+/// How often a client thread checks for a new [`SendPolicy`].
+const POLICY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a client thread waits for incoming [`SendPolicy`] messages each time it checks.
+const POLICY_READ_TIMEOUT: Duration = Duration::from_millis(1);
+
 /// Listens for incoming connections
 /// and streams them puffin profiler data.
 ///
@@ -22,37 +36,71 @@ pub struct Server {
     sink_id: FrameSinkId,
     join_handle: Option<std::thread::JoinHandle<()>>,
     num_clients: Arc<AtomicUsize>,
-    sink_remove: fn(FrameSinkId) -> (),
+    sink_remove: Box<dyn Fn(FrameSinkId) + Send>,
     shutdown: Arc<AtomicBool>,
+    local_addr: SocketAddr,
+    transport: Transport,
+    announcer: Option<crate::discovery::Announcer>,
 }
 
 impl Server {
     /// Start listening for connections on this addr (e.g. "0.0.0.0:8585")
     ///
+    /// Each client decides which frames it wants with a [`SendPolicy`],
+    /// and is told how many frames it did not get.
+    ///
     /// Connects to the [GlobalProfiler]
     pub fn new(bind_addr: &str) -> anyhow::Result<Self> {
-        fn global_add(sink: puffin::FrameSink) -> FrameSinkId {
-            GlobalProfiler::lock().add_sink(sink)
-        }
-        fn global_remove(id: FrameSinkId) {
-            GlobalProfiler::lock().remove_sink(id);
-        }
-
         Self::new_custom(bind_addr, global_add, global_remove)
     }
 
+    /// Start listening for WebSocket connections on this addr (e.g. "0.0.0.0:8585").
+    ///
+    /// Each WebSocket binary message contains exactly one message of the same format as
+    /// is sent over plain TCP by [`Self::new`], so it can be read with [`crate::consume_message`].
+    /// This is what a browser-hosted `puffin_viewer` connects to, using [`crate::WebSocketClient`].
+    ///
+    /// Connects to the [GlobalProfiler]
+    #[cfg(feature = "websocket")]
+    pub fn new_websocket(bind_addr: &str) -> anyhow::Result<Self> {
+        Self::new_custom_websocket(bind_addr, global_add, global_remove)
+    }
+
+    /// Like [`Self::new`], but connects to the given [`puffin::Profiler`] instead of the [`GlobalProfiler`].
+    ///
+    /// ```
+    /// let render_profiler = puffin::Profiler::new();
+    /// let server = puffin_http::Server::new_for_profiler("127.0.0.1:0", &render_profiler).unwrap();
+    /// ```
+    pub fn new_for_profiler(bind_addr: &str, profiler: &puffin::Profiler) -> anyhow::Result<Self> {
+        let (install, remove) = profiler_sink_fns(profiler);
+        Self::start(bind_addr, Transport::Tcp, install, remove)
+    }
+
+    /// Like [`Self::new_websocket`], but connects to the given [`puffin::Profiler`] instead of the [`GlobalProfiler`].
+    #[cfg(feature = "websocket")]
+    pub fn new_websocket_for_profiler(
+        bind_addr: &str,
+        profiler: &puffin::Profiler,
+    ) -> anyhow::Result<Self> {
+        let (install, remove) = profiler_sink_fns(profiler);
+        Self::start(bind_addr, Transport::WebSocket, install, remove)
+    }
+
     /// Starts a new puffin server, with a custom function for installing the server's sink
     ///
     /// # Arguments
     /// * `bind_addr` - The address to bind to, when listening for connections
-    /// (e.g. "localhost:8585" or "127.0.0.1:8585")
+    ///   (e.g. "localhost:8585" or "127.0.0.1:8585")
     /// * `sink_install` - A function that installs the [Server]'s sink into
-    /// a [`GlobalProfiler`], and then returns the [`FrameSinkId`] so that the sink can be removed later
+    ///   a [`GlobalProfiler`], and then returns the [`FrameSinkId`] so that the sink can be removed later
     /// * `sink_remove` - A function that reverts `sink_install`.
-    /// This should be a call to remove the sink from the profiler ([GlobalProfiler::remove_sink])
+    ///   This should be a call to remove the sink from the profiler ([GlobalProfiler::remove_sink])
     ///
     /// # Example
     ///
+    /// Usually [`Self::new_for_profiler`] is simpler.
+    ///
     /// Using this is slightly complicated, but it is possible to use this to set a custom profiler per-thread,
     /// such that threads can be grouped together and profiled separately. E.g. you could have one profiling server
     /// instance for the main UI loop, and another for the background worker loop, and events/frames from those thread(s)
@@ -227,11 +275,41 @@ impl Server {
         bind_addr: &str,
         sink_install: fn(puffin::FrameSink) -> FrameSinkId,
         sink_remove: fn(FrameSinkId) -> (),
+    ) -> anyhow::Result<Self> {
+        Self::start(
+            bind_addr,
+            Transport::Tcp,
+            sink_install,
+            Box::new(sink_remove),
+        )
+    }
+
+    /// Like [`Self::new_custom`], but serves WebSocket connections (see [`Self::new_websocket`]).
+    #[cfg(feature = "websocket")]
+    pub fn new_custom_websocket(
+        bind_addr: &str,
+        sink_install: fn(puffin::FrameSink) -> FrameSinkId,
+        sink_remove: fn(FrameSinkId) -> (),
+    ) -> anyhow::Result<Self> {
+        Self::start(
+            bind_addr,
+            Transport::WebSocket,
+            sink_install,
+            Box::new(sink_remove),
+        )
+    }
+
+    fn start(
+        bind_addr: &str,
+        transport: Transport,
+        sink_install: impl FnOnce(puffin::FrameSink) -> FrameSinkId,
+        sink_remove: Box<dyn Fn(FrameSinkId) + Send>,
     ) -> anyhow::Result<Self> {
         let tcp_listener = TcpListener::bind(bind_addr).context("binding server TCP socket")?;
         tcp_listener
             .set_nonblocking(true)
             .context("TCP set_nonblocking")?;
+        let local_addr = tcp_listener.local_addr().context("TCP local_addr")?;
 
         // We use crossbeam_channel instead of `mpsc`,
         // because on shutdown we want all frames to be sent.
@@ -251,9 +329,9 @@ impl Server {
                 move || {
                     let mut server_impl = PuffinServerImpl {
                         tcp_listener,
+                        transport,
                         clients: Default::default(),
                         num_clients: num_clients_cloned,
-                        send_all_scopes: false,
                         frame_view: Default::default(),
                     };
 
@@ -282,6 +360,9 @@ impl Server {
             join_handle: Some(join_handle),
             num_clients,
             sink_remove,
+            local_addr,
+            transport,
+            announcer: None,
         })
     }
 
@@ -289,10 +370,65 @@ impl Server {
     pub fn num_clients(&self) -> usize {
         self.num_clients.load(Ordering::SeqCst)
     }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Announce this server on the local network, so that `puffin_viewer` can find it
+    /// (see [`crate::Discovery`]).
+    ///
+    /// Once a second, the app name, process id and port are broadcast over UDP on [`crate::DISCOVERY_PORT`].
+    /// A server bound to a loopback address (e.g. "127.0.0.1:8585") is only announced to the local machine.
+    ///
+    /// Calling this again replaces the previous announcement.
+    ///
+    /// ```
+    /// let mut server = puffin_http::Server::new("127.0.0.1:0").unwrap();
+    /// server.announce("my_game").unwrap();
+    /// ```
+    pub fn announce(&mut self, app_name: &str) -> anyhow::Result<()> {
+        self.announcer = None;
+        self.announcer = Some(crate::discovery::Announcer::start(
+            app_name.to_owned(),
+            self.local_addr,
+            self.transport != Transport::Tcp,
+        )?);
+        Ok(())
+    }
+}
+
+fn global_add(sink: puffin::FrameSink) -> FrameSinkId {
+    GlobalProfiler::lock().add_sink(sink)
+}
+
+fn global_remove(id: FrameSinkId) {
+    GlobalProfiler::lock().remove_sink(id);
+}
+
+type SinkInstall = Box<dyn FnOnce(puffin::FrameSink) -> FrameSinkId>;
+type SinkRemove = Box<dyn Fn(FrameSinkId) + Send>;
+
+fn profiler_sink_fns(profiler: &puffin::Profiler) -> (SinkInstall, SinkRemove) {
+    let install = {
+        let profiler = profiler.clone();
+        move |sink| profiler.add_sink(sink)
+    };
+    let remove = {
+        let profiler = profiler.clone();
+        move |id| {
+            profiler.remove_sink(id);
+        }
+    };
+    (Box::new(install), Box::new(remove))
 }
 
 impl Drop for Server {
     fn drop(&mut self) {
+        // Stop announcing ourselves:
+        self.announcer = None;
+
         // Remove ourselves from the profiler
         (self.sink_remove)(self.sink_id);
 
@@ -304,12 +440,236 @@ impl Drop for Server {
     }
 }
 
+/// An encoded [`FrameData`], shared between all clients that are sent it.
 type Packet = Arc<[u8]>;
 
+/// What is sent to a client: the protocol version and [`DroppedFrames`] header,
+/// followed by the frame.
+struct Message {
+    header: [u8; MESSAGE_HEADER_NUM_BYTES],
+    packet: Packet,
+}
+
+const MESSAGE_HEADER_NUM_BYTES: usize = 2 + DroppedFrames::NUM_BYTES;
+
+impl Message {
+    fn new(dropped_frames: DroppedFrames, packet: Packet) -> Self {
+        let mut header = [0_u8; MESSAGE_HEADER_NUM_BYTES];
+        header[..2].copy_from_slice(&crate::PROTOCOL_VERSION.to_le_bytes());
+        header[2..].copy_from_slice(&dropped_frames.to_bytes());
+        Self { header, packet }
+    }
+}
+
+/// Encodes each frame at most once with, and once without, all scopes.
+struct PacketCache<'a> {
+    frame_view: &'a FrameView,
+    packets: HashMap<(u64, bool), Packet>,
+}
+
+impl PacketCache<'_> {
+    fn get(&mut self, frame: &FrameData, send_all_scopes: bool) -> anyhow::Result<Packet> {
+        let key = (frame.frame_index(), send_all_scopes);
+        if let Some(packet) = self.packets.get(&key) {
+            return Ok(packet.clone());
+        }
+
+        let mut packet = vec![];
+        frame
+            .write_into(
+                self.frame_view.scope_collection(),
+                send_all_scopes,
+                &mut packet,
+            )
+            .context("Encode puffin frame")?;
+        let packet: Packet = packet.into();
+        self.packets.insert(key, packet.clone());
+        Ok(packet)
+    }
+
+    /// Encode a frame that is only sent to one client, so there is no point in caching it.
+    fn encode_uncached(&self, frame: &FrameData, send_all_scopes: bool) -> anyhow::Result<Packet> {
+        let mut packet = vec![];
+        frame
+            .write_into(
+                self.frame_view.scope_collection(),
+                send_all_scopes,
+                &mut packet,
+            )
+            .context("Encode puffin frame")?;
+        Ok(packet.into())
+    }
+}
+
+/// Frames merged into one summary frame while a client is lagging, see [`LagPolicy::Merge`].
+struct MergedFrames {
+    /// The index of the last merged frame, used for the summary frame.
+    frame_index: u64,
+    thread_streams: BTreeMap<ThreadInfo, StreamInfo>,
+    num_frames: u64,
+}
+
+impl MergedFrames {
+    fn new(frame: &FrameData) -> anyhow::Result<Self> {
+        let mut merged = Self {
+            frame_index: frame.frame_index(),
+            thread_streams: BTreeMap::new(),
+            num_frames: 0,
+        };
+        merged.add(frame)?;
+        Ok(merged)
+    }
+
+    fn add(&mut self, frame: &FrameData) -> anyhow::Result<()> {
+        let unpacked = frame.unpacked()?;
+        for (thread_info, stream_info) in &unpacked.thread_streams {
+            self.thread_streams
+                .entry(thread_info.clone())
+                .or_default()
+                .extend(&stream_info.as_stream_into_ref());
+        }
+        self.frame_index = frame.frame_index();
+        self.num_frames += 1;
+        Ok(())
+    }
+
+    fn to_frame(&self) -> anyhow::Result<FrameData> {
+        // The scopes are sent by `send_all_scopes`, which is set whenever a frame is merged.
+        FrameData::new(self.frame_index, self.thread_streams.clone(), vec![], false)
+            .map_err(|err| anyhow::anyhow!("Failed to merge puffin frames: {err:?}"))
+    }
+}
+
+/// How packets are framed on the wire.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Transport {
+    /// Packets are written back-to-back to the TCP stream.
+    Tcp,
+
+    /// Each packet is sent as one binary WebSocket message.
+    #[cfg(feature = "websocket")]
+    WebSocket,
+}
+
+enum SendResult {
+    Sent,
+    Full,
+    Disconnected,
+}
+
 struct Client {
     client_addr: SocketAddr,
-    packet_tx: Option<crossbeam_channel::Sender<Packet>>,
+    packet_tx: Option<crossbeam_channel::Sender<Message>>,
     join_handle: Option<std::thread::JoinHandle<()>>,
+
+    /// Set by the client thread whenever the client sends us a new policy.
+    send_policy: Arc<parking_lot::Mutex<SendPolicy>>,
+
+    /// Number of frames offered to this client since it connected.
+    num_frames_seen: u64,
+
+    /// Frames not sent since the last message to this client.
+    dropped_frames: DroppedFrames,
+
+    /// Set when the client has missed a frame, and so may have missed new scopes.
+    send_all_scopes: bool,
+
+    /// The frames we couldn't send because the client was lagging (see [`LagPolicy::Merge`]).
+    merged: Option<MergedFrames>,
+}
+
+impl Client {
+    /// Send the frame if the client's [`SendPolicy`] wants it and it has room for it.
+    ///
+    /// Returns `false` if the client has disconnected.
+    fn offer(
+        &mut self,
+        frame: &Arc<FrameData>,
+        packets: &mut PacketCache<'_>,
+    ) -> anyhow::Result<bool> {
+        let policy = *self.send_policy.lock();
+        self.num_frames_seen += 1;
+
+        if let Some(merged) = self.merged.take() {
+            let num_merged = merged.num_frames;
+            match self.try_send(num_merged, |send_all_scopes| {
+                packets.encode_uncached(&merged.to_frame()?, send_all_scopes)
+            })? {
+                SendResult::Sent => {}
+                SendResult::Full => self.merged = Some(merged),
+                SendResult::Disconnected => return Ok(false),
+            }
+        }
+
+        if !policy.wants(self.num_frames_seen, frame.duration_ns()) {
+            self.dropped_frames.skipped += 1;
+            self.send_all_scopes = true;
+            return Ok(true);
+        }
+
+        // Keep the frames in order by not sending anything until the merged frames are sent:
+        let result = if self.merged.is_some() {
+            SendResult::Full
+        } else {
+            self.try_send(0, |send_all_scopes| packets.get(frame, send_all_scopes))?
+        };
+
+        match result {
+            SendResult::Sent => {}
+            SendResult::Disconnected => return Ok(false),
+            SendResult::Full => {
+                self.send_all_scopes = true;
+                match (policy.lag_policy, &mut self.merged) {
+                    (LagPolicy::Merge, None) => {
+                        self.merged = Some(MergedFrames::new(frame)?);
+                    }
+                    (LagPolicy::Merge, Some(merged)) if merged.num_frames < MAX_MERGED_FRAMES => {
+                        merged.add(frame)?;
+                    }
+                    _ => {
+                        log::info!(
+                            "puffin client {} is not accepting data fast enough; dropping a frame",
+                            self.client_addr
+                        );
+                        self.dropped_frames.dropped += 1;
+                    }
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Send a packet, unless the client's queue is full.
+    ///
+    /// `num_merged` is the number of frames merged into the packet, see [`DroppedFrames::merged`].
+    fn try_send(
+        &mut self,
+        num_merged: u64,
+        encode: impl FnOnce(bool) -> anyhow::Result<Packet>,
+    ) -> anyhow::Result<SendResult> {
+        let Some(packet_tx) = &self.packet_tx else {
+            return Ok(SendResult::Disconnected);
+        };
+        if packet_tx.is_full() {
+            return Ok(SendResult::Full);
+        }
+
+        let packet = encode(self.send_all_scopes)?;
+        let dropped_frames = DroppedFrames {
+            merged: num_merged,
+            ..self.dropped_frames
+        };
+        match packet_tx.try_send(Message::new(dropped_frames, packet)) {
+            Ok(()) => {
+                self.dropped_frames = DroppedFrames::default();
+                self.send_all_scopes = false;
+                Ok(SendResult::Sent)
+            }
+            Err(crossbeam_channel::TrySendError::Full(_)) => Ok(SendResult::Full),
+            Err(crossbeam_channel::TrySendError::Disconnected(_)) => Ok(SendResult::Disconnected),
+        }
+    }
 }
 
 impl Drop for Client {
@@ -330,9 +690,9 @@ impl Drop for Client {
 /// and streams them puffin profiler data.
 struct PuffinServerImpl {
     tcp_listener: TcpListener,
+    transport: Transport,
     clients: Vec<Client>,
     num_clients: Arc<AtomicUsize>,
-    send_all_scopes: bool,
     frame_view: FrameView,
 }
 
@@ -348,18 +708,40 @@ impl PuffinServerImpl {
                     log::info!("{} connected", client_addr);
 
                     let (packet_tx, packet_rx) = crossbeam_channel::bounded(MAX_FRAMES_IN_QUEUE);
+                    let send_policy = Arc::new(parking_lot::Mutex::new(SendPolicy::default()));
 
+                    let transport = self.transport;
                     let join_handle = std::thread::Builder::new()
                         .name("puffin-server-client".to_owned())
-                        .spawn(move || client_loop(packet_rx, client_addr, tcp_stream))
+                        .spawn({
+                            let send_policy = send_policy.clone();
+                            move || match transport {
+                                Transport::Tcp => {
+                                    client_loop(packet_rx, client_addr, tcp_stream, &send_policy);
+                                }
+                                #[cfg(feature = "websocket")]
+                                Transport::WebSocket => {
+                                    websocket_client_loop(
+                                        packet_rx,
+                                        client_addr,
+                                        tcp_stream,
+                                        &send_policy,
+                                    );
+                                }
+                            }
+                        })
                         .context("Couldn't spawn thread")?;
 
-                    // Send all scopes when new client connects.
-                    self.send_all_scopes = true;
                     self.clients.push(Client {
                         client_addr,
                         packet_tx: Some(packet_tx),
                         join_handle: Some(join_handle),
+                        send_policy,
+                        num_frames_seen: 0,
+                        dropped_frames: Default::default(),
+                        // Send all scopes when new client connects.
+                        send_all_scopes: true,
+                        merged: None,
                     });
                     self.num_clients.store(self.clients.len(), Ordering::SeqCst);
                 }
@@ -382,63 +764,263 @@ impl PuffinServerImpl {
         Ok(())
     }
 
-    pub fn send(&mut self, frame: &puffin::FrameData) -> anyhow::Result<()> {
+    pub fn send(&mut self, frame: &Arc<FrameData>) -> anyhow::Result<()> {
         if self.clients.is_empty() {
             return Ok(());
         }
         puffin::profile_function!();
 
-        let mut packet = vec![];
+        let mut packets = PacketCache {
+            frame_view: &self.frame_view,
+            packets: Default::default(),
+        };
 
-        packet
-            .write_all(&crate::PROTOCOL_VERSION.to_le_bytes())
-            .unwrap();
+        let mut result = Ok(());
+        self.clients
+            .retain_mut(|client| match client.offer(frame, &mut packets) {
+                Ok(connected) => connected,
+                Err(err) => {
+                    result = Err(err);
+                    true
+                }
+            });
+        self.num_clients.store(self.clients.len(), Ordering::SeqCst);
 
-        frame
-            .write_into(
-                self.frame_view.scope_collection(),
-                self.send_all_scopes,
-                &mut packet,
-            )
-            .context("Encode puffin frame")?;
-        self.send_all_scopes = false;
+        result
+    }
+}
 
-        let packet: Packet = packet.into();
+fn client_loop(
+    packet_rx: crossbeam_channel::Receiver<Message>,
+    client_addr: SocketAddr,
+    mut tcp_stream: TcpStream,
+    send_policy: &parking_lot::Mutex<SendPolicy>,
+) {
+    if let Err(err) = tcp_stream.set_read_timeout(Some(POLICY_READ_TIMEOUT)) {
+        log::info!("puffin server failed to set read timeout for {client_addr}: {err}");
+        return;
+    }
 
-        self.clients.retain(|client| match &client.packet_tx {
-            None => false,
-            Some(packet_tx) => match packet_tx.try_send(packet.clone()) {
-                Ok(()) => true,
-                Err(crossbeam_channel::TrySendError::Disconnected(_)) => false,
-                Err(crossbeam_channel::TrySendError::Full(_)) => {
+    let mut received = vec![];
+    let mut last_policy_check = Instant::now();
+    loop {
+        match packet_rx.recv_timeout(POLICY_POLL_INTERVAL) {
+            Ok(message) => {
+                if let Err(err) = tcp_stream
+                    .write_all(&message.header)
+                    .and_then(|()| tcp_stream.write_all(&message.packet))
+                {
                     log::info!(
-                        "puffin client {} is not accepting data fast enough; dropping a frame",
-                        client.client_addr
+                        "puffin server failed sending to {}: {} (kind: {:?})",
+                        client_addr,
+                        err,
+                        err.kind()
                     );
-                    true
+                    break;
                 }
-            },
-        });
-        self.num_clients.store(self.clients.len(), Ordering::SeqCst);
+            }
+            Err(crossbeam_channel::RecvTimeoutError::Timeout) => {}
+            Err(crossbeam_channel::RecvTimeoutError::Disconnected) => break,
+        }
 
-        Ok(())
+        // Reading blocks for a little while, so don't do it for every message:
+        if last_policy_check.elapsed() < POLICY_POLL_INTERVAL {
+            continue;
+        }
+        last_policy_check = Instant::now();
+
+        match receive_send_policies(&mut tcp_stream, &mut received, send_policy) {
+            Ok(true) => {}
+            Ok(false) => {
+                log::info!("puffin client {client_addr} disconnected");
+                break;
+            }
+            Err(err) => {
+                log::info!("puffin server failed reading from {client_addr}: {err:#}");
+                break;
+            }
+        }
     }
 }
 
-fn client_loop(
-    packet_rx: crossbeam_channel::Receiver<Packet>,
+/// Read any [`SendPolicy`] the client has sent us, waiting at most [`POLICY_READ_TIMEOUT`].
+///
+/// Returns `false` if the client closed the connection.
+fn receive_send_policies(
+    tcp_stream: &mut TcpStream,
+    received: &mut Vec<u8>,
+    send_policy: &parking_lot::Mutex<SendPolicy>,
+) -> anyhow::Result<bool> {
+    let mut buffer = [0_u8; 64];
+    loop {
+        match tcp_stream.read(&mut buffer) {
+            Ok(0) => return Ok(false),
+            Ok(n) => received.extend_from_slice(&buffer[..n]),
+            Err(err) if is_timeout(&err) => break,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    while received.len() >= SendPolicy::NUM_BYTES {
+        let mut bytes = [0_u8; SendPolicy::NUM_BYTES];
+        bytes.copy_from_slice(&received[..SendPolicy::NUM_BYTES]);
+        received.drain(..SendPolicy::NUM_BYTES);
+        *send_policy.lock() = SendPolicy::from_bytes(&bytes)?;
+    }
+
+    Ok(true)
+}
+
+fn is_timeout(err: &std::io::Error) -> bool {
+    matches!(
+        err.kind(),
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+    )
+}
+
+#[cfg(feature = "websocket")]
+fn websocket_client_loop(
+    packet_rx: crossbeam_channel::Receiver<Message>,
     client_addr: SocketAddr,
-    mut tcp_stream: TcpStream,
+    tcp_stream: TcpStream,
+    send_policy: &parking_lot::Mutex<SendPolicy>,
 ) {
-    while let Ok(packet) = packet_rx.recv() {
-        if let Err(err) = tcp_stream.write_all(&packet) {
-            log::info!(
-                "puffin server failed sending to {}: {} (kind: {:?})",
-                client_addr,
-                err,
-                err.kind()
-            );
-            break;
+    let mut websocket = match tungstenite::accept(tcp_stream) {
+        Ok(websocket) => websocket,
+        Err(err) => {
+            log::info!("puffin server WebSocket handshake with {client_addr} failed: {err}");
+            return;
+        }
+    };
+
+    if let Err(err) = websocket
+        .get_ref()
+        .set_read_timeout(Some(POLICY_READ_TIMEOUT))
+    {
+        log::info!("puffin server failed to set read timeout for {client_addr}: {err}");
+        return;
+    }
+
+    let mut last_policy_check = Instant::now();
+    loop {
+        match packet_rx.recv_timeout(POLICY_POLL_INTERVAL) {
+            Ok(message) => {
+                let mut bytes = Vec::with_capacity(message.header.len() + message.packet.len());
+                bytes.extend_from_slice(&message.header);
+                bytes.extend_from_slice(&message.packet);
+                if let Err(err) = websocket.send(tungstenite::Message::binary(bytes)) {
+                    log::info!("puffin server failed sending to {client_addr}: {err}");
+                    break;
+                }
+            }
+            Err(crossbeam_channel::RecvTimeoutError::Timeout) => {}
+            Err(crossbeam_channel::RecvTimeoutError::Disconnected) => break,
+        }
+
+        // Reading blocks for a little while, so don't do it for every message:
+        if last_policy_check.elapsed() < POLICY_POLL_INTERVAL {
+            continue;
         }
+        last_policy_check = Instant::now();
+
+        match receive_websocket_send_policies(&mut websocket, send_policy) {
+            Ok(true) => {}
+            Ok(false) => {
+                log::info!("puffin client {client_addr} disconnected");
+                break;
+            }
+            Err(err) => {
+                log::info!("puffin server failed reading from {client_addr}: {err:#}");
+                break;
+            }
+        }
+    }
+
+    websocket.close(None).ok();
+    websocket.flush().ok();
+}
+
+/// Like [`receive_send_policies`], but each policy is its own binary WebSocket message.
+#[cfg(feature = "websocket")]
+fn receive_websocket_send_policies(
+    websocket: &mut tungstenite::WebSocket<TcpStream>,
+    send_policy: &parking_lot::Mutex<SendPolicy>,
+) -> anyhow::Result<bool> {
+    loop {
+        match websocket.read() {
+            Ok(tungstenite::Message::Binary(bytes)) => {
+                let bytes = bytes
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_err| anyhow::anyhow!("Unexpected message from client"))?;
+                *send_policy.lock() = SendPolicy::from_bytes(bytes)?;
+            }
+            Ok(tungstenite::Message::Close(_)) => return Ok(false),
+            Ok(_) => {} // ping, pong, text, etc.
+            Err(tungstenite::Error::Io(err)) if is_timeout(&err) => return Ok(true),
+            Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                return Ok(false);
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(message: &Message) -> crate::Message {
+        let mut bytes = message.header.to_vec();
+        bytes.extend_from_slice(&message.packet);
+        crate::read_message(&mut bytes.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn merge_frames_while_lagging() {
+        let (packet_tx, packet_rx) = crossbeam_channel::bounded(1);
+        let mut client = Client {
+            client_addr: "127.0.0.1:0".parse().unwrap(),
+            packet_tx: Some(packet_tx),
+            join_handle: None,
+            send_policy: Arc::new(parking_lot::Mutex::new(SendPolicy {
+                lag_policy: LagPolicy::Merge,
+                ..Default::default()
+            })),
+            num_frames_seen: 0,
+            dropped_frames: Default::default(),
+            send_all_scopes: true,
+            merged: None,
+        };
+        let frame_view = FrameView::default();
+        let mut packets = PacketCache {
+            frame_view: &frame_view,
+            packets: Default::default(),
+        };
+
+        for frame_index in 0..3 {
+            assert!(client
+                .offer(&crate::test_frame(frame_index), &mut packets)
+                .unwrap());
+        }
+        let message = read(&packet_rx.try_recv().unwrap());
+        assert_eq!(message.frame_data.frame_index(), 0);
+        assert_eq!(message.dropped_frames, DroppedFrames::default());
+
+        // Now there is room for the frames that didn't fit:
+        assert!(client.offer(&crate::test_frame(3), &mut packets).unwrap());
+        let message = read(&packet_rx.try_recv().unwrap());
+        assert_eq!(message.frame_data.frame_index(), 2);
+        assert_eq!(message.frame_data.range_ns(), (1_000, 2_500));
+        assert_eq!(message.frame_data.meta().num_scopes, 2);
+        assert_eq!(
+            message.dropped_frames,
+            DroppedFrames {
+                merged: 2,
+                ..Default::default()
+            }
+        );
+        assert_eq!(client.merged.as_ref().unwrap().num_frames, 1);
     }
 }
@@ -0,0 +1,330 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering::SeqCst},
+    Arc,
+};
+
+use puffin::FrameView;
+
+use crate::{DroppedFrames, SendPolicy};
+
+/// Connect to a [`crate::Server`] started with `Server::new_websocket`,
+/// reading profile data and feeding it to a [`puffin::FrameView`].
+///
+/// Unlike [`crate::Client`] this also works in a browser (when compiling for wasm),
+/// where it uses the browser's `WebSocket` API.
+///
+/// Will retry connection until it succeeds, and reconnect on failures.
+pub struct WebSocketClient {
+    url: String,
+    connected: Arc<AtomicBool>,
+    alive: Arc<AtomicBool>,
+    frame_view: Arc<parking_lot::Mutex<FrameView>>,
+    send_policy: Arc<parking_lot::Mutex<SendPolicy>>,
+    send_policy_changed: Arc<AtomicBool>,
+    dropped_frames: Arc<parking_lot::Mutex<DroppedFrames>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    recording: crate::recording::SharedRecording,
+}
+
+impl Drop for WebSocketClient {
+    fn drop(&mut self) {
+        self.alive.store(false, SeqCst);
+    }
+}
+
+impl WebSocketClient {
+    /// Connects to the given WebSocket url (e.g. `ws://127.0.0.1:8585`)
+    /// and receives puffin profile data.
+    ///
+    /// You can then view the data with
+    /// [`puffin_egui`](https://crates.io/crates/puffin_egui).
+    ///
+    /// ``` no_run
+    /// puffin_http::WebSocketClient::new("ws://127.0.0.1:8585".to_owned());
+    /// ```
+    pub fn new(url: String) -> Self {
+        let client = Self {
+            url,
+            connected: Arc::new(AtomicBool::new(false)),
+            alive: Arc::new(AtomicBool::new(true)),
+            frame_view: Arc::new(parking_lot::Mutex::new(FrameView::default())),
+            send_policy: Arc::new(parking_lot::Mutex::new(SendPolicy::default())),
+            send_policy_changed: Arc::new(AtomicBool::new(false)),
+            dropped_frames: Arc::new(parking_lot::Mutex::new(DroppedFrames::default())),
+            #[cfg(not(target_arch = "wasm32"))]
+            recording: Default::default(),
+        };
+
+        log::info!("Connecting to {}…", client.url);
+        Connection {
+            url: client.url.clone(),
+            connected: client.connected.clone(),
+            alive: client.alive.clone(),
+            frame_view: client.frame_view.clone(),
+            send_policy: client.send_policy.clone(),
+            send_policy_changed: client.send_policy_changed.clone(),
+            dropped_frames: client.dropped_frames.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            recording: client.recording.clone(),
+        }
+        .start();
+
+        client
+    }
+
+    /// The url we are connected to or trying to connect to.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Are we currently connect to the server?
+    pub fn connected(&self) -> bool {
+        self.connected.load(SeqCst)
+    }
+
+    /// Get the current data.
+    pub fn frame_view(&self) -> parking_lot::MutexGuard<'_, FrameView> {
+        self.frame_view.lock()
+    }
+
+    /// Which frames we ask the server to send us.
+    pub fn send_policy(&self) -> SendPolicy {
+        *self.send_policy.lock()
+    }
+
+    /// Ask the server to only send us some of the frames, e.g. to save bandwidth.
+    ///
+    /// The policy is kept across reconnects.
+    pub fn set_send_policy(&self, policy: SendPolicy) {
+        *self.send_policy.lock() = policy;
+        self.send_policy_changed.store(true, SeqCst);
+    }
+
+    /// How many frames the server has not sent us since we connected.
+    pub fn dropped_frames(&self) -> DroppedFrames {
+        *self.dropped_frames.lock()
+    }
+
+    /// Start writing every frame we receive to a `.puffin` file,
+    /// without the limits of [`Self::frame_view`].
+    ///
+    /// Any previous recording is finished first.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn start_recording(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        let scope_collection = self.frame_view.lock().scope_collection().clone();
+        crate::recording::start_recording(&self.recording, path.as_ref(), scope_collection)
+    }
+
+    /// Finish the recording started with [`Self::start_recording`], if any.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn stop_recording(&self) -> anyhow::Result<Option<crate::RecordingInfo>> {
+        crate::recording::stop_recording(&self.recording)
+    }
+
+    /// The state of the current recording, if any.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recording(&self) -> Option<crate::RecordingInfo> {
+        crate::recording::recording_info(&self.recording)
+    }
+}
+
+/// State shared between the [`WebSocketClient`] and its background connection.
+#[derive(Clone)]
+struct Connection {
+    url: String,
+    connected: Arc<AtomicBool>,
+    alive: Arc<AtomicBool>,
+    frame_view: Arc<parking_lot::Mutex<FrameView>>,
+    send_policy: Arc<parking_lot::Mutex<SendPolicy>>,
+    send_policy_changed: Arc<AtomicBool>,
+    dropped_frames: Arc<parking_lot::Mutex<DroppedFrames>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    recording: crate::recording::SharedRecording,
+}
+
+impl Connection {
+    fn on_connected(&self) {
+        *self.frame_view.lock() = FrameView::default();
+        *self.dropped_frames.lock() = DroppedFrames::default();
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(recording) = self.recording.lock().as_mut() {
+            recording.reconnected();
+        }
+        log::info!("Connected to {}", self.url);
+        self.connected.store(true, SeqCst);
+        // Always tell a new server what we want:
+        self.send_policy_changed.store(true, SeqCst);
+    }
+
+    /// The encoded [`SendPolicy`] to send to the server, if it has changed since last time.
+    fn changed_send_policy(&self) -> Option<[u8; SendPolicy::NUM_BYTES]> {
+        self.send_policy_changed
+            .swap(false, SeqCst)
+            .then(|| self.send_policy.lock().to_bytes())
+    }
+
+    /// Returns `false` if the message could not be decoded, and the connection should be closed.
+    fn on_message(&self, mut packet: &[u8]) -> bool {
+        match crate::client::read_message(&mut packet) {
+            Ok(message) => {
+                let frame_data = Arc::new(message.frame_data);
+                self.frame_view.lock().add_frame(frame_data.clone());
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some(recording) = self.recording.lock().as_mut() {
+                    recording.record(&frame_data);
+                }
+                *self.dropped_frames.lock() += message.dropped_frames;
+                true
+            }
+            Err(err) => {
+                log::warn!("Bad message from puffin server: {err:#}");
+                false
+            }
+        }
+    }
+
+    fn on_disconnected(&self, reason: &str) {
+        if self.connected.swap(false, SeqCst) {
+            log::warn!("Connection to puffin server closed: {reason}");
+        } else {
+            log::debug!("Failed to connect to {}: {reason}", self.url);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Connection {
+    fn start(self) {
+        let _ = std::thread::Builder::new()
+            .name("websocket_client_thread".to_owned())
+            .spawn(move || {
+                while self.alive.load(SeqCst) {
+                    match tungstenite::connect(&self.url) {
+                        Ok((mut websocket, _response)) => {
+                            self.on_connected();
+                            // Wake up regularly to check if we should send a new policy:
+                            if let tungstenite::stream::MaybeTlsStream::Plain(tcp_stream) =
+                                websocket.get_ref()
+                            {
+                                tcp_stream
+                                    .set_read_timeout(Some(std::time::Duration::from_millis(100)))
+                                    .ok();
+                            }
+                            while self.alive.load(SeqCst) {
+                                if let Some(policy) = self.changed_send_policy() {
+                                    if let Err(err) = websocket
+                                        .send(tungstenite::Message::binary(policy.to_vec()))
+                                    {
+                                        self.on_disconnected(&err.to_string());
+                                        break;
+                                    }
+                                }
+                                match websocket.read() {
+                                    Ok(tungstenite::Message::Binary(packet)) => {
+                                        if !self.on_message(&packet) {
+                                            websocket.close(None).ok();
+                                            self.on_disconnected("bad message");
+                                            break;
+                                        }
+                                    }
+                                    Ok(_) => {} // ping, pong, text, etc.
+                                    Err(tungstenite::Error::Io(err))
+                                        if matches!(
+                                            err.kind(),
+                                            std::io::ErrorKind::WouldBlock
+                                                | std::io::ErrorKind::TimedOut
+                                        ) => {}
+                                    Err(err) => {
+                                        self.on_disconnected(&err.to_string());
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                        Err(err) => {
+                            self.on_disconnected(&err.to_string());
+                            std::thread::sleep(std::time::Duration::from_secs(1));
+                        }
+                    }
+                }
+            });
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Connection {
+    fn start(self) {
+        use wasm_bindgen::{closure::Closure, JsCast as _};
+
+        if !self.alive.load(SeqCst) {
+            return;
+        }
+
+        let websocket = match web_sys::WebSocket::new(&self.url) {
+            Ok(websocket) => websocket,
+            Err(err) => {
+                log::error!("Failed to open WebSocket to {}: {err:?}", self.url);
+                return;
+            }
+        };
+        websocket.set_binary_type(web_sys::BinaryType::Arraybuffer);
+
+        let onopen = Closure::<dyn FnMut()>::new({
+            let connection = self.clone();
+            let websocket = websocket.clone();
+            move || {
+                connection.on_connected();
+                connection.send_changed_policy(&websocket);
+            }
+        });
+        websocket.set_onopen(Some(onopen.as_ref().unchecked_ref()));
+        onopen.forget();
+
+        let onmessage = Closure::<dyn FnMut(web_sys::MessageEvent)>::new({
+            let connection = self.clone();
+            let websocket = websocket.clone();
+            move |event: web_sys::MessageEvent| {
+                if let Ok(buffer) = event.data().dyn_into::<js_sys::ArrayBuffer>() {
+                    let packet = js_sys::Uint8Array::new(&buffer).to_vec();
+                    if !connection.on_message(&packet) || !connection.alive.load(SeqCst) {
+                        websocket.close().ok();
+                    } else {
+                        connection.send_changed_policy(&websocket);
+                    }
+                }
+            }
+        });
+        websocket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        onmessage.forget();
+
+        let onclose = Closure::<dyn FnMut(web_sys::CloseEvent)>::new({
+            let connection = self;
+            move |event: web_sys::CloseEvent| {
+                connection.on_disconnected(&format!("code {}", event.code()));
+                connection.clone().reconnect_later();
+            }
+        });
+        websocket.set_onclose(Some(onclose.as_ref().unchecked_ref()));
+        onclose.forget();
+    }
+
+    fn send_changed_policy(&self, websocket: &web_sys::WebSocket) {
+        if let Some(policy) = self.changed_send_policy() {
+            if let Err(err) = websocket.send_with_u8_array(&policy) {
+                log::warn!("Failed to send policy to puffin server: {err:?}");
+            }
+        }
+    }
+
+    fn reconnect_later(self) {
+        use wasm_bindgen::{closure::Closure, JsCast as _};
+
+        let Some(window) = web_sys::window() else {
+            return;
+        };
+        let reconnect = Closure::once_into_js(move || self.start());
+        window
+            .set_timeout_with_callback_and_timeout_and_arguments_0(reconnect.unchecked_ref(), 1000)
+            .ok();
+    }
+}
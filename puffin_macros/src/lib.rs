@@ -0,0 +1,139 @@
+//! The `#[puffin::profile]` attribute.
+//!
+//! Use it through the `macros` feature of [`puffin`](https://docs.rs/puffin),
+//! which re-exports it as `puffin::profile`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    ext::IdentExt as _,
+    parse::{Parse, ParseStream},
+    parse_macro_input, Expr, Ident, ItemFn, ReturnType, Token, Type,
+};
+
+/// Profile the whole function, like `puffin::profile_function!()` as its first line.
+///
+/// Optional arguments:
+/// * `name = "..."`: name the scope, like `puffin::profile_scope!`, instead of after the function.
+/// * `category = "..."`: the `ScopeDetails::category` of the scope.
+/// * `data = expr`: the data of the scope, e.g. `data = path.display().to_string()`.
+/// * `if = cond`: only profile the function when this is true.
+///
+/// The `data` and `if` expressions are evaluated at the start of the function.
+///
+/// An `async fn` is profiled each time it is polled, so the time it spends waiting
+/// is not part of the scope, and the scope never spans an `.await`.
+///
+/// ```ignore
+/// #[puffin::profile]
+/// fn update() {}
+///
+/// #[puffin::profile(name = "load", category = "io", data = path.to_owned(), if = !path.is_empty())]
+/// async fn load(path: &str) -> Vec<u8> {
+///     vec![]
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn profile(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as Args);
+    let item_fn = parse_macro_input!(item as ItemFn);
+    profile_fn(&args, item_fn).into()
+}
+
+#[derive(Default)]
+struct Args {
+    name: Option<Expr>,
+    category: Option<Expr>,
+    data: Option<Expr>,
+    condition: Option<Expr>,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let mut args = Self::default();
+        while !input.is_empty() {
+            // `if` is a keyword, so parse any identifier:
+            let key = input.call(Ident::parse_any)?;
+            input.parse::<Token![=]>()?;
+            let value = input.parse::<Expr>()?;
+            let arg = match key.to_string().as_str() {
+                "name" => &mut args.name,
+                "category" => &mut args.category,
+                "data" => &mut args.data,
+                "if" => &mut args.condition,
+                _ => {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "expected `name`, `category`, `data` or `if`",
+                    ));
+                }
+            };
+            if arg.replace(value).is_some() {
+                return Err(syn::Error::new(key.span(), format!("duplicate `{key}`")));
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(args)
+    }
+}
+
+fn profile_fn(args: &Args, mut item_fn: ItemFn) -> TokenStream2 {
+    let condition = args
+        .condition
+        .as_ref()
+        .map_or_else(|| quote!(true), |condition| quote!(#condition));
+    let data = args
+        .data
+        .as_ref()
+        .map_or_else(|| quote!(""), |data| quote!(#data));
+    let category = args.category.as_ref().map(|category| quote!(#category));
+    let name = args.name.as_ref();
+
+    let body = &item_fn.block;
+    let new_body = if item_fn.sig.asyncness.is_some() {
+        // Annotate the output, so that `?` in the body knows what to convert to:
+        let output = match &item_fn.sig.output {
+            ReturnType::Type(_, ty) if !matches!(**ty, Type::ImplTrait(_)) => quote!(#ty),
+            ReturnType::Type(..) => quote!(_),
+            ReturnType::Default => quote!(()),
+        };
+        let future = quote! {
+            async move {
+                let output: #output = #body;
+                output
+            }
+        };
+        match name {
+            Some(name) => quote! {{
+                ::puffin::__profile_future!(named [#category] #condition, #name, #data, #future)
+            }},
+            None => quote! {{
+                ::puffin::__profile_future!(function [#category] #condition, #data, #future)
+            }},
+        }
+    } else {
+        let scope = match (name, category) {
+            (Some(name), Some(category)) => quote! {
+                ::puffin::profile_scope_if!(#condition, #name, category = #category, #data);
+            },
+            (Some(name), None) => quote! {
+                ::puffin::profile_scope_if!(#condition, #name, #data);
+            },
+            (None, Some(category)) => quote! {
+                ::puffin::profile_function_if!(#condition, category = #category, #data);
+            },
+            (None, None) => quote! {
+                ::puffin::profile_function_if!(#condition, #data);
+            },
+        };
+        quote! {{
+            #scope
+            #body
+        }}
+    };
+    item_fn.block = syn::parse2(new_body).expect("a block");
+    quote!(#item_fn)
+}
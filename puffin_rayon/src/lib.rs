@@ -0,0 +1,174 @@
+//! Profile the jobs of [`rayon`] thread pools with [`puffin`].
+//!
+//! * [`thread_pool_builder`] names the worker threads after their pool, e.g. `"physics 0"`,
+//!   which is what the threads are called in the profiler.
+//! * [`ThreadPool::install`], [`ThreadPool::spawn`] and [`spawn`] give each job a scope on the worker
+//!   running it, tagged with the scope the job came from: the job scope has the same name as that scope,
+//!   and the name of the pool as its data.
+//! * [`ParallelIteratorExt::for_each_profiled`] does the same for each piece of work of a parallel iterator.
+//!
+//! Workers often run jobs while waiting for another job to finish, so the scopes of one job can be
+//! nested inside those of a much longer one. Once a job ends, its worker reports what it recorded so far
+//! if a new frame has started, so that each job shows up in the frame it ran in.
+//!
+//! ```
+//! use puffin_rayon::ParallelIteratorExt as _;
+//! use rayon::prelude::*;
+//!
+//! let pool = puffin_rayon::ThreadPool::new("physics", 2).unwrap();
+//!
+//! puffin::profile_scope!("update");
+//! pool.install(|| {
+//!     // Each piece of work gets an "update" scope with "bodies" as its data:
+//!     (0..1000).into_par_iter().for_each_profiled("bodies", |body| {
+//!         let _ = body;
+//!     });
+//! });
+//! ```
+
+use puffin::{ProfilerScope, ScopeId, ThreadProfiler};
+
+/// A [`rayon::ThreadPoolBuilder`] naming the worker threads `"{name} {index}"`.
+///
+/// Build the global pool with it to name the workers of [`rayon::spawn`] and parallel iterators:
+///
+/// ```
+/// puffin_rayon::thread_pool_builder("rayon").build_global().ok();
+/// ```
+pub fn thread_pool_builder(name: impl Into<String>) -> rayon::ThreadPoolBuilder {
+    let name = name.into();
+    rayon::ThreadPoolBuilder::new().thread_name(move |index| format!("{name} {index}"))
+}
+
+/// A [`rayon::ThreadPool`] with named workers, which profiles each job.
+pub struct ThreadPool {
+    name: String,
+    pool: rayon::ThreadPool,
+}
+
+impl ThreadPool {
+    /// A pool with `num_threads` workers named after the pool, see [`thread_pool_builder`].
+    ///
+    /// With `0` threads rayon picks the number, like for [`rayon::ThreadPoolBuilder::num_threads`].
+    ///
+    /// # Errors
+    /// If the threads can't be spawned.
+    pub fn new(
+        name: impl Into<String>,
+        num_threads: usize,
+    ) -> Result<Self, rayon::ThreadPoolBuildError> {
+        let name = name.into();
+        let pool = thread_pool_builder(name.clone())
+            .num_threads(num_threads)
+            .build()?;
+        Ok(Self { name, pool })
+    }
+
+    /// The name of the pool, which is the data of the job scopes.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The wrapped rayon pool, for running work without job scopes.
+    pub fn rayon_pool(&self) -> &rayon::ThreadPool {
+        &self.pool
+    }
+
+    /// Run `op` in the pool and wait for the result, like [`rayon::ThreadPool::install`].
+    ///
+    /// Parallel iterators and [`rayon::join`] inside `op` run in this pool too.
+    pub fn install<OP, R>(&self, op: OP) -> R
+    where
+        OP: FnOnce() -> R + Send,
+        R: Send,
+    {
+        if self.pool.current_thread_index().is_some() {
+            // Already in the pool, so `op` runs right here within the scope it came from:
+            return op();
+        }
+        let parent = Parent::current();
+        self.pool.install(|| {
+            let _job = parent.begin_job(&self.name);
+            op()
+        })
+    }
+
+    /// Run `op` in the pool without waiting for it, like [`rayon::ThreadPool::spawn`].
+    pub fn spawn<OP>(&self, op: OP)
+    where
+        OP: FnOnce() + Send + 'static,
+    {
+        let parent = Parent::current();
+        let name = self.name.clone();
+        self.pool.spawn(move || {
+            let _job = parent.begin_job(&name);
+            op();
+        });
+    }
+}
+
+/// Run `op` in the global pool without waiting for it, like [`rayon::spawn`].
+///
+/// The job scope has `"rayon"` as its data.
+pub fn spawn<OP>(op: OP)
+where
+    OP: FnOnce() + Send + 'static,
+{
+    let parent = Parent::current();
+    rayon::spawn(move || {
+        let _job = parent.begin_job("rayon");
+        op();
+    });
+}
+
+/// Profiled versions of the methods of [`rayon::iter::ParallelIterator`].
+pub trait ParallelIteratorExt: rayon::iter::ParallelIterator {
+    /// Like [`rayon::iter::ParallelIterator::for_each`], with a job scope around each piece of work,
+    /// tagged with the scope this is called from, and `data` as its data.
+    fn for_each_profiled<OP>(self, data: &str, op: OP)
+    where
+        OP: Fn(Self::Item) + Sync + Send,
+    {
+        let parent = Parent::current();
+        self.for_each_init(|| parent.begin_job(data), |_job, item| op(item));
+    }
+}
+
+impl<I: rayon::iter::ParallelIterator> ParallelIteratorExt for I {}
+
+/// The innermost scope of the thread a job came from.
+#[derive(Clone, Copy)]
+struct Parent(Option<ScopeId>);
+
+impl Parent {
+    fn current() -> Self {
+        let scope_id = if puffin::are_scopes_on() {
+            ThreadProfiler::call(|tp| tp.current_scope_id())
+        } else {
+            None
+        };
+        Self(scope_id)
+    }
+
+    /// Called on the worker running the job.
+    fn begin_job(self, data: &str) -> JobScope {
+        let scope = match self.0 {
+            Some(scope_id) if puffin::are_scopes_on() => Some(ProfilerScope::new(scope_id, data)),
+            // Not spawned from within a scope:
+            _ => puffin::profile_scope_custom!("rayon job", data),
+        };
+        JobScope(scope)
+    }
+}
+
+/// Ends the scope of a job, and reports it if it is within another job that began in an earlier frame.
+struct JobScope(Option<ProfilerScope>);
+
+impl Drop for JobScope {
+    fn drop(&mut self) {
+        if let Some(scope) = self.0.take() {
+            drop(scope);
+            ThreadProfiler::call(|tp| tp.flush_on_new_frame());
+        }
+    }
+}
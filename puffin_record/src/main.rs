@@ -0,0 +1,265 @@
+//! Headless recorder, connecting to a [`puffin_http::Server`] and writing a `.puffin` file.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write as _},
+    net::TcpStream,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+use puffin::{FrameWriter, ScopeCollection};
+use puffin_http::{DroppedFrames, SendPolicy};
+
+/// puffin headless recorder.
+///
+/// Connects to a puffin server and records its profile data to a .puffin file,
+/// until the duration or frame count is reached, the server disconnects, or Ctrl-C is pressed.
+#[derive(argh::FromArgs)]
+struct Arguments {
+    /// which server to connect to, e.g. `127.0.0.1:8585`.
+    #[argh(option, default = "default_url()")]
+    url: String,
+
+    /// what .puffin file to write, e.g. `my/recording.puffin`.
+    #[argh(option, short = 'o', default = "PathBuf::from(\"recording.puffin\")")]
+    output: PathBuf,
+
+    /// stop recording after this many seconds (counted from when we connect).
+    #[argh(option)]
+    duration: Option<f64>,
+
+    /// stop recording after this many frames.
+    #[argh(option)]
+    frames: Option<usize>,
+
+    /// only record frames that took longer than this many milliseconds.
+    #[argh(option)]
+    only_slower_than: Option<f64>,
+}
+
+fn default_url() -> String {
+    format!("127.0.0.1:{}", puffin_http::DEFAULT_PORT)
+}
+
+fn main() {
+    // Log to stderr (use `RUST_LOG=debug` for more).
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let args: Arguments = argh::from_env();
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    if let Err(err) = ctrlc::set_handler({
+        let interrupted = interrupted.clone();
+        move || interrupted.store(true, SeqCst)
+    }) {
+        log::error!("Failed to set Ctrl-C handler: {err}");
+        std::process::exit(1);
+    }
+
+    if let Err(err) = record(&args, &interrupted) {
+        log::error!("{err:#}");
+        std::process::exit(1);
+    }
+}
+
+/// Record until done, or until `interrupted` is set.
+fn record(args: &Arguments, interrupted: &AtomicBool) -> anyhow::Result<()> {
+    // Create the file up front, so we always leave a valid (if empty) recording behind:
+    let file = File::create(&args.output)
+        .with_context(|| format!("Failed to create {}", args.output.display()))?;
+    let mut writer = FrameWriter::new(BufWriter::new(file))?;
+
+    let Some(mut stream) = connect(&args.url, interrupted) else {
+        log::info!("Interrupted before connecting");
+        return finish(args, writer, DroppedFrames::default());
+    };
+
+    // Let the server do the filtering, so it can also send us the scopes of the frames we skip.
+    let min_frame_duration_ns = args
+        .only_slower_than
+        .map_or(0, |ms| (ms * 1e6) as puffin::NanoSecond);
+    let send_policy = SendPolicy {
+        min_frame_duration_ns,
+        ..Default::default()
+    };
+    send_policy
+        .write_to(&mut stream)
+        .context("Failed to send policy to server")?;
+
+    // Read in a background thread, so that we can stop while waiting for frames:
+    let (message_tx, message_rx) = crossbeam_channel::unbounded();
+    std::thread::Builder::new()
+        .name("puffin_record_reader".to_owned())
+        .spawn(move || loop {
+            let message = puffin_http::read_message(&mut stream);
+            let disconnected = message.is_err();
+            if message_tx.send(message).is_err() || disconnected {
+                break;
+            }
+        })
+        .context("Couldn't spawn thread")?;
+
+    let deadline = args
+        .duration
+        .map(|seconds| Instant::now() + Duration::from_secs_f64(seconds));
+    let mut scope_collection = ScopeCollection::default();
+    let mut dropped_frames = DroppedFrames::default();
+
+    loop {
+        if interrupted.load(SeqCst) {
+            log::info!("Interrupted");
+            break;
+        }
+        if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+            break;
+        }
+        if args
+            .frames
+            .is_some_and(|frames| frames <= writer.num_frames())
+        {
+            break;
+        }
+
+        match message_rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(Ok(message)) => {
+                for scope in &message.frame_data.scope_delta {
+                    scope_collection.insert(scope.clone());
+                }
+                dropped_frames += message.dropped_frames;
+                // Servers older than protocol version 3 ignore our policy:
+                if message.frame_data.duration_ns() < min_frame_duration_ns {
+                    continue;
+                }
+                writer
+                    .write_frame(&message.frame_data, &scope_collection)
+                    .with_context(|| format!("Failed to write {}", args.output.display()))?;
+            }
+            Ok(Err(err)) => {
+                log::info!("Connection to puffin server closed: {err:#}");
+                break;
+            }
+            Err(crossbeam_channel::RecvTimeoutError::Timeout) => {}
+            Err(crossbeam_channel::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    finish(args, writer, dropped_frames)
+}
+
+/// Connect to the server, retrying until it is up.
+///
+/// Returns `None` if interrupted before connecting.
+fn connect(url: &str, interrupted: &AtomicBool) -> Option<TcpStream> {
+    log::info!("Connecting to {url}…");
+    while !interrupted.load(SeqCst) {
+        match TcpStream::connect(url) {
+            Ok(stream) => {
+                log::info!("Connected to {url}");
+                return Some(stream);
+            }
+            Err(err) => {
+                log::debug!("Failed to connect to {url}: {err}");
+                std::thread::sleep(Duration::from_millis(250));
+            }
+        }
+    }
+    None
+}
+
+fn finish(
+    args: &Arguments,
+    writer: FrameWriter<BufWriter<File>>,
+    dropped_frames: DroppedFrames,
+) -> anyhow::Result<()> {
+    let num_frames = writer.num_frames();
+    writer
+        .into_inner()
+        .flush()
+        .with_context(|| format!("Failed to write {}", args.output.display()))?;
+
+    let num_bytes = std::fs::metadata(&args.output).map_or(0, |metadata| metadata.len());
+    log::info!(
+        "Recorded {num_frames} frames ({:.1} MB) to {}",
+        num_bytes as f64 * 1e-6,
+        args.output.display()
+    );
+    if dropped_frames.dropped > 0 {
+        log::warn!(
+            "The server dropped {} frames because we didn't read them fast enough",
+            dropped_frames.dropped
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Report one scope per frame to the profiler, until `stop` is set.
+    fn profile_frames(profiler: &puffin::Profiler, stop: &AtomicBool) {
+        use puffin::testing::{FrameBuilder, ScopeTree};
+        let mut frame_index = 0;
+        while !stop.load(SeqCst) {
+            let start_ns = frame_index * 1_000;
+            let frame = FrameBuilder::new(0)
+                .thread("main", [ScopeTree::new("work", start_ns, 500)])
+                .build();
+            let scope_details: Vec<_> = frame
+                .scope_delta
+                .iter()
+                .map(|details| (**details).clone())
+                .collect();
+            for (thread_info, stream_info) in &frame.unpacked().unwrap().thread_streams {
+                profiler.report(
+                    thread_info.clone(),
+                    &scope_details,
+                    &stream_info.as_stream_into_ref(),
+                );
+            }
+            profiler.new_frame();
+            frame_index += 1;
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn record_frames_from_server() {
+        let profiler = puffin::Profiler::new();
+        let server = puffin_http::Server::new_for_profiler("127.0.0.1:0", &profiler).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let app = std::thread::Builder::new()
+            .spawn({
+                let stop = stop.clone();
+                move || profile_frames(&profiler, &stop)
+            })
+            .unwrap();
+
+        let output =
+            std::env::temp_dir().join(format!("puffin_record_{}.puffin", std::process::id()));
+        let args = Arguments {
+            url: server.local_addr().to_string(),
+            output: output.clone(),
+            duration: Some(30.0), // Only if something is broken
+            frames: Some(3),
+            only_slower_than: None,
+        };
+        record(&args, &AtomicBool::new(false)).unwrap();
+        stop.store(true, SeqCst);
+        app.join().unwrap();
+
+        let frame_view = puffin::FrameView::read(&mut File::open(&output).unwrap()).unwrap();
+        std::fs::remove_file(&output).unwrap();
+        assert_eq!(frame_view.all_uniq().count(), 3);
+        assert!(frame_view
+            .scope_collection()
+            .fetch_by_name("work")
+            .is_some());
+    }
+}
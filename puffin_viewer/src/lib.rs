@@ -12,16 +12,30 @@ use puffin_egui::MaybeMutRef;
 pub enum Source {
     None,
     Http(puffin_http::Client),
+    WebSocket(puffin_http::WebSocketClient),
     FilePath(std::path::PathBuf, FrameView),
     FileName(String, FrameView),
 }
 
 impl Source {
+    /// Connect to a live puffin server.
+    ///
+    /// Urls starting with `ws://` or `wss://` are connected to using WebSocket
+    /// (see `puffin_http::Server::new_websocket`), everything else using plain TCP.
+    pub fn connect(url: String) -> Self {
+        if url.starts_with("ws://") || url.starts_with("wss://") {
+            Self::WebSocket(puffin_http::WebSocketClient::new(url))
+        } else {
+            Self::Http(puffin_http::Client::new(url))
+        }
+    }
+
     #[cfg(not(target_arch = "wasm32"))]
     fn frame_view(&self) -> FrameView {
         match self {
             Self::None => Default::default(),
             Self::Http(http_client) => http_client.frame_view().clone(),
+            Self::WebSocket(ws_client) => ws_client.frame_view().clone(),
             Self::FilePath(_, frame_view) | Self::FileName(_, frame_view) => frame_view.clone(),
         }
     }
@@ -33,11 +47,26 @@ impl Source {
             }
             Self::Http(http_client) => {
                 if http_client.connected() {
-                    ui.label(format!("Connected to {}", http_client.addr()));
+                    ui.label(format!(
+                        "Connected to {}{}",
+                        http_client.addr(),
+                        dropped_frames_text(http_client.dropped_frames())
+                    ));
                 } else {
                     ui.label(format!("Connecting to {}…", http_client.addr()));
                 }
             }
+            Self::WebSocket(ws_client) => {
+                if ws_client.connected() {
+                    ui.label(format!(
+                        "Connected to {}{}",
+                        ws_client.url(),
+                        dropped_frames_text(ws_client.dropped_frames())
+                    ));
+                } else {
+                    ui.label(format!("Connecting to {}…", ws_client.url()));
+                }
+            }
             Self::FilePath(path, _) => {
                 ui.label(format!("Viewing {}", path.display()));
             }
@@ -46,13 +75,124 @@ impl Source {
             }
         }
     }
+
+    fn send_policy(&self) -> Option<puffin_http::SendPolicy> {
+        match self {
+            Self::Http(http_client) => Some(http_client.send_policy()),
+            Self::WebSocket(ws_client) => Some(ws_client.send_policy()),
+            Self::None | Self::FilePath(..) | Self::FileName(..) => None,
+        }
+    }
+
+    fn set_send_policy(&self, policy: puffin_http::SendPolicy) {
+        match self {
+            Self::Http(http_client) => http_client.set_send_policy(policy),
+            Self::WebSocket(ws_client) => ws_client.set_send_policy(policy),
+            Self::None | Self::FilePath(..) | Self::FileName(..) => {}
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn recording(&self) -> Option<puffin_http::RecordingInfo> {
+        match self {
+            Self::Http(http_client) => http_client.recording(),
+            Self::WebSocket(ws_client) => ws_client.recording(),
+            Self::None | Self::FilePath(..) | Self::FileName(..) => None,
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn start_recording(&self, path: &std::path::Path) -> Result<(), String> {
+        let result = match self {
+            Self::Http(http_client) => http_client.start_recording(path),
+            Self::WebSocket(ws_client) => ws_client.start_recording(path),
+            Self::None | Self::FilePath(..) | Self::FileName(..) => return Ok(()),
+        };
+        result.map_err(|err| format!("Failed to start recording: {err:#}"))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn stop_recording(&self) -> Result<(), String> {
+        let result = match self {
+            Self::Http(http_client) => http_client.stop_recording(),
+            Self::WebSocket(ws_client) => ws_client.stop_recording(),
+            Self::None | Self::FilePath(..) | Self::FileName(..) => return Ok(()),
+        };
+        result
+            .map(|_info| ())
+            .map_err(|err| format!("Failed to finish recording: {err:#}"))
+    }
+
+    /// Lets the user pick which frames the server should send, when connected to one.
+    fn send_policy_ui(&self, ui: &mut egui::Ui) {
+        let Some(mut policy) = self.send_policy() else {
+            return;
+        };
+
+        ui.menu_button("Bandwidth", |ui| {
+            let mut min_frame_duration_ms = policy.min_frame_duration_ns as f64 * 1e-6;
+            ui.horizontal(|ui| {
+                ui.label("Only frames slower than");
+                ui.add(
+                    egui::DragValue::new(&mut min_frame_duration_ms)
+                        .range(0.0..=1000.0)
+                        .speed(0.1)
+                        .suffix(" ms"),
+                );
+            });
+            policy.min_frame_duration_ns = (min_frame_duration_ms * 1e6) as _;
+
+            ui.horizontal(|ui| {
+                ui.label("Only every");
+                ui.add(egui::DragValue::new(&mut policy.every_nth_frame).range(1..=1000));
+                ui.label("frame");
+            });
+
+            ui.label("When falling behind:");
+            ui.radio_value(
+                &mut policy.lag_policy,
+                puffin_http::LagPolicy::Drop,
+                "Drop frames",
+            );
+            ui.radio_value(
+                &mut policy.lag_policy,
+                puffin_http::LagPolicy::Merge,
+                "Merge frames",
+            );
+
+            if ui.button("Reset").clicked() {
+                policy = Default::default();
+            }
+        });
+
+        if Some(policy) != self.send_policy() {
+            self.set_send_policy(policy);
+        }
+    }
+}
+
+fn dropped_frames_text(dropped_frames: puffin_http::DroppedFrames) -> String {
+    if dropped_frames.total() == 0 && dropped_frames.merged == 0 {
+        String::new()
+    } else {
+        format!(
+            " ({} frames skipped, {} dropped, {} merged)",
+            dropped_frames.skipped, dropped_frames.dropped, dropped_frames.merged
+        )
+    }
 }
 
 pub struct PuffinViewer {
     profiler_ui: puffin_egui::ProfilerUi,
     source: Source,
+    /// What WebSocket url to connect to from the web viewer.
+    #[cfg(target_arch = "wasm32")]
+    websocket_url: String,
     error: Option<String>,
     profile_self: bool,
+    /// Started the first time the user opens the "Connect to…" menu.
+    #[cfg(not(target_arch = "wasm32"))]
+    discovery: Option<Result<puffin_http::Discovery, String>>,
     /// if [`Self::profile_self`] is checked, use this to introspect.
     global_profiler_ui: puffin_egui::GlobalProfilerUi,
 }
@@ -66,8 +206,12 @@ impl PuffinViewer {
         Self {
             profiler_ui,
             source,
+            #[cfg(target_arch = "wasm32")]
+            websocket_url: format!("ws://127.0.0.1:{}", puffin_http::DEFAULT_PORT),
             error: None,
             profile_self: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            discovery: None,
             global_profiler_ui: Default::default(),
         }
     }
@@ -165,6 +309,8 @@ impl PuffinViewer {
                         self.save_dialog();
                     }
 
+                    ui.menu_button("Connect to…", |ui| self.connect_menu_ui(ui));
+
                     if ui.button("Quit").clicked() {
                         ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
                     }
@@ -173,10 +319,114 @@ impl PuffinViewer {
                     ui.checkbox(&mut self.profile_self, "Profile self")
                         .on_hover_text("Show the flamegraph for puffin_viewer");
                 });
+                self.source.send_policy_ui(ui);
+                self.record_ui(ui);
             });
         });
     }
 
+    /// Toggle streaming all received frames to a file, when connected to a server.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn record_ui(&mut self, ui: &mut egui::Ui) {
+        if self.source.send_policy().is_none() {
+            return; // Not connected to a server
+        }
+
+        if let Some(recording) = self.source.recording() {
+            if ui
+                .selectable_label(true, "⏹ Stop recording")
+                .on_hover_text(if recording.num_files == 1 {
+                    format!("Recording to {}", recording.path.display())
+                } else {
+                    format!(
+                        "Recording to {} (file {} since reconnecting)",
+                        recording.path.display(),
+                        recording.num_files
+                    )
+                })
+                .clicked()
+            {
+                self.error = self.source.stop_recording().err();
+                return;
+            }
+
+            let seconds = recording.elapsed.as_secs();
+            ui.label(format!(
+                "{:02}:{:02} · {} frames · {:.1} MB",
+                seconds / 60,
+                seconds % 60,
+                recording.num_frames,
+                recording.num_bytes as f64 * 1e-6
+            ));
+            if let Some(error) = recording.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_millis(500));
+        } else if ui
+            .selectable_label(false, "⏺ Record")
+            .on_hover_text("Write every received frame to a .puffin file, so nothing is lost")
+            .clicked()
+        {
+            if let Some(path) = rfd::FileDialog::new()
+                .add_filter("puffin", &["puffin"])
+                .save_file()
+            {
+                self.error = self.source.start_recording(&path).err();
+            }
+        }
+    }
+
+    /// List the servers announcing themselves with `puffin_http::Server::announce`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn connect_menu_ui(&mut self, ui: &mut egui::Ui) {
+        let discovery = self
+            .discovery
+            .get_or_insert_with(|| puffin_http::Discovery::new().map_err(|err| format!("{err:#}")));
+
+        let servers = match discovery {
+            Ok(discovery) => discovery.servers(),
+            Err(err) => {
+                ui.colored_label(
+                    egui::Color32::RED,
+                    format!("Failed to listen for servers: {err}"),
+                );
+                return;
+            }
+        };
+
+        if servers.is_empty() {
+            ui.label("Looking for puffin servers…")
+                .on_hover_text("Only servers that call puffin_http::Server::announce are listed");
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_millis(500));
+        }
+
+        for server in servers {
+            let label = format!(
+                "{} (pid {}) at {}",
+                server.app_name,
+                server.pid,
+                server.url()
+            );
+            let response = ui.add_enabled(
+                server.protocol_version == puffin_http::PROTOCOL_VERSION,
+                egui::Button::new(label),
+            );
+            let response = response.on_disabled_hover_text(format!(
+                "This server uses protocol version {}, but this viewer uses {}",
+                server.protocol_version,
+                puffin_http::PROTOCOL_VERSION
+            ));
+            if response.clicked() {
+                self.profiler_ui.reset();
+                self.source = Source::connect(server.url());
+                self.error = None;
+                ui.close_menu();
+            }
+        }
+    }
+
     fn ui_file_drag_and_drop(&mut self, ctx: &egui::Context) {
         use egui::*;
 
@@ -235,6 +485,21 @@ impl eframe::App for PuffinViewer {
                     ui.code("cargo install puffin_viewer --locked");
                 });
                 ui.hyperlink("https://github.com/EmbarkStudios/puffin");
+                ui.horizontal(|ui| {
+                    ui.label(
+                        "Connect to a server started with puffin_http::Server::new_websocket:",
+                    );
+                    let response = ui.text_edit_singleline(&mut self.websocket_url);
+                    let enter_pressed =
+                        response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if ui.button("Connect").clicked() || enter_pressed {
+                        self.profiler_ui.reset();
+                        self.source = Source::WebSocket(puffin_http::WebSocketClient::new(
+                            self.websocket_url.clone(),
+                        ));
+                    }
+                    self.source.send_policy_ui(ui);
+                });
             });
         }
 
@@ -263,6 +528,10 @@ impl eframe::App for PuffinViewer {
                         self.profiler_ui
                             .ui(ui, &mut MaybeMutRef::MutRef(&mut http_client.frame_view()));
                     }
+                    Source::WebSocket(ws_client) => {
+                        self.profiler_ui
+                            .ui(ui, &mut MaybeMutRef::MutRef(&mut ws_client.frame_view()));
+                    }
                     Source::FilePath(_, frame_view) | Source::FileName(_, frame_view) => {
                         self.profiler_ui.ui(ui, &mut MaybeMutRef::Ref(frame_view));
                     }
@@ -13,7 +13,7 @@ fn main() -> Result<(), eframe::Error> {
     /// or open a .puffin recording file.
     #[derive(argh::FromArgs)]
     struct Arguments {
-        /// which server to connect to, e.g. `127.0.0.1:8585`.
+        /// which server to connect to, e.g. `127.0.0.1:8585`, or `ws://127.0.0.1:8585` for WebSocket.
         #[argh(option, default = "default_url()")]
         url: String,
 
@@ -52,7 +52,7 @@ fn main() -> Result<(), eframe::Error> {
             }
         }
     } else {
-        Source::Http(puffin_http::Client::new(opt.url))
+        Source::connect(opt.url)
     };
 
     let icon = eframe::icon_data::from_png_bytes(include_bytes!("../icon.png")).unwrap();
@@ -12,11 +12,17 @@ pub async fn start(canvas_id: &str) -> Result<(), eframe::wasm_bindgen::JsValue>
     // Redirect [`log`] message to `console.log` and friends:
     eframe::WebLogger::init(log::LevelFilter::Debug).ok();
 
+    let canvas = eframe::web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.get_element_by_id(canvas_id))
+        .ok_or_else(|| JsValue::from_str(&format!("Failed to find canvas {canvas_id:?}")))?
+        .dyn_into::<eframe::web_sys::HtmlCanvasElement>()?;
+
     let web_options = eframe::WebOptions::default();
     let runner = eframe::WebRunner::new();
     runner
         .start(
-            canvas_id,
+            canvas,
             web_options,
             Box::new(|cc| {
                 Ok(Box::new(crate::PuffinViewer::new(